@@ -0,0 +1,62 @@
+-- Portable between PostgreSQL and SQLite: timestamps are RFC 3339 text, booleans
+-- are 0/1 integers and list fields (recovery codes, projects, events, topics) are JSON text.
+
+CREATE TABLE users (
+    id TEXT PRIMARY KEY,
+    username TEXT NOT NULL UNIQUE,
+    password TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    jira_email TEXT,
+    jira_api_key TEXT,
+    jira_url TEXT,
+    webhook_url TEXT,
+    webhook_functional BIGINT,
+    webhook_last_check TEXT,
+    totp_secret TEXT,
+    totp_pending TEXT,
+    recovery_codes TEXT
+);
+
+-- `workspace` is the owner id used as the object prefix by the S3 and local backends.
+CREATE TABLE connectors (
+    workspace TEXT NOT NULL,
+    name TEXT NOT NULL,
+    description TEXT NOT NULL,
+    token TEXT NOT NULL,
+    bot_id TEXT,
+    chatid TEXT NOT NULL,
+    thread_id BIGINT,
+    topics TEXT NOT NULL,
+    active BIGINT NOT NULL,
+    schedule BIGINT NOT NULL,
+    duration TEXT NOT NULL,
+    project TEXT NOT NULL,
+    event TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    updated_at TEXT,
+    PRIMARY KEY (workspace, name)
+);
+
+-- One row per (project, event) a connector listens to, `site` is the project's
+-- Jira site with the default site resolved to the workspace id.
+CREATE TABLE connector_routes (
+    workspace TEXT NOT NULL,
+    connector TEXT NOT NULL,
+    project_id TEXT NOT NULL,
+    event TEXT NOT NULL,
+    site TEXT NOT NULL,
+    PRIMARY KEY (workspace, connector, project_id, event, site)
+);
+CREATE INDEX connector_routes_lookup ON connector_routes (workspace, project_id, event);
+
+-- Ids are ObjectIds, which sort in insertion order.
+CREATE TABLE delivery_logs (
+    id TEXT PRIMARY KEY,
+    workspace TEXT NOT NULL,
+    connector TEXT NOT NULL,
+    event TEXT NOT NULL,
+    status TEXT NOT NULL,
+    attempt BIGINT NOT NULL,
+    time TEXT NOT NULL
+);
+CREATE INDEX delivery_logs_connector ON delivery_logs (workspace, connector, id);
@@ -0,0 +1,5 @@
+-- Connectors are addressed by an immutable id, their routes and delivery logs refer to it
+-- instead of the name. Rows created before this get their id at startup, derived from
+-- workspace and name the same way the object stores derive it.
+ALTER TABLE connectors ADD COLUMN id TEXT;
+CREATE UNIQUE INDEX connectors_id ON connectors (id);
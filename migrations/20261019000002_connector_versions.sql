@@ -0,0 +1,2 @@
+-- Bumped by every write, updates only apply to the version they were based on.
+ALTER TABLE connectors ADD COLUMN version BIGINT NOT NULL DEFAULT 0;
@@ -0,0 +1,12 @@
+-- A snapshot of each connector after every change, tokens left out. Kept after the
+-- connector is deleted so it can be restored, removed with the workspace.
+CREATE TABLE connector_history (
+    workspace TEXT NOT NULL,
+    connector TEXT NOT NULL,
+    version BIGINT NOT NULL,
+    change TEXT NOT NULL,
+    author TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    snapshot TEXT NOT NULL,
+    PRIMARY KEY (workspace, connector, version)
+);
@@ -0,0 +1,2 @@
+-- Last TOTP time step a code was accepted for, so an observed code can't be replayed.
+ALTER TABLE users ADD COLUMN totp_step BIGINT;
@@ -0,0 +1,143 @@
+use std::time::{Duration, Instant};
+
+use actix_web::{web, App, HttpResponse, HttpServer};
+use futures::stream::{self, StreamExt};
+use teloxide::Bot;
+
+use crate::{client, crypto};
+use crate::actions::{connector, event, log};
+use crate::models::{connector::{Connector, Project}, event::Event};
+
+const TOKEN: &str = "123456:bench";
+const EVENT: &str = "jira:issue_created";
+
+/// Serves every Bot API call as a successful `sendMessage` after `latency`, returns its url.
+async fn mock_telegram(latency: Duration) -> Result<(reqwest::Url, actix_web::dev::ServerHandle), String> {
+    let server = HttpServer::new(move || App::new().default_service(web::to(move || async move {
+            tokio::time::sleep(latency).await;
+            HttpResponse::Ok().json(serde_json::json!({
+                "ok": true,
+                "result": { "message_id": 1, "date": 0, "chat": { "id": 1, "type": "private", "first_name": "bench" }, "text": "bench" }
+            }))
+        })))
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .map_err(|e| e.to_string())?;
+    let url = format!("http://{}", server.addrs()[0]).parse::<reqwest::Url>().map_err(|e| e.to_string())?;
+    let server = server.run();
+    let handle = server.handle();
+    actix_rt::spawn(server);
+    Ok((url, handle))
+}
+
+fn bench_connector(index: usize) -> Connector {
+    Connector {
+        id: crate::models::connector::new_id(),
+        name: format!("bench-{}", index),
+        description: "Delivery benchmark".to_string(),
+        token: TOKEN.to_string(),
+        bot_id: None,
+        chatid: "1".to_string(),
+        thread_id: None,
+        topics: Default::default(),
+        active: true,
+        schedule: false,
+        duration: String::new(),
+        project: vec![Project { id: "bench".to_string(), name: "Bench".to_string(), site: None }],
+        event: vec![EVENT.to_string()],
+        created_at: chrono::Utc::now()
+            .with_timezone(&chrono::FixedOffset::east_opt(7 * 3600).unwrap()),
+        updated_at: None,
+        version: 1
+    }
+}
+
+/// Loading `{id}/*.yml` and delivering to each connector one at a time, the way it was done
+/// before loads and deliveries ran concurrently.
+async fn sequential(db: &client::storage::Client, workspace: &str, url: &reqwest::Url, jira: &Event, text: &str, time: &str) -> Result<(Duration, Duration), String> {
+    let start = Instant::now();
+    let mut cons = vec![];
+    match db {
+        client::storage::Client::Sql(_) => cons = connector::get_connectors(db, workspace.to_string()).await.map_err(|e| e.to_string())?,
+        _ => for key in db.list(&format!("{}/", workspace)).await?.into_iter().filter(|key| key.ends_with(".yml")) {
+            let mut con: Connector = serde_yaml::from_slice(&db.get(&key).await?).map_err(|e| e.to_string())?;
+            con.token = crypto::decrypt(&con.token).map_err(|e| e.to_string())?;
+            cons.push(con);
+        }
+    }
+    let load = start.elapsed();
+
+    let start = Instant::now();
+    for con in cons {
+        let bot = Bot::new(&con.token).set_api_url(url.clone());
+        event::deliver(db, bot, con, jira, text, time, workspace).await;
+    }
+    Ok((load, start.elapsed()))
+}
+
+async fn concurrent(db: &client::storage::Client, workspace: &str, url: &reqwest::Url, jira: &Event, text: &str, time: &str) -> Result<(Duration, Duration), String> {
+    let start = Instant::now();
+    let cons = connector::get_connectors(db, workspace.to_string()).await.map_err(|e| e.to_string())?;
+    let load = start.elapsed();
+
+    let start = Instant::now();
+    stream::iter(cons).for_each_concurrent(event::SEND_CONCURRENCY, |con| async move {
+        let bot = client::telegram::bot(&con.token).set_api_url(url.clone());
+        event::deliver(db, bot, con, jira, text, time, workspace).await;
+    }).await;
+    Ok((load, start.elapsed()))
+}
+
+async fn run(db: &client::storage::Client, workspace: &str, count: usize, latency: Duration) -> Result<String, String> {
+    db.create_folder(workspace).await?;
+    for index in 0..count {
+        let mut con = bench_connector(index);
+        con.token = crypto::encrypt(&con.token).map_err(|e| e.to_string())?;
+        match db {
+            client::storage::Client::Sql(sql) => sql.insert_connector(workspace, &con).await?,
+            _ => {
+                db.put(&connector::object(workspace, &con.id, "yml"), serde_yaml::to_string(&con).unwrap().into_bytes()).await?;
+                log::add_log(db, con.id, None, workspace.to_string()).await.map_err(|e| e.to_string())?;
+            }
+        }
+    }
+
+    let (url, server) = mock_telegram(latency).await?;
+    let jira = Event {
+        timestamp: 0,
+        webhook_event: EVENT.to_string(),
+        project_id: "bench".to_string(),
+        project_name: "Bench".to_string(),
+        issue_key: "BENCH-1".to_string(),
+        summary: "Delivery benchmark".to_string(),
+        issue_type: "Task".to_string(),
+        assignee: "-".to_string(),
+        user: "Bench".to_string(),
+        changes: String::new(),
+        comment: String::new()
+    };
+    let (text, time) = ("Bench created new issue in project Bench", "19/10/2026 00:00");
+    let before = sequential(db, workspace, &url, &jira, text, time).await;
+    let after = concurrent(db, workspace, &url, &jira, text, time).await;
+    server.stop(true).await;
+    let ((load_before, send_before), (load_after, send_after)) = (before?, after?);
+
+    Ok(format!("{} connectors, {} ms Bot API latency\nload:     sequential {} ms, concurrent {} ms\ndelivery: sequential {} ms, concurrent {} ms",
+        count,
+        latency.as_millis(),
+        load_before.as_millis(),
+        load_after.as_millis(),
+        send_before.as_millis(),
+        send_after.as_millis()
+    ))
+}
+
+/// Times loading and notifying `count` connectors the old sequential way and the current
+/// concurrent way, against the configured storage and a local stand-in for the Bot API
+/// answering after `latency`. The connectors live in a throwaway workspace removed afterwards.
+pub async fn bench_delivery(db: &client::storage::Client, count: usize, latency: Duration) -> Result<String, String> {
+    let workspace = format!("bench-{}", mongodb::bson::oid::ObjectId::new().to_hex());
+    let result = run(db, &workspace, count, latency).await;
+    db.delete_folder(&workspace).await?;
+    result
+}
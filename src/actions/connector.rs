@@ -1,23 +1,47 @@
 
-use rusoto_s3::{S3, S3Client, GetObjectRequest, ListObjectsV2Request, PutObjectRequest, HeadObjectRequest, DeleteObjectRequest};
 use teloxide::prelude::*;
-use std::io::Read;
+use teloxide::types::{ChatAction, ChatKind, PublicChatKind};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use futures::stream::{self, StreamExt};
 
+use crate::{client, crypto};
 use crate::errortype::ConnectorError;
-use crate::models::connector::{ConnectorInput, Connector};
-use crate::actions::log;
+use crate::models::{connector::{self, ConnectorInput, Connector}, history::Change};
+use crate::actions::{history, log, routing};
 
-const BUCKET: &'static str = "atlassian-connector";
+/// Connector objects fetched from storage at the same time.
+const LOAD_CONCURRENCY: usize = 16;
 
-pub async fn add_connector(db: &S3Client, payload: ConnectorInput, id: String) -> Result<String, ConnectorError> {
+/// Key of a connector's `extension` object, `yml` for the connector and `csv` for its log.
+pub fn object(id: &str, connector_id: &str, extension: &str) -> String {
+    format!("{}/{}.{}", id, connector_id, extension)
+}
+
+/// Names are display labels, one shaped like an id would make lookups ambiguous.
+fn check_name(name: &str) -> Result<(), ConnectorError> {
+    if name.trim().is_empty() || connector::is_id(name) {
+        return Err(ConnectorError::ConNameInval)
+    }
+    Ok(())
+}
+
+pub async fn add_connector(db: &client::storage::Client, payload: ConnectorInput, id: String, author: &str) -> Result<String, ConnectorError> {
+    check_name(&payload.name)?;
     if connector_exist(&db, &payload.name, &id).await {
         return Err(ConnectorError::ConCreateExist)
     } else {
+        check_bot(&payload.token, &payload.chatid, payload.thread_id, &payload.topics).await?;
+
         let file = Connector {
+            id: connector::new_id(),
             name: payload.name,
             description: payload.description,
-            token: payload.token,
+            token: if payload.bot_id.is_some() { String::new() } else { payload.token },
+            bot_id: payload.bot_id,
             chatid: payload.chatid,
+            thread_id: payload.thread_id,
+            topics: payload.topics,
             active: payload.active,
             schedule: payload.schedule,
             duration: payload.duration,
@@ -25,180 +49,641 @@ pub async fn add_connector(db: &S3Client, payload: ConnectorInput, id: String) -
             event: payload.event,
             created_at: chrono::Utc::now()
                     .with_timezone(&chrono::FixedOffset::east_opt(7 * 3600).unwrap()),
-            updated_at: None
+            updated_at: None,
+            version: 1
         };
-        match db.put_object(PutObjectRequest {
-            bucket: BUCKET.to_owned(),
-            key: format!("{}/{}.yml", id, file.name),
-            body: Some((serde_yaml::to_string(&file).unwrap().into_bytes()).into()),
-            ..Default::default()
-        }).await{
+        if let client::storage::Client::Sql(sql) = db {
+            return match sql.insert_connector(&id, &seal(&file)?).await {
+                Ok(_) => {
+                    routing::update(&id, &file.id, Some(&file));
+                    history::record(db, &file, Change::Created, file.version, author, &id).await;
+                    Ok(format!("Connector successfuly created with id {}", file.id))
+                },
+                Err(e) => Err(ConnectorError::DbError(e))
+            }
+        }
+        // Keys are ids, only the lock keeps a concurrent create or rename from taking the name too
+        let guard = lock_workspace(db, &id).await?;
+        if connector_exist(&db, &file.name, &id).await {
+            return Err(ConnectorError::ConCreateExist)
+        }
+        match db.put(&object(&id, &file.id, "yml"), seal_connector(&file)?.into_bytes()).await{
             Ok(_) => {
-                match log::add_log(&db, file.name.clone(), None, id).await{
-                    Ok(_) => return Ok("Connector successfuly created".to_owned()),
-                    Err (e) => return Err(ConnectorError::RusError(e.to_string()))
+                match log::add_log(&db, file.id.clone(), None, id.clone()).await{
+                    Ok(_) => {
+                        drop(guard);
+                        routing::update(&id, &file.id, Some(&file));
+                        history::record(db, &file, Change::Created, file.version, author, &id).await;
+                        return Ok(format!("Connector successfuly created with id {}", file.id))
+                    },
+                    Err (e) => {
+                        // A connector without its log can't record deliveries, undo it
+                        let _res = db.delete(&object(&id, &file.id, "yml")).await;
+                        return Err(ConnectorError::RusError(e.to_string()))
+                    }
                 }
             },
-            Err(e) => return Err(ConnectorError::RusError(e.to_string()))
+            Err(e) => return Err(ConnectorError::RusError(e))
         }
     }
 }
 
-pub async fn delete_connector(db: &S3Client, target_name: String, id: String) -> Result<String, ConnectorError> {
-    if connector_exist(&db, &target_name, &id).await {
-        match db.delete_object( DeleteObjectRequest  {
-            bucket: BUCKET.to_owned(),
-            key: format!("{}/{}.yml", id, target_name),
-            ..Default::default()
-        }).await{
-            Ok(_) => {
-                let _res = db.delete_object( DeleteObjectRequest  {
-                    bucket: BUCKET.to_owned(),
-                    key: format!("{}/{}.csv", id, target_name),
-                    ..Default::default()
-                }).await;
-                return Ok("Connector successfuly deleted".to_owned())
-            },
-            Err(e) => return Err(ConnectorError::RusError(e.to_string()))
+/// Deletes the connector `target_name` and its delivery log, its history is kept so it
+/// can be restored.
+pub async fn delete_connector(db: &client::storage::Client, target_name: String, id: String, author: &str) -> Result<String, ConnectorError> {
+    let con = match find_connector(db, &target_name, &id).await? {
+        Some(con) => con,
+        None => return Err(ConnectorError::ConNotFound)
+    };
+    let deleted = match db {
+        client::storage::Client::Sql(sql) => sql.delete_connector(&id, &con.id).await.map_err(ConnectorError::DbError),
+        _ => {
+            let _guard = lock_workspace(db, &id).await?;
+            db.delete(&object(&id, &con.id, "yml")).await.map_err(ConnectorError::RusError)
         }
-    } else {
-        return Err(ConnectorError::ConNotFound)
+    };
+    match deleted {
+        Ok(_) => {
+            routing::update(&id, &con.id, None);
+            if !matches!(db, client::storage::Client::Sql(_)) {
+                let _res = db.delete(&object(&id, &con.id, "csv")).await;
+            }
+            history::record(db, &con, Change::Deleted, con.version + 1, author, &id).await;
+            return Ok("Connector successfuly deleted".to_owned())
+        },
+        Err(e) => return Err(e)
     }
 }
 
-pub async fn update_connector(db: &S3Client, target_name: String, payload: &mut Connector, id: String) -> Result<String, ConnectorError> {
-    if connector_exist(&db, &target_name, &id).await {
-        if connector_exist(&db, &payload.name, &id).await && &payload.name != &target_name {
-            return Err(ConnectorError::ConUpdateExist)
-        } 
-        
-        let bot = teloxide::Bot::new(&payload.token.to_owned());
-
-        if let Err(_e) = bot.get_me().await {
-            return Err(ConnectorError::TokenInval)
-        }
-
-        if let Err(_e) = bot.get_chat(payload.chatid.clone()).send().await {
-            return Err(ConnectorError::ChatidInval)
-        }
-        
-        payload.updated_at = Some(chrono::Utc::now()
-            .with_timezone(&chrono::FixedOffset::east_opt(7 * 3600).unwrap()));
-
-        match db.put_object(PutObjectRequest {
-            bucket: BUCKET.to_string(),
-            key: format!("{}/{}.yml", id, payload.name),
-            body: Some((serde_yaml::to_string(&payload).unwrap().into_bytes()).into()),
-            ..Default::default()
-        }).await{
-            Ok(_) => {          
-                if &payload.name != &target_name {
-                    match db.delete_object(DeleteObjectRequest {
-                        bucket: BUCKET.to_owned(),
-                        key: format!("{}/{}.yml", id, target_name),
-                        ..Default::default()
-                    }).await {
-                        Ok(_) =>  {
-                            log::add_log(&db, payload.name.clone(), Some(log::get_one_log(&db, target_name.clone(), id.clone()).await.unwrap()), id.clone()).await;
-                            db.delete_object(DeleteObjectRequest  {
-                                bucket: BUCKET.to_owned(),
-                                key: format!("{}/{}.csv", id, target_name),
-                                ..Default::default()
-                            }).await;
-
-                            return Ok("Connector successfuly updated! ps.new name".to_owned())
-                        },
-                        Err(e) => return Err(ConnectorError::RusError(e.to_string()))
+/// Held across a read-check-write of a workspace's objects, released when dropped.
+pub struct WorkspaceGuard {
+    _folder: Option<std::fs::File>,
+    _task: tokio::sync::OwnedMutexGuard<()>
+}
+
+/// Serialises connector, log and history writes per workspace for the object stores, which
+/// can't make a write conditional on the version they replace. Tasks of this process queue
+/// on a mutex, other processes sharing a local data directory on its folder lock. Nothing
+/// covers other instances writing to the same bucket, S3 storage takes a single instance.
+pub async fn lock_workspace(db: &client::storage::Client, id: &str) -> Result<WorkspaceGuard, ConnectorError> {
+    static LOCKS: OnceLock<Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>> = OnceLock::new();
+    let lock = LOCKS.get_or_init(Default::default).lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+        .entry(id.to_string()).or_default().clone();
+    let task = lock.lock_owned().await;
+    match db.lock_folder(id).await {
+        Ok(folder) => Ok(WorkspaceGuard { _folder: folder, _task: task }),
+        Err(e) => Err(ConnectorError::RusError(e))
+    }
+}
+
+/// Replaces the connector `target_name` (its id or name) with `payload`. The id stays, so a
+/// rename is a plain update and the delivery log stays where it is. `expected` is the
+/// version the caller based its change on (`If-Match`, else `payload.version` when set),
+/// the update fails with `ConConflict` if the connector has moved on since, and so does
+/// one racing another update.
+pub async fn update_connector(db: &client::storage::Client, target_name: String, payload: &mut Connector, id: String, expected: Option<i64>, author: &str) -> Result<String, ConnectorError> {
+    let current = match find_connector(db, &target_name, &id).await? {
+        Some(con) => con,
+        None => return Err(ConnectorError::ConNotFound)
+    };
+    let expected = expected.or(Some(payload.version).filter(|version| *version != 0));
+    if expected.is_some_and(|version| version != current.version) {
+        return Err(ConnectorError::ConConflict)
+    }
+    check_name(&payload.name)?;
+    if payload.name != current.name && connector_exist(&db, &payload.name, &id).await {
+        return Err(ConnectorError::ConUpdateExist)
+    }
+    payload.id = current.id.clone();
+
+    if payload.bot_id.is_none() && crypto::is_unchanged(&payload.token, &current.token) {
+        payload.token = current.token.clone();
+    }
+
+    check_bot(&payload.token, &payload.chatid, payload.thread_id, &payload.topics).await?;
+    if payload.bot_id.is_some() {
+        payload.token.clear();
+    }
+    save(db, &current, payload, &id, author, Change::Updated).await
+}
+
+/// Applies a JSON merge patch (RFC 7396) to `current`: members set to `null` are removed,
+/// objects merge recursively and anything else replaces what was there. A masked or empty
+/// `token` keeps the current one, `id` and `created_at` can't be changed.
+pub fn merge_patch(current: &Connector, patch: &serde_json::Value) -> Result<Connector, ConnectorError> {
+    if !patch.is_object() {
+        return Err(ConnectorError::ConPatchInval("the patch must be a JSON object".to_owned()))
+    }
+    let mut merged = serde_json::to_value(current).map_err(|e| ConnectorError::ConPatchInval(e.to_string()))?;
+    merge(&mut merged, patch);
+    let mut patched: Connector = serde_json::from_value(merged).map_err(|e| ConnectorError::ConPatchInval(e.to_string()))?;
+    if patched.id != current.id {
+        return Err(ConnectorError::ConPatchInval("id cannot be changed".to_owned()))
+    }
+    if patched.created_at != current.created_at {
+        return Err(ConnectorError::ConPatchInval("created_at cannot be changed".to_owned()))
+    }
+    if crypto::is_unchanged(&patched.token, &current.token) {
+        patched.token = current.token.clone();
+    }
+    // `version` is only a precondition, removing it asks for none like leaving it out
+    if patched.version == 0 {
+        patched.version = current.version;
+    }
+    Ok(patched)
+}
+
+fn merge(target: &mut serde_json::Value, patch: &serde_json::Value) {
+    match patch {
+        serde_json::Value::Object(members) => {
+            if !target.is_object() {
+                *target = serde_json::Value::Object(Default::default());
+            }
+            if let serde_json::Value::Object(target) = target {
+                for (key, value) in members {
+                    if value.is_null() {
+                        target.remove(key);
+                    } else {
+                        merge(target.entry(key.clone()).or_insert(serde_json::Value::Null), value);
                     }
-                } else {
-                    return Ok("Connector successfuly updated!".to_owned())
                 }
-            }, 
-            Err(e) => return Err(ConnectorError::RusError(e.to_string()))
+            }
+        },
+        _ => *target = patch.clone()
+    }
+}
+
+/// Whether `patched` delivers somewhere else than `current`, which is when the bot and
+/// chat have to be checked again. A registry bot's token is resolved by its `bot_id`.
+pub fn bot_changed(current: &Connector, patched: &Connector) -> bool {
+    patched.bot_id != current.bot_id
+        || patched.chatid != current.chatid
+        || patched.thread_id != current.thread_id
+        || patched.topics != current.topics
+        || (patched.bot_id.is_none() && patched.token != current.token)
+}
+
+/// Stores `patched`, the result of `merge_patch` on `current`, checking only what changed:
+/// the name when renamed and the bot and chat when `bot_changed`. `expected` works as for
+/// `update_connector`, without `If-Match` a `version` in the patch is the expected one.
+pub async fn patch_connector(db: &client::storage::Client, current: &Connector, patched: &mut Connector, id: String, expected: Option<i64>, author: &str) -> Result<String, ConnectorError> {
+    if expected.unwrap_or(patched.version) != current.version {
+        return Err(ConnectorError::ConConflict)
+    }
+    if patched.name != current.name {
+        check_name(&patched.name)?;
+        if connector_exist(&db, &patched.name, &id).await {
+            return Err(ConnectorError::ConUpdateExist)
         }
-    } else {
-        return Err(ConnectorError::ConNotFound)
     }
+    if bot_changed(current, patched) {
+        check_bot(&patched.token, &patched.chatid, patched.thread_id, &patched.topics).await?;
+    }
+    if patched.bot_id.is_some() {
+        patched.token.clear();
+    }
+    save(db, current, patched, &id, author, Change::Updated).await
+}
+
+/// Puts a connector back the way a revision of its history left it, `restored` being that
+/// revision's snapshot with the bot token of its `bot_id` resolved or a replacement for its
+/// own token, else the current token is kept. The restore is a new version, checked like a
+/// patch against `current`. A deleted connector (`current` is `None`) is recreated under
+/// its old id with a fresh delivery log.
+pub async fn restore_connector(db: &client::storage::Client, current: Option<&Connector>, restored: &mut Connector, id: String, expected: Option<i64>, author: &str) -> Result<String, ConnectorError> {
+    let current = match current {
+        Some(current) => current,
+        None => return recreate(db, restored, &id, author).await
+    };
+    if expected.is_some_and(|version| version != current.version) {
+        return Err(ConnectorError::ConConflict)
+    }
+    if restored.name != current.name {
+        check_name(&restored.name)?;
+        if connector_exist(&db, &restored.name, &id).await {
+            return Err(ConnectorError::ConUpdateExist)
+        }
+    }
+    restored.id = current.id.clone();
+    restored.created_at = current.created_at;
+    if restored.bot_id.is_none() && crypto::is_unchanged(&restored.token, &current.token) {
+        restored.token = current.token.clone();
+    }
+    if bot_changed(current, restored) {
+        check_bot(&restored.token, &restored.chatid, restored.thread_id, &restored.topics).await?;
+    }
+    if restored.bot_id.is_some() {
+        restored.token.clear();
+    }
+    save(db, current, restored, &id, author, Change::Restored).await
+}
+
+async fn recreate(db: &client::storage::Client, restored: &mut Connector, id: &str, author: &str) -> Result<String, ConnectorError> {
+    check_name(&restored.name)?;
+    if connector_exist(&db, &restored.name, id).await {
+        return Err(ConnectorError::ConCreateExist)
+    }
+    check_bot(&restored.token, &restored.chatid, restored.thread_id, &restored.topics).await?;
+    if restored.bot_id.is_some() {
+        restored.token.clear();
+    }
+    let latest = history::read_history(db, &restored.id, id).await?.iter().map(|revision| revision.version).max();
+    restored.version = latest.unwrap_or(restored.version) + 1;
+    restored.updated_at = Some(chrono::Utc::now()
+        .with_timezone(&chrono::FixedOffset::east_opt(7 * 3600).unwrap()));
+
+    match db {
+        client::storage::Client::Sql(sql) => sql.insert_connector(id, &seal(restored)?).await.map_err(ConnectorError::DbError)?,
+        _ => {
+            let guard = lock_workspace(db, id).await?;
+            if db.exists(&object(id, &restored.id, "yml")).await {
+                return Err(ConnectorError::ConConflict)
+            }
+            if connector_exist(&db, &restored.name, id).await {
+                return Err(ConnectorError::ConCreateExist)
+            }
+            db.put(&object(id, &restored.id, "yml"), seal_connector(restored)?.into_bytes()).await.map_err(ConnectorError::RusError)?;
+            drop(guard);
+            if let Err(e) = log::add_log(db, restored.id.clone(), None, id.to_string()).await {
+                let _res = db.delete(&object(id, &restored.id, "yml")).await;
+                return Err(ConnectorError::RusError(e.to_string()))
+            }
+        }
+    }
+    routing::update(id, &restored.id, Some(restored));
+    history::record(db, restored, Change::Restored, restored.version, author, id).await;
+    Ok(format!("Connector successfuly restored as version {} with id {}", restored.version, restored.id))
+}
+
+/// Writes `payload` over `current` as its next version, unless `current` has been replaced
+/// in the meantime, and records it in the history as `change` by `author`.
+async fn save(db: &client::storage::Client, current: &Connector, payload: &mut Connector, id: &str, author: &str, change: Change) -> Result<String, ConnectorError> {
+    payload.updated_at = Some(chrono::Utc::now()
+        .with_timezone(&chrono::FixedOffset::east_opt(7 * 3600).unwrap()));
+    payload.version = current.version + 1;
+
+    let stored = match db {
+        client::storage::Client::Sql(sql) => match sql.replace_connector(id, &seal(payload)?, current.version).await {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(ConnectorError::ConConflict),
+            Err(e) => Err(ConnectorError::DbError(e))
+        },
+        _ => {
+            let _guard = lock_workspace(db, id).await?;
+            match find_connector(db, &current.id, id).await? {
+                // A rename checked the name before the lock, a concurrent create may have taken it since
+                Some(_) if payload.name != current.name && find_connector(db, &payload.name, id).await?.is_some_and(|con| con.id != payload.id) => {
+                    Err(ConnectorError::ConUpdateExist)
+                },
+                Some(latest) if latest.version == current.version => {
+                    db.put(&object(id, &payload.id, "yml"), seal_connector(payload)?.into_bytes()).await.map_err(ConnectorError::RusError)
+                },
+                Some(_) => Err(ConnectorError::ConConflict),
+                None => Err(ConnectorError::ConNotFound)
+            }
+        }
+    };
+    match stored {
+        Ok(_) => {
+            routing::update(id, &payload.id, Some(payload));
+            history::record(db, payload, change, payload.version, author, id).await;
+            if change == Change::Restored {
+                return Ok(format!("Connector successfuly restored as version {}", payload.version))
+            }
+            if payload.name != current.name {
+                return Ok("Connector successfuly updated! ps.new name".to_owned())
+            }
+            return Ok("Connector successfuly updated!".to_owned())
+        },
+        Err(e) => return Err(e)
+    }
+}
+
+/// Parses the connector stored at `key` (`{id}/{connector id}.yml`), the key decides its id.
+fn load(key: &str, data: &[u8]) -> Result<Connector, ConnectorError> {
+    let mut con: Connector = serde_yaml::from_slice(data)
+        .map_err(|e| ConnectorError::RusError(format!("{} is not a valid connector: {}", key, e)))?;
+    if let Some(connector_id) = connector_id(key) {
+        con.id = connector_id.to_string();
+    }
+    open_connector(con)
+}
+
+/// The connector id of a `{id}/{connector id}.yml` key, `None` for any other object.
+fn connector_id(key: &str) -> Option<&str> {
+    let stem = key.rsplit('/').next()?.strip_suffix(".yml")?;
+    connector::is_id(stem).then_some(stem)
 }
 
-pub async fn get_connectors(db: &S3Client, id: String) -> Result<Vec<Connector>, ConnectorError> {
-    match db.list_objects_v2(ListObjectsV2Request {
-        bucket: BUCKET.to_owned(),
-        prefix: Some(format!("{}/", id)),
-        ..Default::default()
-    }).await {
-        Ok(object) => {
-            if &object.contents.as_ref().unwrap().len() == &"1".parse::<usize>().unwrap() {
+pub async fn get_connectors(db: &client::storage::Client, id: String) -> Result<Vec<Connector>, ConnectorError> {
+    if let client::storage::Client::Sql(sql) = db {
+        return match sql.get_connectors(&id).await {
+            Ok(cons) if cons.is_empty() => Err(ConnectorError::ConEmpty),
+            Ok(cons) => cons.into_iter().map(open_connector).collect(),
+            Err(e) => Err(ConnectorError::DbError(e))
+        }
+    }
+    match db.list(&format!("{}/", id)).await {
+        Ok(keys) => {
+            // Judged by the connectors themselves, the `{id}/` marker may be missing
+            let list: Vec<String> = keys
+                .into_iter()
+                .filter(|key| connector_id(key).is_some())
+                .collect();
+            if list.is_empty() {
                 return Err(ConnectorError::ConEmpty)
             } else {
-                let list: Vec<String> = object.contents.unwrap()
-                    .into_iter()
-                    .flat_map(|ob| {
-                        if ob.key.as_ref().unwrap().ends_with(".yml") { Some(ob.key.unwrap())} 
-                        else { None }
-                    })
-                    .collect();
-                
                 let mut cons: Vec<Connector> = Vec::new();
 
-                for pa in list {
-                    match db.get_object(GetObjectRequest {
-                        bucket: BUCKET.to_owned(),
-                        key: pa.clone(),
-                        ..Default::default()
-                    }).await {
-                        Ok(ob) =>{
-                            let result = tokio::task::spawn_blocking(|| {
-                                let mut data = String::new();
-                                ob.body.unwrap().into_blocking_read().read_to_string(&mut data);
-                                let yaml: Connector = serde_yaml::from_str(&data).unwrap();
-                                return yaml
-                            }).await.expect("Task panicked");
-                            cons.push(result);
-                        },
-                        Err(e) => return Err(ConnectorError::RusError(e.to_string()))
+                let mut loads = stream::iter(list).map(|pa| async move { (db.get(&pa).await, pa) }).buffered(LOAD_CONCURRENCY);
+                while let Some((loaded, pa)) = loads.next().await {
+                    match loaded {
+                        Ok(data) => cons.push(load(&pa, &data)?),
+                        Err(e) => return Err(ConnectorError::RusError(e))
                     }    
                 }   
                 return Ok(cons)
             }
         },
-        Err(e) => return Err(ConnectorError::RusError(e.to_string()))
+        Err(e) => return Err(ConnectorError::RusError(e))
     }
     
 }
 
-#[allow(warnings)]
-pub async fn get_one_connector(db: &S3Client, target_name: String, id: String) -> Option<Connector> {
-    if connector_exist(&db, &target_name, &id).await {
-        match db.get_object(GetObjectRequest {
-            bucket: BUCKET.to_owned(),
-            key: format!("{}/{}.yml", id, target_name),
-            ..Default::default()
-        }).await {
-            Ok(ob) =>{
-                let result = tokio::task::spawn_blocking(|| {
-                    let mut data = String::new();
-                    ob.body.unwrap().into_blocking_read().read_to_string(&mut data);
-                    let yaml: Connector = serde_yaml::from_str(&data).unwrap();
-                    return yaml
-                }).await.expect("Task panicked");
-                Some(result)
+/// The connector with id or, failing that, name `target`. Ids are a single read, names are
+/// looked up among all of the workspace's connectors.
+pub async fn find_connector(db: &client::storage::Client, target: &str, id: &str) -> Result<Option<Connector>, ConnectorError> {
+    if let client::storage::Client::Sql(sql) = db {
+        return match sql.find_connector(id, target).await {
+            Ok(con) => con.map(open_connector).transpose(),
+            Err(e) => Err(ConnectorError::DbError(e))
+        }
+    }
+    let key = object(id, target, "yml");
+    if connector::is_id(target) && db.exists(&key).await {
+        return match db.get(&key).await {
+            Ok(data) => load(&key, &data).map(Some),
+            Err(e) => Err(ConnectorError::RusError(e))
+        }
+    }
+    match get_connectors(db, id.to_string()).await {
+        Ok(cons) => Ok(cons.into_iter().find(|con| con.name == target)),
+        Err(ConnectorError::ConEmpty) => Ok(None),
+        Err(e) => Err(e)
+    }
+}
+
+pub async fn get_one_connector(db: &client::storage::Client, target_name: String, id: String) -> Option<Connector> {
+    match find_connector(db, &target_name, &id).await {
+        Ok(con) => con,
+        Err(e) => {
+            println!("{:?}", e);
+            None
+        }
+    }
+}
+
+/// Active connectors routed to the event. Answers come from the in-memory route index,
+/// on a miss the SQL backend runs a single indexed query and the object stores load every
+/// connector of the workspace, indexing all of its routes at once.
+pub async fn get_routed_connectors(db: &client::storage::Client, project_id: &str, event: &str, site: &str, id: String) -> Result<Vec<Connector>, ConnectorError> {
+    if let Some(cons) = routing::lookup(&id, project_id, event, site) {
+        return Ok(cons)
+    }
+    let generation = routing::generation();
+    match db {
+        client::storage::Client::Sql(sql) => match sql.route_connectors(&id, project_id, event, site).await {
+            Ok(cons) => {
+                let cons = cons.into_iter().map(open_connector).collect::<Result<Vec<Connector>, ConnectorError>>()?;
+                routing::store(generation, &id, project_id, event, site, cons.clone());
+                Ok(cons)
             },
-            Err(_e) => None
+            Err(e) => Err(ConnectorError::DbError(e))
+        },
+        _ => match get_connectors(db, id.clone()).await {
+            Ok(cons) => Ok(routing::store_workspace(generation, &id, project_id, event, site, cons)),
+            Err(ConnectorError::ConEmpty) => Ok(routing::store_workspace(generation, &id, project_id, event, site, vec![])),
+            Err(e) => Err(e)
         }
-    } else {
-        None
     }
 }
 
-pub async fn connector_exist(db: &S3Client, name: &str, id: &str) -> bool {
-    match db.head_object(HeadObjectRequest {
-        bucket: BUCKET.to_string(),
-        key: format!("{}/{}.yml", id, name.to_owned()),
-        ..Default::default()
-    }).await{
-        Ok(_) => true,
-        Err(_) => false
+/// Written once every connector has been moved to an id-based key, later starts skip the scan.
+const UPGRADED_KEYS: &str = ".upgraded-keys";
+
+/// Moves connectors stored before ids existed, at `{id}/{name}.yml`, to
+/// `{id}/{connector id}.yml` along with their log. The id comes from the old key, so a
+/// run cut short is finished by the next one. Objects that aren't valid connectors stay
+/// where they are. A connector a storage error kept from moving is retried on the next run,
+/// the `UPGRADED_KEYS` marker is only written once none is left. Returns how many connectors were moved.
+pub async fn upgrade_keys(db: &client::storage::Client) -> Result<usize, String> {
+    if let client::storage::Client::Sql(_) = db {
+        return Ok(0)
+    }
+    if db.exists(UPGRADED_KEYS).await {
+        return Ok(0)
+    }
+    let (mut moved, mut left) = (0, 0);
+    for key in db.list("").await? {
+        let (workspace, name) = match key.strip_suffix(".yml").and_then(|stem| stem.split_once('/')) {
+            Some((workspace, name)) if !connector::is_id(name) => (workspace, name),
+            _ => continue
+        };
+        match upgrade_key(db, &key, workspace, name).await {
+            Ok(true) => moved += 1,
+            Ok(false) => {},
+            Err(e) => {
+                println!("Failed to move {}, retrying on the next start: {}", key, e);
+                left += 1;
+            }
+        }
+    }
+    if left == 0 {
+        db.put(UPGRADED_KEYS, Vec::new()).await?;
+    }
+    Ok(moved)
+}
+
+/// False when the object at `key` isn't a valid connector and was left in place.
+async fn upgrade_key(db: &client::storage::Client, key: &str, workspace: &str, name: &str) -> Result<bool, String> {
+    let mut con: Connector = match serde_yaml::from_slice(&db.get(key).await?) {
+        Ok(con) => con,
+        Err(e) => {
+            println!("Leaving {} in place, it is not a valid connector: {}", key, e);
+            return Ok(false)
+        }
+    };
+    con.id = connector::legacy_id(workspace, name);
+    db.put(&object(workspace, &con.id, "yml"), serde_yaml::to_string(&con).unwrap().into_bytes()).await?;
+
+    let (log, legacy_log) = (object(workspace, &con.id, "csv"), object(workspace, name, "csv"));
+    if db.exists(&legacy_log).await {
+        // Kept if an earlier run already moved it, deliveries may have been logged since
+        if !db.exists(&log).await {
+            db.put(&log, db.get(&legacy_log).await?).await?;
+        }
+        db.delete(&legacy_log).await?;
+    }
+    db.delete(key).await?;
+    Ok(true)
+}
+
+fn seal(con: &Connector) -> Result<Connector, ConnectorError> {
+    let mut sealed = con.clone();
+    sealed.token = crypto::encrypt(&con.token).map_err(|e| ConnectorError::SecretError(e.to_string()))?;
+    Ok(sealed)
+}
+
+fn seal_connector(con: &Connector) -> Result<String, ConnectorError> {
+    Ok(serde_yaml::to_string(&seal(con)?).unwrap())
+}
+
+fn open_connector(mut con: Connector) -> Result<Connector, ConnectorError> {
+    con.token = crypto::decrypt(&con.token).map_err(|e| ConnectorError::SecretError(e.to_string()))?;
+    Ok(con)
+}
+
+pub async fn connector_exist(db: &client::storage::Client, name: &str, id: &str) -> bool {
+    matches!(find_connector(db, name, id).await, Ok(Some(_)))
+}
+
+pub async fn check_bot(token: &str, chatid: &str, thread_id: Option<i32>, topics: &HashMap<String, i32>) -> Result<(), ConnectorError> {
+    let bot = client::telegram::bot(token);
+
+    if let Err(_e) = bot.get_me().await {
+        return Err(ConnectorError::TokenInval)
+    }
+
+    let chat = match bot.get_chat(chatid.to_owned()).send().await {
+        Ok(chat) => chat,
+        Err(_e) => return Err(ConnectorError::ChatidInval)
+    };
+
+    if thread_id.is_none() && topics.is_empty() {
+        return Ok(())
+    }
+
+    let is_forum = match chat.kind {
+        ChatKind::Public(public) => match public.kind {
+            PublicChatKind::Supergroup(group) => group.is_forum,
+            _ => false
+        },
+        ChatKind::Private(_) => false
+    };
+    if !is_forum {
+        return Err(ConnectorError::ChatNotForum)
+    }
+
+    // The Bot API has no lookup for a single topic, so probe each one with a chat action
+    for topic in thread_id.iter().chain(topics.values()) {
+        if let Err(_e) = bot.send_chat_action(chatid.to_owned(), ChatAction::Typing).message_thread_id(*topic).send().await {
+            return Err(ConnectorError::TopicInval(*topic))
+        }
     }
+    Ok(())
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::connector::Project;
+    use serde_json::json;
+
+    fn current() -> Connector {
+        Connector {
+            id: connector::new_id(),
+            name: "alerts".to_string(),
+            description: "Issue alerts".to_string(),
+            token: "123456:ABCDEFGHIJ".to_string(),
+            bot_id: None,
+            chatid: "-100200".to_string(),
+            thread_id: Some(7),
+            topics: HashMap::from([("jira:issue_created".to_string(), 3)]),
+            active: true,
+            schedule: false,
+            duration: String::new(),
+            project: vec![Project { id: "10000".to_string(), name: "Ops".to_string(), site: Some("site".to_string()) }],
+            event: vec!["jira:issue_created".to_string()],
+            created_at: chrono::DateTime::parse_from_rfc3339("2026-10-19T08:00:00+07:00").unwrap(),
+            updated_at: None,
+            version: 4
+        }
+    }
+
+    #[test]
+    fn members_replace_and_null_removes() {
+        let current = current();
+        let patched = merge_patch(&current, &json!({ "description": "Renamed", "thread_id": null, "event": ["jira:issue_updated"] })).unwrap();
+        assert_eq!(patched.description, "Renamed");
+        assert_eq!(patched.thread_id, None);
+        assert_eq!(patched.event, vec!["jira:issue_updated".to_string()]);
+        assert_eq!(patched.name, current.name);
+        assert_eq!(patched.project, current.project);
+    }
+
+    #[test]
+    fn objects_merge_member_by_member() {
+        let current = current();
+        let patched = merge_patch(&current, &json!({ "topics": { "jira:issue_updated": 5, "jira:issue_created": null } })).unwrap();
+        assert_eq!(patched.topics, HashMap::from([("jira:issue_updated".to_string(), 5)]));
+    }
+
+    #[test]
+    fn required_members_cannot_be_removed() {
+        assert!(matches!(merge_patch(&current(), &json!({ "name": null })), Err(ConnectorError::ConPatchInval(_))));
+        assert!(matches!(merge_patch(&current(), &json!({ "active": "yes" })), Err(ConnectorError::ConPatchInval(_))));
+    }
+
+    #[test]
+    fn patch_must_be_an_object() {
+        assert!(matches!(merge_patch(&current(), &json!([{ "name": "x" }])), Err(ConnectorError::ConPatchInval(_))));
+        assert!(matches!(merge_patch(&current(), &json!(null)), Err(ConnectorError::ConPatchInval(_))));
+    }
+
+    #[test]
+    fn id_and_created_at_are_immutable() {
+        let current = current();
+        assert!(matches!(merge_patch(&current, &json!({ "id": connector::new_id() })), Err(ConnectorError::ConPatchInval(_))));
+        assert!(matches!(merge_patch(&current, &json!({ "id": null })), Err(ConnectorError::ConPatchInval(_))));
+        assert!(matches!(merge_patch(&current, &json!({ "created_at": "2020-01-01T00:00:00+07:00" })), Err(ConnectorError::ConPatchInval(_))));
+        // Sending them back unchanged is fine
+        assert!(merge_patch(&current, &json!({ "id": current.id, "created_at": current.created_at })).is_ok());
+    }
+
+    #[test]
+    fn masked_or_empty_token_keeps_the_current_one() {
+        let current = current();
+        for token in [json!(crypto::mask(&current.token)), json!(""), json!(null)] {
+            assert_eq!(merge_patch(&current, &json!({ "token": token })).unwrap().token, current.token);
+        }
+        assert_eq!(merge_patch(&current, &json!({ "token": "654321:NEWTOKEN" })).unwrap().token, "654321:NEWTOKEN");
+    }
+
+    #[test]
+    fn version_is_the_expected_version() {
+        let current = current();
+        assert_eq!(merge_patch(&current, &json!({})).unwrap().version, 4);
+        assert_eq!(merge_patch(&current, &json!({ "version": 2 })).unwrap().version, 2);
+        assert_eq!(merge_patch(&current, &json!({ "version": null })).unwrap().version, 4);
+    }
+
+    #[test]
+    fn bot_changes_only_when_delivery_target_changes() {
+        let current = current();
+        let patched = |patch| merge_patch(&current, &patch).unwrap();
+        assert!(!bot_changed(&current, &patched(json!({ "name": "renamed", "active": false, "project": [] }))));
+        assert!(!bot_changed(&current, &patched(json!({ "token": crypto::mask(&current.token) }))));
+        assert!(bot_changed(&current, &patched(json!({ "token": "654321:NEWTOKEN" }))));
+        assert!(bot_changed(&current, &patched(json!({ "chatid": "-100300" }))));
+        assert!(bot_changed(&current, &patched(json!({ "thread_id": null }))));
+        assert!(bot_changed(&current, &patched(json!({ "topics": { "jira:issue_created": 4 } }))));
+        assert!(bot_changed(&current, &patched(json!({ "bot_id": "bot" }))));
+
+        // A registry bot's token is resolved from its bot_id, a token in the patch doesn't matter
+        let mut registry = current.clone();
+        registry.bot_id = Some("bot".to_string());
+        registry.token.clear();
+        let patched = merge_patch(&registry, &json!({ "token": "654321:NEWTOKEN" })).unwrap();
+        assert!(!bot_changed(&registry, &patched));
+    }
+}
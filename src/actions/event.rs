@@ -1,12 +1,19 @@
+use std::str::FromStr;
+
 use chrono::TimeZone;
+use futures::stream::{self, StreamExt};
 use serde_json::Value;
-use rusoto_s3::S3Client;
-use teloxide::requests::Requester;
+use teloxide::payloads::SendMessageSetters;
+use teloxide::requests::{Request, Requester};
 
 use crate::actions::{connector, log};
+use crate::client;
 use crate::models::{connector::Connector, event::Event};
 
-pub async fn process_event(db: &S3Client, val: Value, id: String) -> Result<String, String>{
+/// Connectors an event is sent to at the same time.
+pub const SEND_CONCURRENCY: usize = 16;
+
+pub async fn process_event(db: &client::storage::Client, mongodb: &client::mongodb::Client, val: Value, id: String) -> Result<String, String>{
     let mut event = Event {
         timestamp: val.get("timestamp").and_then(|v| v.as_i64())
                     .unwrap_or(0) as i64,
@@ -93,9 +100,19 @@ pub async fn process_event(db: &S3Client, val: Value, id: String) -> Result<Stri
             }).unwrap_or_else(|| "".to_string());
     }
 
-    match find_connectors(db, &event.project_id, &event.webhook_event, id.clone()).await {
+    // Webhooks are registered per Jira site, the default site shares its workspace's id
+    let site = id;
+    let id = match mongodb::bson::oid::ObjectId::from_str(&site) {
+        Ok(site_id) => match mongodb.find_site(site_id).await {
+            Some(found) => found.workspace.to_hex(),
+            None => site.clone()
+        },
+        Err(_) => site.clone()
+    };
+
+    match find_connectors(db, &event.project_id, &event.webhook_event, &site, id.clone()).await {
         Some(cons)=> {
-            send_notification(db, event,cons,id).await;
+            send_notification(db, mongodb, event,cons,id).await;
             return Ok("Event processed".to_string())
         }
         None => return Ok("No Connector Related Found".to_string())
@@ -103,14 +120,14 @@ pub async fn process_event(db: &S3Client, val: Value, id: String) -> Result<Stri
    
 }
 
-pub async fn find_connectors(db: &S3Client, project_id: &str, event: &str, id: String) -> Option<Vec<Connector>> {
-    match connector::get_connectors(&db, id).await {
+pub async fn find_connectors(db: &client::storage::Client, project_id: &str, event: &str, site: &str, id: String) -> Option<Vec<Connector>> {
+    match connector::get_routed_connectors(&db, project_id, event, site, id.clone()).await {
         Ok(cons) => {
             let now = (chrono::Utc::now() + chrono::Duration::hours(7)).naive_utc().time();
 
             let filtered = cons
                 .into_iter()
-                .filter(|con| con.project.iter().any(|proyek|proyek.id == project_id)
+                .filter(|con| con.project.iter().any(|proyek|proyek.id == project_id && proyek.site.as_deref().unwrap_or(&id) == site)
                     && con.event.iter().any(|even| even.eq(&event))
                     && con.active
                     && if con.schedule {
@@ -144,7 +161,7 @@ pub async fn find_connectors(db: &S3Client, project_id: &str, event: &str, id: S
     }
 }
 
-pub async fn send_notification(db: &S3Client, event: Event, connectors: Vec<Connector>, id: String ) {
+pub async fn send_notification(db: &client::storage::Client, mongodb: &client::mongodb::Client, event: Event, connectors: Vec<Connector>, id: String ) {
     let time = chrono::Utc.timestamp_millis_opt(event.timestamp).unwrap()
         .with_timezone(&chrono::FixedOffset::east_opt(7 * 3600).unwrap())
         .format("%d/%m/%Y %H:%M").to_string();
@@ -192,18 +209,46 @@ pub async fn send_notification(db: &S3Client, event: Event, connectors: Vec<Conn
         );
     }
    
-    for con in connectors {
-        let mut attempt = 0;
-        loop {
-            attempt+=1;
-            let send = teloxide::Bot::new(con.token.clone()).send_message(con.chatid.clone(), &text.clone()).await;
-            if send.is_ok(){
-                log::write_log(&db, con.name, event.webhook_event.to_string(), "sent".to_string(), attempt, time.clone(), &id).await;
-                break
-            } else if attempt == 3{
-                log::write_log(&db, con.name, event.webhook_event.to_string(), "fail".to_string(), attempt, time.clone(), &id).await;
-                break
-            }
-        }  
+    stream::iter(connectors).for_each_concurrent(SEND_CONCURRENCY, |con| {
+        let (event, text, time, id) = (&event, &text, &time, &id);
+        async move {
+            let token = match &con.bot_id {
+                Some(bot_id) => {
+                    let owner = match mongodb::bson::oid::ObjectId::from_str(id) {
+                        Ok(owner) => owner,
+                        Err(_) => return
+                    };
+                    match mongodb.bot_token(bot_id, owner).await {
+                        Ok(token) => token,
+                        Err(_) => {
+                            log::write_log(&db, con.id, event.webhook_event.to_string(), "fail".to_string(), 0, time.clone(), id).await;
+                            return
+                        }
+                    }
+                },
+                None => con.token.clone()
+            };
+            deliver(db, client::telegram::bot(&token), con, event, text, time, id).await;
+        }
+    }).await;
+}
+
+/// Sends the message to one connector's chat, retrying up to three times, and logs the outcome.
+pub async fn deliver(db: &client::storage::Client, bot: teloxide::Bot, con: Connector, event: &Event, text: &str, time: &str, id: &str) {
+    let mut attempt = 0;
+    loop {
+        attempt+=1;
+        let mut message = bot.send_message(con.chatid.clone(), text);
+        if let Some(topic) = con.topics.get(&event.project_id).or(con.thread_id.as_ref()) {
+            message = message.message_thread_id(*topic);
+        }
+        let send = message.send().await;
+        if send.is_ok(){
+            log::write_log(&db, con.id, event.webhook_event.to_string(), "sent".to_string(), attempt, time.to_string(), id).await;
+            break
+        } else if attempt == 3{
+            log::write_log(&db, con.id, event.webhook_event.to_string(), "fail".to_string(), attempt, time.to_string(), id).await;
+            break
+        }
     }
-}
\ No newline at end of file
+}
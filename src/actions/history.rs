@@ -0,0 +1,111 @@
+use std::collections::BTreeSet;
+
+use crate::client;
+use crate::actions::connector;
+use crate::errortype::ConnectorError;
+use crate::models::{connector::{self as model, Connector}, history::{Change, FieldChange, Revision}};
+
+/// Fields that differ between any two revisions, left out of diffs.
+const UNDIFFED: [&str; 3] = ["token", "updated_at", "version"];
+
+/// Records `con` as `change` by `author` producing `version`. Like the delivery log a
+/// failure is printed instead of failing the change itself.
+pub async fn record(db: &client::storage::Client, con: &Connector, change: Change, version: i64, author: &str, id: &str) {
+    let mut snapshot = con.clone();
+    snapshot.token.clear();
+    let revision = Revision {
+        version,
+        change,
+        author: author.to_string(),
+        created_at: chrono::Utc::now()
+            .with_timezone(&chrono::FixedOffset::east_opt(7 * 3600).unwrap()),
+        connector: snapshot
+    };
+    if let client::storage::Client::Sql(sql) = db {
+        if let Err(e) = sql.add_revision(id, &revision).await {
+            println!("{:?}", e);
+        }
+        return
+    }
+    let _guard = match connector::lock_workspace(db, id).await {
+        Ok(guard) => guard,
+        Err(e) => {
+            println!("{:?}", e);
+            return
+        }
+    };
+    match read_history(db, &con.id, id).await {
+        Ok(mut revisions) => {
+            revisions.push(revision);
+            if let Err(e) = db.put(&connector::object(id, &con.id, "history"), serde_yaml::to_string(&revisions).unwrap().into_bytes()).await {
+                println!("{:?}", e);
+            }
+        },
+        Err(e) => println!("{:?}", e)
+    }
+}
+
+/// Revisions of the connector with id `connector_id`, oldest first.
+pub async fn read_history(db: &client::storage::Client, connector_id: &str, id: &str) -> Result<Vec<Revision>, ConnectorError> {
+    if let client::storage::Client::Sql(sql) = db {
+        return sql.get_history(id, connector_id).await.map_err(ConnectorError::DbError)
+    }
+    let key = connector::object(id, connector_id, "history");
+    if !db.exists(&key).await {
+        return Ok(vec![])
+    }
+    match db.get(&key).await {
+        Ok(data) => serde_yaml::from_slice(&data).map_err(|e| ConnectorError::RusError(format!("{} is not a valid history: {}", key, e))),
+        Err(e) => Err(ConnectorError::RusError(e))
+    }
+}
+
+/// The connector `target` (id or name) if it still exists and its history. Deleted
+/// connectors are only found by id, through the history they left.
+async fn find_history(db: &client::storage::Client, target: &str, id: &str) -> Result<(Option<Connector>, Vec<Revision>), ConnectorError> {
+    match connector::find_connector(db, target, id).await? {
+        Some(con) => {
+            let revisions = read_history(db, &con.id, id).await?;
+            Ok((Some(con), revisions))
+        },
+        None if model::is_id(target) => match read_history(db, target, id).await? {
+            revisions if revisions.is_empty() => Err(ConnectorError::ConNotFound),
+            revisions => Ok((None, revisions))
+        },
+        None => Err(ConnectorError::ConNotFound)
+    }
+}
+
+pub async fn get_history(db: &client::storage::Client, target_name: String, id: String) -> Result<Vec<Revision>, ConnectorError> {
+    find_history(db, &target_name, &id).await.map(|(_, revisions)| revisions)
+}
+
+/// The connector `target_name` if it still exists and its revision `version`, to restore.
+pub async fn get_revision(db: &client::storage::Client, target_name: String, version: i64, id: String) -> Result<(Option<Connector>, Revision), ConnectorError> {
+    let (current, revisions) = find_history(db, &target_name, &id).await?;
+    match revisions.into_iter().find(|revision| revision.version == version) {
+        Some(revision) => Ok((current, revision)),
+        None => Err(ConnectorError::ConVersionNotFound(version))
+    }
+}
+
+/// Fields that changed from revision `from` to revision `to` of the connector `target_name`.
+pub async fn diff(db: &client::storage::Client, target_name: String, from: i64, to: i64, id: String) -> Result<Vec<FieldChange>, ConnectorError> {
+    let (_, revisions) = find_history(db, &target_name, &id).await?;
+    let snapshot = |version: i64| revisions.iter()
+        .find(|revision| revision.version == version)
+        .map(|revision| serde_json::to_value(&revision.connector).unwrap())
+        .ok_or(ConnectorError::ConVersionNotFound(version));
+    let (from, to) = (snapshot(from)?, snapshot(to)?);
+    let (from, to) = (from.as_object().cloned().unwrap_or_default(), to.as_object().cloned().unwrap_or_default());
+
+    let fields: BTreeSet<&String> = from.keys().chain(to.keys()).collect();
+    Ok(fields.into_iter()
+        .filter(|field| !UNDIFFED.contains(&field.as_str()) && from.get(*field) != to.get(*field))
+        .map(|field| FieldChange {
+            field: field.clone(),
+            from: from.get(field).cloned(),
+            to: to.get(field).cloned()
+        })
+        .collect())
+}
@@ -1,12 +1,15 @@
-use rusoto_s3::{S3, S3Client, GetObjectRequest, PutObjectRequest};
-use std::io::Read;
-
+use crate::client;
+use crate::actions::connector;
 use crate::errortype::ConnectorError;
 use crate::models::log::Log;
 
-const BUCKET: &'static str = "atlassian-connector";
-
-pub async fn add_log(db: &S3Client, name: String, rec: Option<Vec<Log>>, id: String) -> Result<String, ConnectorError> {
+pub async fn add_log(db: &client::storage::Client, name: String, rec: Option<Vec<Log>>, id: String) -> Result<String, ConnectorError> {
+    if let client::storage::Client::Sql(sql) = db {
+        return match sql.replace_logs(&id, &name, rec.unwrap_or_default()).await {
+            Ok(_) => Ok("Log successfuly created".to_owned()),
+            Err(e) => Err(ConnectorError::DbError(e))
+        }
+    }
     let mut wtr = csv::Writer::from_writer(vec![]);
     wtr.write_record(&["event","status","attempt","time"]);
     if rec.is_some() {
@@ -17,19 +20,28 @@ pub async fn add_log(db: &S3Client, name: String, rec: Option<Vec<Log>>, id: Str
     wtr.flush();
     let data = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
 
-    match db.put_object(PutObjectRequest {
-        bucket: BUCKET.to_owned(),
-        key: format!("{}/{}.csv", id, name),
-        body: Some(data.into_bytes().into()),
-        ..Default::default()
-    }).await{
+    match db.put(&format!("{}/{}.csv", id, name), data.into_bytes()).await{
         Ok(_) => return Ok("Log successfuly created".to_owned()),
-        Err(e) => return Err(ConnectorError::RusError(e.to_string()))
+        Err(e) => return Err(ConnectorError::RusError(e))
     }
 }
 
-pub async fn write_log(db: &S3Client, target_name: String, ev: String, stat: String, att: i32, tim: String, id: &str) {
-    match get_one_log(db, target_name.clone(), id.to_string()).await {
+pub async fn write_log(db: &client::storage::Client, target_name: String, ev: String, stat: String, att: i32, tim: String, id: &str) {
+    if let client::storage::Client::Sql(sql) = db {
+        if let Err(e) = sql.add_log(id, &target_name, Log { event: ev, status: stat, attempt: att, time: tim }).await {
+            println!("{:?}", e);
+        }
+        return
+    }
+    // Appending rewrites the whole log, a concurrent delivery must not drop this row
+    let _guard = match connector::lock_workspace(db, id).await {
+        Ok(guard) => guard,
+        Err(e) => {
+            println!("{:?}", e);
+            return
+        }
+    };
+    match read_log(db, &target_name, id).await {
         Ok(mut rec)=> {
             rec.push(Log { 
                 event: ev,
@@ -43,29 +55,29 @@ pub async fn write_log(db: &S3Client, target_name: String, ev: String, stat: Str
     }
 }
 
-pub async fn get_one_log(db: &S3Client, target_name: String, id: String) -> Result< Vec<Log>, ConnectorError>{
-    match db.get_object(GetObjectRequest {
-        bucket: BUCKET.to_owned(),
-        key: format!("{}/{}.csv", id, target_name),
-        ..Default::default()
-    }).await {
-        Ok(ob) =>{
-            let result = tokio::task::spawn_blocking(|| {
-                let mut object_data = ob.body.unwrap().into_blocking_read();
-                let mut buffer = Vec::new();
-                object_data.read_to_end(&mut buffer);
+/// Delivery log of the connector `target_name`, its id or name.
+pub async fn get_one_log(db: &client::storage::Client, target_name: String, id: String) -> Result< Vec<Log>, ConnectorError>{
+    match connector::find_connector(db, &target_name, &id).await? {
+        Some(con) => read_log(db, &con.id, &id).await,
+        None => Err(ConnectorError::ConNotFound)
+    }
+}
 
-                let mut csv_reader = csv::ReaderBuilder::new()
-                    .from_reader(std::io::BufReader::new(&buffer[..]));
-                let records: Vec<Log> = csv_reader.deserialize::<Log>().map(|res| res.unwrap()).collect();
-                return records
-            }).await.expect("Task panicked");
+async fn read_log(db: &client::storage::Client, connector_id: &str, id: &str) -> Result< Vec<Log>, ConnectorError>{
+    if let client::storage::Client::Sql(sql) = db {
+        return sql.get_logs(id, connector_id).await.map_err(ConnectorError::DbError)
+    }
+    match db.get(&connector::object(id, connector_id, "csv")).await {
+        Ok(buffer) =>{
+            let mut csv_reader = csv::ReaderBuilder::new()
+                .from_reader(std::io::BufReader::new(&buffer[..]));
+            let result: Vec<Log> = csv_reader.deserialize::<Log>().map(|res| res.unwrap()).collect();
             println!("{:?}", result);
             Ok(result)
         },
         Err(e) => 
         {
-            return  Err(ConnectorError::RusError(e.to_string()))
+            return  Err(ConnectorError::RusError(e))
         }    
     }
     
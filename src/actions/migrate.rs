@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+
+use crate::client;
+use crate::models::{connector::{self, Connector}, history::Revision, log::Log};
+
+/// Splits a `{id}/{connector}.{extension}` key into its workspace and connector id or, for
+/// objects written before ids existed, name.
+fn split<'k>(key: &'k str, extension: &str) -> Option<(&'k str, &'k str)> {
+    let (workspace, file) = key.split_once('/')?;
+    let name = file.strip_suffix(extension)?;
+    if workspace.is_empty() || name.is_empty() || name.contains('/') {
+        return None
+    }
+    Some((workspace, name))
+}
+
+/// Copies every connector (`{id}/{connector}.yml`), delivery log (`{id}/{connector}.csv`) and
+/// connector history (`{id}/{connector id}.history`) from `source` into the SQL backend. Existing rows are overwritten so the copy can be re-run,
+/// objects that don't parse are listed in the summary instead of aborting the run.
+/// Tokens stay sealed with whichever key sealed them.
+pub async fn migrate_storage(source: &client::storage::Client, target: &client::sql::Client, dry_run: bool) -> Result<String, String> {
+    let keys = source.list("").await?;
+    let mut failed: Vec<String> = vec![];
+    // `{id}/{connector}` of every migrated connector, to its id
+    let mut migrated = HashMap::new();
+    let (mut connectors, mut logs, mut revisions) = (0, 0, 0);
+
+    // `.healthcheck/` probes and the `.upgraded-keys` marker aren't workspace objects
+    for key in keys.iter().filter(|key| !key.ends_with('/') && !key.starts_with('.')) {
+        if key.ends_with(".csv") || key.ends_with(".history") {
+            continue
+        }
+        let (workspace, name) = match split(key, ".yml") {
+            Some(parts) => parts,
+            None => {
+                failed.push(format!("{}: not a connector or delivery log", key));
+                continue
+            }
+        };
+        let mut con = match serde_yaml::from_slice::<Connector>(&source.get(key).await?) {
+            Ok(con) => con,
+            Err(e) => {
+                failed.push(format!("{}: {}", key, e));
+                continue
+            }
+        };
+        // The key decides the id, connectors from before ids existed are keyed by name
+        if connector::is_id(name) {
+            con.id = name.to_string();
+        } else {
+            con.id = connector::legacy_id(workspace, name);
+            con.name = name.to_string();
+        }
+
+        if !dry_run {
+            let stored = match target.find_connector(workspace, &con.id).await? {
+                Some(existing) if existing.id == con.id => target.replace_connector(workspace, &con, existing.version).await.map(|_| ()),
+                _ => target.insert_connector(workspace, &con).await
+            };
+            if let Err(e) = stored {
+                failed.push(format!("{}: {}", key, e));
+                continue
+            }
+        }
+        migrated.insert(key.trim_end_matches(".yml").to_string(), con.id);
+        connectors += 1;
+    }
+
+    for key in keys.iter().filter(|key| key.ends_with(".csv")) {
+        let (workspace, _) = match split(key, ".csv") {
+            Some(parts) => parts,
+            None => {
+                failed.push(format!("{}: not a delivery log", key));
+                continue
+            }
+        };
+        let connector_id = match migrated.get(key.trim_end_matches(".csv")) {
+            Some(connector_id) => connector_id,
+            None => {
+                failed.push(format!("{}: delivery log without a migrated connector", key));
+                continue
+            }
+        };
+        let data = source.get(key).await?;
+        let records: Result<Vec<Log>, csv::Error> = csv::Reader::from_reader(&data[..]).deserialize().collect();
+        match records {
+            Ok(records) => {
+                logs += records.len();
+                if !dry_run {
+                    target.replace_logs(workspace, connector_id, records).await?;
+                }
+            },
+            Err(e) => failed.push(format!("{}: {}", key, e))
+        }
+    }
+
+    // History outlives its connector, so it is keyed by id whether or not the connector was migrated
+    for key in keys.iter().filter(|key| key.ends_with(".history")) {
+        let (workspace, connector_id) = match split(key, ".history") {
+            Some((workspace, connector_id)) if connector::is_id(connector_id) => (workspace, connector_id),
+            _ => {
+                failed.push(format!("{}: not a connector history", key));
+                continue
+            }
+        };
+        match serde_yaml::from_slice::<Vec<Revision>>(&source.get(key).await?) {
+            Ok(history) => {
+                revisions += history.len();
+                if !dry_run {
+                    target.replace_history(workspace, connector_id, history).await?;
+                }
+            },
+            Err(e) => failed.push(format!("{}: {}", key, e))
+        }
+    }
+
+    let mut summary = format!("{} {} connector(s), {} delivery log entr(ies) and {} history revision(s)",
+        if dry_run { "Would migrate" } else { "Migrated" }, connectors, logs, revisions);
+    if !failed.is_empty() {
+        summary.push_str(&format!(", {} object(s) could not be migrated:\n{}", failed.len(), failed.join("\n")));
+    }
+    Ok(summary)
+}
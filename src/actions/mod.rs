@@ -1,3 +1,8 @@
 pub mod connector;
 pub mod log;
-pub mod event;
\ No newline at end of file
+pub mod event;
+pub mod secret;
+pub mod migrate;
+pub mod routing;
+pub mod bench;
+pub mod history;
\ No newline at end of file
@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::time::{Duration, Instant};
+
+use lru_cache::LruCache;
+
+use crate::config;
+use crate::models::connector::Connector;
+
+/// Workspace id, Jira project id, webhook event and Jira site of an incoming event.
+type Route = (String, String, String, String);
+
+struct Entry {
+    connectors: Vec<Connector>,
+    loaded: Instant
+}
+
+struct Index {
+    routes: LruCache<Route, Entry>,
+    /// Bumped by every connector change, a load that started before a change is not stored.
+    generation: u64
+}
+
+static INDEX: OnceLock<Mutex<Index>> = OnceLock::new();
+
+fn index() -> Option<MutexGuard<'static, Index>> {
+    let size = config::get().route_cache_size;
+    if size == 0 {
+        return None
+    }
+    let index = INDEX.get_or_init(|| Mutex::new(Index { routes: LruCache::new(size), generation: 0 }));
+    Some(index.lock().unwrap_or_else(|poisoned| poisoned.into_inner()))
+}
+
+fn route(workspace: &str, project_id: &str, event: &str, site: &str) -> Route {
+    (workspace.to_string(), project_id.to_string(), event.to_string(), site.to_string())
+}
+
+/// Routes an active connector receives events on, projects without a site use the workspace's default site.
+fn routes(workspace: &str, con: &Connector) -> Vec<Route> {
+    if !con.active {
+        return vec![]
+    }
+    con.project.iter()
+        .flat_map(|project| con.event.iter().map(move |event| route(workspace, &project.id, event, project.site.as_deref().unwrap_or(workspace))))
+        .collect()
+}
+
+/// The active connectors routed to the event, `None` when it isn't indexed yet or has expired.
+pub fn lookup(workspace: &str, project_id: &str, event: &str, site: &str) -> Option<Vec<Connector>> {
+    let mut index = index()?;
+    let key = route(workspace, project_id, event, site);
+    let ttl = Duration::from_secs(config::get().route_cache_ttl);
+    let expired = index.routes.get_mut(&key)?.loaded.elapsed() > ttl;
+    if expired {
+        index.routes.remove(&key);
+        return None
+    }
+    index.routes.get_mut(&key).map(|entry| entry.connectors.clone())
+}
+
+/// Taken before loading from storage and handed back to `store`/`store_workspace`.
+pub fn generation() -> u64 {
+    index().map(|index| index.generation).unwrap_or_default()
+}
+
+/// Indexes the connectors a routed query returned for one event.
+pub fn store(generation: u64, workspace: &str, project_id: &str, event: &str, site: &str, connectors: Vec<Connector>) {
+    if let Some(mut index) = index() {
+        if index.generation == generation {
+            index.routes.insert(route(workspace, project_id, event, site), Entry { connectors, loaded: Instant::now() });
+        }
+    }
+}
+
+/// Indexes every route of a workspace from its full connector list and returns the
+/// connectors routed to the requested event, which is indexed even when nothing matches.
+pub fn store_workspace(generation: u64, workspace: &str, project_id: &str, event: &str, site: &str, connectors: Vec<Connector>) -> Vec<Connector> {
+    let requested = route(workspace, project_id, event, site);
+    let mut entries: HashMap<Route, Vec<Connector>> = HashMap::new();
+    for con in &connectors {
+        for key in routes(workspace, con) {
+            entries.entry(key).or_default().push(con.clone());
+        }
+    }
+    let routed = entries.remove(&requested).unwrap_or_default();
+
+    if let Some(mut index) = index() {
+        if index.generation == generation {
+            let loaded = Instant::now();
+            for (key, connectors) in entries {
+                index.routes.insert(key, Entry { connectors, loaded });
+            }
+            // Inserted last so it is the one that survives a full cache
+            index.routes.insert(requested, Entry { connectors: routed.clone(), loaded });
+        }
+    }
+    routed
+}
+
+/// Applies a change of connector `id` to the indexed routes of its workspace, `current`
+/// is what it is now (`None` once deleted). Routes that aren't indexed are left to be
+/// loaded on the next event.
+pub fn update(workspace: &str, id: &str, current: Option<&Connector>) {
+    let mut index = match index() {
+        Some(index) => index,
+        None => return
+    };
+    index.generation += 1;
+    let added = current.map(|con| routes(workspace, con)).unwrap_or_default();
+    for (key, entry) in index.routes.iter_mut().filter(|(key, _)| key.0 == workspace) {
+        entry.connectors.retain(|con| con.id != id);
+        if let Some(con) = current.filter(|_| added.contains(key)) {
+            entry.connectors.push(con.clone());
+        }
+    }
+}
+
+/// Drops every indexed route of a deleted workspace.
+pub fn forget(workspace: &str) {
+    if let Some(mut index) = index() {
+        index.generation += 1;
+        let keys: Vec<Route> = index.routes.iter().filter(|(key, _)| key.0 == workspace).map(|(key, _)| key.clone()).collect();
+        for key in keys {
+            index.routes.remove(&key);
+        }
+    }
+}
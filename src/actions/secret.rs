@@ -0,0 +1,148 @@
+use futures::TryStreamExt;
+use mongodb::bson::doc;
+
+use crate::{client, config, crypto};
+use crate::models::{bot::Bot, connector::Connector, site::JiraSite, user::User, workspace::Workspace};
+
+fn reseal(value: &str) -> Result<String, String> {
+    let plain = crypto::decrypt(value).map_err(|e| e.to_string())?;
+    crypto::encrypt(&plain).map_err(|e| e.to_string())
+}
+
+pub async fn rotate_keys(db: &client::storage::Client, mongodb: &mongodb::Client) -> Result<String, String> {
+    let keyring = crypto::keyring();
+    let (mut users, mut bots, mut connectors) = (0, 0, 0);
+
+    let collection = mongodb.database(&config::get().database).collection::<User>("users");
+    let list: Vec<User> = collection
+        .find(doc! { "jira_api_key": { "$ne": null } }, None)
+        .await.map_err(|e| e.to_string())?
+        .try_collect().await.map_err(|e| e.to_string())?;
+    for user in list {
+        let key = user.jira_api_key.unwrap_or_default();
+        if keyring.needs_rotation(&key) {
+            collection.update_one(doc! { "_id": user.id }, doc! { "$set": { "jira_api_key": reseal(&key)? } }, None)
+                .await.map_err(|e| e.to_string())?;
+            users += 1;
+        }
+    }
+
+    let list: Vec<User> = collection
+        .find(doc! { "totp_secret": { "$ne": null } }, None)
+        .await.map_err(|e| e.to_string())?
+        .try_collect().await.map_err(|e| e.to_string())?;
+    for user in list {
+        let sealed = user.totp_secret.unwrap_or_default();
+        if keyring.needs_rotation(&sealed) {
+            collection.update_one(doc! { "_id": user.id }, doc! { "$set": { "totp_secret": reseal(&sealed)? } }, None)
+                .await.map_err(|e| e.to_string())?;
+            users += 1;
+        }
+    }
+
+    if config::get().user_store == config::UserStore::Sql {
+        let sql = client::sql::Client::new();
+        for user in sql.users().await? {
+            let mut fields = doc! {};
+            if let Some(key) = user.jira_api_key.filter(|key| keyring.needs_rotation(key)) {
+                fields.insert("jira_api_key", reseal(&key)?);
+            }
+            if let Some(sealed) = user.totp_secret.filter(|sealed| keyring.needs_rotation(sealed)) {
+                fields.insert("totp_secret", reseal(&sealed)?);
+            }
+            if !fields.is_empty() {
+                users += fields.len();
+                sql.set_user(user.id, fields).await?;
+            }
+        }
+    }
+
+    let collection = mongodb.database(&config::get().database).collection::<Workspace>("workspaces");
+    let list: Vec<Workspace> = collection
+        .find(doc! { "jira_api_key": { "$ne": null } }, None)
+        .await.map_err(|e| e.to_string())?
+        .try_collect().await.map_err(|e| e.to_string())?;
+    for workspace in list {
+        let key = workspace.jira_api_key.unwrap_or_default();
+        if keyring.needs_rotation(&key) {
+            collection.update_one(doc! { "_id": workspace.id }, doc! { "$set": { "jira_api_key": reseal(&key)? } }, None)
+                .await.map_err(|e| e.to_string())?;
+            users += 1;
+        }
+    }
+
+    let list: Vec<Workspace> = collection
+        .find(doc! { "jira_oauth": { "$ne": null } }, None)
+        .await.map_err(|e| e.to_string())?
+        .try_collect().await.map_err(|e| e.to_string())?;
+    for workspace in list {
+        let mut oauth = workspace.jira_oauth.unwrap();
+        if keyring.needs_rotation(&oauth.access_token) || keyring.needs_rotation(&oauth.refresh_token) {
+            oauth.access_token = reseal(&oauth.access_token)?;
+            oauth.refresh_token = reseal(&oauth.refresh_token)?;
+            let oauth = mongodb::bson::to_bson(&oauth).map_err(|e| e.to_string())?;
+            collection.update_one(doc! { "_id": workspace.id }, doc! { "$set": { "jira_oauth": oauth } }, None)
+                .await.map_err(|e| e.to_string())?;
+            users += 1;
+        }
+    }
+
+    let collection = mongodb.database(&config::get().database).collection::<JiraSite>("jira_sites");
+    let list: Vec<JiraSite> = collection
+        .find(None, None)
+        .await.map_err(|e| e.to_string())?
+        .try_collect().await.map_err(|e| e.to_string())?;
+    for site in list {
+        let key = site.jira_api_key.clone().unwrap_or_default();
+        if keyring.needs_rotation(&key) {
+            collection.update_one(doc! { "_id": site.id }, doc! { "$set": { "jira_api_key": reseal(&key)? } }, None)
+                .await.map_err(|e| e.to_string())?;
+            users += 1;
+        }
+        if let Some(oauth) = site.jira_oauth {
+            if keyring.needs_rotation(&oauth.access_token) || keyring.needs_rotation(&oauth.refresh_token) {
+                collection.update_one(doc! { "_id": site.id }, doc! { "$set": {
+                    "jira_oauth.access_token": reseal(&oauth.access_token)?,
+                    "jira_oauth.refresh_token": reseal(&oauth.refresh_token)?
+                } }, None).await.map_err(|e| e.to_string())?;
+                users += 1;
+            }
+        }
+    }
+
+    let collection = mongodb.database(&config::get().database).collection::<Bot>("bots");
+    let list: Vec<Bot> = collection
+        .find(None, None)
+        .await.map_err(|e| e.to_string())?
+        .try_collect().await.map_err(|e| e.to_string())?;
+    for bot in list {
+        if keyring.needs_rotation(&bot.token) {
+            collection.update_one(doc! { "_id": bot.id }, doc! { "$set": { "token": reseal(&bot.token)? } }, None)
+                .await.map_err(|e| e.to_string())?;
+            bots += 1;
+        }
+    }
+
+    if let client::storage::Client::Sql(sql) = db {
+        for (workspace, connector, token) in sql.connector_tokens().await? {
+            if keyring.needs_rotation(&token) {
+                sql.set_connector_token(&workspace, &connector, &reseal(&token)?).await?;
+                connectors += 1;
+            }
+        }
+    } else {
+        for key in db.list("").await?.into_iter().filter(|key| key.ends_with(".yml")) {
+            let data = db.get(&key).await?;
+            let mut con = serde_yaml::from_slice::<Connector>(&data).map_err(|e| format!("{}: {}", key, e))?;
+
+            if keyring.needs_rotation(&con.token) {
+                con.token = reseal(&con.token)?;
+                db.put(&key, serde_yaml::to_string(&con).unwrap().into_bytes()).await?;
+                connectors += 1;
+            }
+        }
+    }
+
+    Ok(format!("Re-encrypted {} user secret(s), {} bot token(s) and {} connector token(s) with key {}",
+        users, bots, connectors, keyring.active))
+}
@@ -1,15 +1,101 @@
 use std::str::FromStr;
 
+use chrono::{Duration, Utc};
+use jsonwebtoken::{encode, Header, EncodingKey};
 use mongodb::bson::doc;
 use mongodb::bson::oid::ObjectId;
-use mongodb::options::UpdateOptions;
-use serde_json::Value;
+use futures::TryStreamExt;
+use mongodb::options::{ReplaceOptions, UpdateOptions};
+use serde_json::{json, Value};
 
-use crate::models::jira::{Project, ProjectList, Webhook, Filter};
+use crate::models::jira::{Project, ProjectList, Webhook, Filter, OAuthToken, AccessibleResource, DynamicWebhook, DynamicWebhookInput};
+use crate::{config, crypto};
 use crate::errortype::JiraError;
-use crate::models::user::User;
+use crate::middleware::OAuthState;
+use crate::models::{site::JiraSite, workspace::{Workspace, JiraFlavor, JiraOAuth}};
 use crate::routes::jira::WebhookInput;
 
+const AUTHORIZE_URL: &'static str = "https://auth.atlassian.com/authorize";
+const TOKEN_URL: &'static str = "https://auth.atlassian.com/oauth/token";
+const RESOURCES_URL: &'static str = "https://api.atlassian.com/oauth/token/accessible-resources";
+const OAUTH_SCOPES: &'static str = "read:jira-work read:jira-user manage:jira-webhook offline_access";
+
+/// How a request to Jira is authenticated.
+#[derive(Debug, Clone)]
+pub enum Credential {
+    Basic { email: String, api_key: String },
+    Bearer(String),
+}
+
+/// A Jira site ready to be called: the REST base url and a valid credential.
+#[derive(Debug, Clone)]
+pub struct Connection {
+    pub base: String,
+    pub credential: Credential,
+    pub flavor: JiraFlavor,
+}
+
+impl Connection {
+    /// Email + API token for Cloud, a personal access token for Server and Data Center.
+    pub fn new(jira_url: &str, email: Option<&str>, api_key: &str, flavor: JiraFlavor) -> Result<Self, JiraError> {
+        let credential = match (flavor, email) {
+            (JiraFlavor::Server, _) => Credential::Bearer(api_key.to_string()),
+            (JiraFlavor::Cloud, Some(email)) if !email.is_empty() => Credential::Basic { email: email.to_string(), api_key: api_key.to_string() },
+            (JiraFlavor::Cloud, _) => return Err(JiraError::NoCredentials)
+        };
+        Ok(Self { base: site_url(jira_url), credential, flavor })
+    }
+
+    pub fn api(&self, path: &str) -> String {
+        format!("/rest/api/{}{}", self.flavor.api_version(), path)
+    }
+
+    pub fn authorize(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match &self.credential {
+            Credential::Basic { email, api_key } => request.basic_auth(email, Some(api_key)),
+            Credential::Bearer(token) => request.bearer_auth(token),
+        }
+    }
+
+    pub fn request(&self, client: &reqwest::Client, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        self.authorize(client.request(method, format!("{}{}", self.base, path)))
+    }
+}
+
+struct OAuthConfig {
+    client_id: String,
+    client_secret: String,
+    redirect_uri: String,
+}
+
+fn oauth_config() -> Result<OAuthConfig, JiraError> {
+    let redirect_uri = std::env::var("JIRA_OAUTH_REDIRECT_URI")
+        .unwrap_or_else(|_| format!("{}/jira/oauth/callback", config::get().public_url));
+    match (std::env::var("JIRA_OAUTH_CLIENT_ID"), std::env::var("JIRA_OAUTH_CLIENT_SECRET")) {
+        (Ok(client_id), Ok(client_secret)) => Ok(OAuthConfig { client_id, client_secret, redirect_uri }),
+        _ => Err(JiraError::OAuthNotConfigured)
+    }
+}
+
+/// `jira_url` may carry a scheme and a context path for on-prem installs, e.g. `http://intranet/jira`.
+fn site_url(jira_url: &str) -> String {
+    let jira_url = jira_url.trim_end_matches('/');
+    if jira_url.starts_with("http://") || jira_url.starts_with("https://") {
+        jira_url.to_string()
+    } else {
+        format!("https://{}", jira_url)
+    }
+}
+
+fn webhook_events() -> Vec<String> {
+    vec!["jira:issue_created".to_string(),"jira:issue_updated".to_string(),"jira:issue_deleted".to_string(),
+        "comment_created".to_string(),"comment_updated".to_string(),"comment_deleted".to_string()]
+}
+
+fn event_url(site: &JiraSite) -> String {
+    format!("{}/event/{}", config::get().public_url, site.id.to_hex())
+}
+
 #[derive(Clone)]
 pub struct Client {
     pub reqwest: reqwest::Client
@@ -22,13 +108,248 @@ impl Client{
         }
     }
 
-    pub async fn get_projects(&self, user: User) -> Result<Vec<Project>, JiraError>{
-        match self.reqwest
-            .get(format!("https://{}/rest/api/3/project", user.jira_url.unwrap()))
-            .basic_auth(user.jira_email.unwrap(), Some(user.jira_api_key.unwrap()))
+    /// Resolves the credential for a site, refreshing an expired OAuth access token on the way.
+    pub async fn connect(&self, mongodb: &mongodb::Client, site: &JiraSite) -> Result<Connection, JiraError> {
+        if let Some(oauth) = &site.jira_oauth {
+            let token = if oauth.expires_at > Utc::now() + Duration::seconds(60) {
+                oauth.access_token.clone()
+            } else {
+                self.refresh_oauth(mongodb, site, oauth).await?
+            };
+            return Ok(Connection {
+                base: format!("https://api.atlassian.com/ex/jira/{}", oauth.cloud_id),
+                credential: Credential::Bearer(token),
+                flavor: JiraFlavor::Cloud
+            })
+        }
+
+        match (&site.jira_url, &site.jira_api_key) {
+            (Some(url), Some(api_key)) => Connection::new(url, site.jira_email.as_deref(), api_key, site.jira_flavor),
+            _ => Err(JiraError::NoCredentials)
+        }
+    }
+
+    async fn refresh_oauth(&self, mongodb: &mongodb::Client, site: &JiraSite, oauth: &JiraOAuth) -> Result<String, JiraError> {
+        let config = oauth_config()?;
+        let res = match self.reqwest
+            .post(TOKEN_URL)
+            .json(&json!({
+                "grant_type": "refresh_token",
+                "client_id": config.client_id,
+                "client_secret": config.client_secret,
+                "refresh_token": oauth.refresh_token
+            }))
+            .send()
+            .await {
+                Ok(res) => res,
+                Err(e) => return Err(JiraError::ErrorMessage(e.to_string()))
+            };
+        if !res.status().is_success() {
+            return Err(JiraError::OAuthExpired)
+        }
+        let token: OAuthToken = match res.json().await {
+            Ok(token) => token,
+            Err(e) => return Err(JiraError::ErrorMessage(e.to_string()))
+        };
+
+        // Atlassian rotates refresh tokens, the old one stops working once the new one is used,
+        // so every site authorized by the same grant has to move to the new pair together
+        let refresh_token = token.refresh_token.unwrap_or_else(|| oauth.refresh_token.clone());
+        let expires_at = (Utc::now() + Duration::seconds(token.expires_in)).with_timezone(&chrono::FixedOffset::east_opt(7 * 3600).unwrap());
+        let filter = if oauth.grant.is_empty() {
+            doc! { "_id": site.id }
+        } else {
+            doc! { "workspace": site.workspace, "jira_oauth.grant": &oauth.grant }
+        };
+        match mongodb
+            .database(&config::get().database)
+            .collection::<JiraSite>("jira_sites")
+            .update_many(filter,
+                doc! {
+                    "$set": {
+                        "jira_oauth.access_token": crypto::encrypt(&token.access_token).map_err(|e| JiraError::ErrorMessage(e.to_string()))?,
+                        "jira_oauth.refresh_token": crypto::encrypt(&refresh_token).map_err(|e| JiraError::ErrorMessage(e.to_string()))?,
+                        "jira_oauth.expires_at": mongodb::bson::to_bson(&expires_at).map_err(|e| JiraError::ErrorMessage(e.to_string()))?
+                    }
+                }, None)
+            .await {
+                Ok(_) => Ok(token.access_token),
+                Err(e) => Err(JiraError::ErrorMessage(e.to_string()))
+            }
+    }
+
+    /// Writes the whole site, sealing its credentials first.
+    async fn save_site(&self, mongodb: &mongodb::Client, site: &JiraSite) -> Result<(), JiraError> {
+        let mut sealed = site.clone();
+        if let Some(api_key) = &site.jira_api_key {
+            sealed.jira_api_key = Some(crypto::encrypt(api_key).map_err(|e| JiraError::ErrorMessage(e.to_string()))?);
+        }
+        if let Some(oauth) = sealed.jira_oauth.as_mut() {
+            oauth.access_token = crypto::encrypt(&oauth.access_token).map_err(|e| JiraError::ErrorMessage(e.to_string()))?;
+            oauth.refresh_token = crypto::encrypt(&oauth.refresh_token).map_err(|e| JiraError::ErrorMessage(e.to_string()))?;
+        }
+        match mongodb
+            .database(&config::get().database)
+            .collection::<JiraSite>("jira_sites")
+            .replace_one(doc! { "_id": site.id }, &sealed, ReplaceOptions::builder().upsert(true).build())
+            .await {
+                Ok(_) => Ok(()),
+                Err(e) => Err(JiraError::ErrorMessage(e.to_string()))
+            }
+    }
+
+    /// Url of the Atlassian consent screen, `state` carries the workspace, user and browser nonce signed with the JWT secret.
+    pub fn authorize_url(&self, workspace: ObjectId, user: ObjectId, nonce: &str, secret: &str) -> Result<String, JiraError> {
+        let config = oauth_config()?;
+        let claims = OAuthState {
+            workspace,
+            user,
+            nonce: nonce.to_string(),
+            purpose: "jira_oauth".to_string(),
+            exp: (Utc::now() + Duration::minutes(10)).timestamp() as usize
+        };
+        let state = match encode(&Header::default(), &claims, &EncodingKey::from_secret(secret.as_ref())) {
+            Ok(state) => state,
+            Err(e) => return Err(JiraError::ErrorMessage(e.to_string()))
+        };
+        match reqwest::Url::parse_with_params(AUTHORIZE_URL, &[
+            ("audience", "api.atlassian.com"),
+            ("client_id", config.client_id.as_str()),
+            ("scope", OAUTH_SCOPES),
+            ("redirect_uri", config.redirect_uri.as_str()),
+            ("state", state.as_str()),
+            ("response_type", "code"),
+            ("prompt", "consent"),
+        ]) {
+            Ok(url) => Ok(url.to_string()),
+            Err(e) => Err(JiraError::ErrorMessage(e.to_string()))
+        }
+    }
+
+    /// Exchanges the authorization code and connects every Jira site the account can reach,
+    /// registering a webhook for sites that don't have one yet.
+    pub async fn complete_oauth(&self, mongodb: &mongodb::Client, workspace: &Workspace, code: &str) -> Result<String, JiraError> {
+        let config = oauth_config()?;
+        let res = match self.reqwest
+            .post(TOKEN_URL)
+            .json(&json!({
+                "grant_type": "authorization_code",
+                "client_id": config.client_id,
+                "client_secret": config.client_secret,
+                "code": code,
+                "redirect_uri": config.redirect_uri
+            }))
+            .send()
+            .await {
+                Ok(res) => res,
+                Err(e) => return Err(JiraError::ErrorMessage(e.to_string()))
+            };
+        if !res.status().is_success() {
+            return Err(JiraError::ErrorMessage("Authorization code is invalid or has expired".to_string()))
+        }
+        let token: OAuthToken = match res.json().await {
+            Ok(token) => token,
+            Err(e) => return Err(JiraError::ErrorMessage(e.to_string()))
+        };
+        let refresh_token = match token.refresh_token {
+            Some(refresh_token) => refresh_token,
+            None => return Err(JiraError::ErrorMessage("Atlassian did not return a refresh token, make sure the app requests the offline_access scope".to_string()))
+        };
+
+        let resources: Vec<AccessibleResource> = match self.reqwest
+            .get(RESOURCES_URL)
+            .bearer_auth(&token.access_token)
+            .send()
+            .await {
+                Ok(res) => match res.json().await {
+                    Ok(resources) => resources,
+                    Err(e) => return Err(JiraError::ErrorMessage(e.to_string()))
+                },
+                Err(e) => return Err(JiraError::ErrorMessage(e.to_string()))
+            };
+        if resources.is_empty() {
+            return Err(JiraError::ErrorMessage("The authorized account has no accessible Jira site".to_string()))
+        }
+
+        let mut existing: Vec<JiraSite> = match mongodb
+            .database(&config::get().database)
+            .collection::<JiraSite>("jira_sites")
+            .find(doc! { "workspace": workspace.id }, None)
+            .await {
+                Ok(cursor) => cursor.try_collect().await.unwrap_or_else(|_| vec![]),
+                Err(e) => return Err(JiraError::ErrorMessage(e.to_string()))
+            };
+
+        let now = chrono::Utc::now().with_timezone(&chrono::FixedOffset::east_opt(7 * 3600).unwrap());
+        let expires_at = (Utc::now() + Duration::seconds(token.expires_in)).with_timezone(&chrono::FixedOffset::east_opt(7 * 3600).unwrap());
+        let grant = ObjectId::new().to_hex();
+        let mut messages = vec![];
+        for resource in resources {
+            let oauth = JiraOAuth {
+                access_token: token.access_token.clone(),
+                refresh_token: refresh_token.clone(),
+                expires_at,
+                cloud_id: resource.id.clone(),
+                site_url: resource.url.clone(),
+                scope: token.scope.clone(),
+                grant: grant.clone()
+            };
+            let site = match existing.iter().find(|site| site.jira_oauth.as_ref().is_some_and(|o| o.cloud_id == resource.id)) {
+                Some(site) => JiraSite { jira_oauth: Some(oauth), ..site.clone() },
+                None => JiraSite {
+                    id: ObjectId::new(),
+                    workspace: workspace.id,
+                    name: resource.name.clone(),
+                    created_at: now,
+                    jira_email: None,
+                    jira_api_key: None,
+                    jira_url: Some(resource.url.trim_start_matches("https://").trim_end_matches('/').to_string()),
+                    jira_flavor: JiraFlavor::Cloud,
+                    jira_oauth: Some(oauth),
+                    webhook_url: None,
+                    webhook_id: None,
+                    webhook_functional: None,
+                    webhook_last_check: None
+                }
+            };
+            self.save_site(mongodb, &site).await?;
+
+            if site.webhook_id.is_none() {
+                let connection = self.connect(mongodb, &site).await?;
+                match self.create_dynamic_webhook(mongodb, &site, &connection).await {
+                    Ok(_) => messages.push(format!("Jira site {} successfully connected", resource.name)),
+                    Err(e) => messages.push(format!("Jira site {} connected but its webhook could not be created: {}", resource.name, e))
+                }
+            } else {
+                messages.push(format!("Jira site {} successfully reconnected", resource.name));
+            }
+            existing.retain(|other| other.id != site.id);
+            existing.push(site);
+        }
+        Ok(messages.join(", "))
+    }
+
+    pub async fn get_projects(&self, mongodb: &mongodb::Client, site: JiraSite) -> Result<Vec<Project>, JiraError>{
+        let connection = self.connect(mongodb, &site).await?;
+        match connection
+            .request(&self.reqwest, reqwest::Method::GET, &connection.api("/project"))
             .send()
             .await {
             Ok(sip) => {
+                if sip.status() == 401 && site.jira_oauth.is_some() {
+                    return Err(JiraError::OAuthExpired)
+                }
+                if connection.flavor == JiraFlavor::Server {
+                    // Server answers a bad token with a plain 401 and may legitimately have no projects
+                    if sip.status() == 401 || sip.status() == 403 {
+                        return Err(JiraError::ApiKeyError)
+                    }
+                    let list: Vec<ProjectList> = match sip.json().await {
+                        Ok(list) => list,
+                        Err(e) => return Err(JiraError::ErrorMessage(e.to_string()))
+                    };
+                    return Ok(list.into_iter().map(|po| Project { id: po.id, name: po.name }).collect())
+                }
                 let text = sip.text().await.unwrap();
                 if text.contains("Basic authentication with passwords is deprecated") {
                     return Err(JiraError::ApiKeyError)
@@ -45,13 +366,17 @@ impl Client{
         }
     }
     
-    pub async fn create_webhook(&self, mongodb: &mongodb::Client,  webhook: &WebhookInput, user: User) -> Result<String, JiraError>{
-        let source_url = format!("https://{}/rest/webhooks/1.0/webhook", webhook.jira_url);
+    pub async fn create_webhook(&self, mongodb: &mongodb::Client,  webhook: &WebhookInput, site: JiraSite) -> Result<String, JiraError>{
+        if site.jira_oauth.is_some() {
+            let connection = self.connect(mongodb, &site).await?;
+            return self.create_dynamic_webhook(mongodb, &site, &connection).await.map(|_| "Webhook successfully created".to_string())
+        }
+
+        let connection = Connection::new(&webhook.jira_url, Some(&webhook.email), &webhook.api_key, webhook.flavor)?;
         let payload = Webhook {
-            name: format!("User {}'s webhook", user.id.to_hex()).to_string(),
-            url: format!("https://atlassian-connector-api.dev-domain.site/event/{}", user.id.to_hex()).to_string(),
-            events: vec!["jira:issue_created".to_string(),"jira:issue_updated".to_string(),"jira:issue_deleted".to_string(),
-                    "comment_created".to_string(),"comment_updated".to_string(),"comment_deleted".to_string()],
+            name: format!("Workspace {}'s webhook", site.workspace.to_hex()).to_string(),
+            url: event_url(&site),
+            events: webhook_events(),
             filters: Filter{
                 issue_related_events_section: "".to_string()
             },
@@ -59,16 +384,20 @@ impl Client{
             enabled: true
         };
         
-        match self.reqwest
-            .post(source_url)
-            .basic_auth(webhook.email.to_string(), Some(webhook.api_key.to_string()))
+        match connection
+            .request(&self.reqwest, reqwest::Method::POST, "/rest/webhooks/1.0/webhook")
             .json(&payload)
             .send()
             .await {
             Ok(text) => {
+                    let status = text.status();
                     let message=  text.text().await.unwrap();
 
-                    if message.contains("Webhook with the same URL, set of events, filters"){
+                    if connection.flavor == JiraFlavor::Server && (status == 401 || status == 403) {
+                        return Err(JiraError::ErrorMessage("Incorrect jira personal access token, or the token's user is not a Jira administrator".to_string()))
+                    } else if connection.flavor == JiraFlavor::Server && status == 404 {
+                        return Err(JiraError::ErrorMessage("Jira url not found, make sure to enter the correct path".to_string()))
+                    } else if message.contains("Webhook with the same URL, set of events, filters"){
                         return Err(JiraError::ErrorMessage("You have already created a webhook for this account".to_string()))
                     } else if message.contains("Page unavailable") {
                         return Err(JiraError::ErrorMessage("Jira url not found, make sure to enter the correct path".to_string()))
@@ -80,26 +409,17 @@ impl Client{
                         let webhook_url = val.get("self").and_then(|v| v.as_str().map(String::from))
                         .unwrap_or_else(|| "".to_string());
 
-                        match mongodb
-                            .database("telconnect")
-                            .collection::<User>("users")
-                            .update_one( doc! { "_id": ObjectId::from_str(&user.id.to_hex()).unwrap() }, 
-                                doc!{
-                                    "$set": {
-                                        "jira_email": &webhook.email,
-                                        "jira_api_key": &webhook.api_key,
-                                        "jira_url": &webhook.jira_url,
-                                        "webhook_url": webhook_url
-                                    }
-                                }, 
-                        UpdateOptions::builder()
-                                    .upsert(false) 
-                                    .build()).await{
-                                Ok(o) => {
-                                    println!("{:?}", o);
-                                    return Ok("Webhook successfully created".to_string())
-                                },
-                                Err(e) => return Err(JiraError::ErrorMessage(e.to_string()))
+                        let site = JiraSite {
+                            jira_email: if webhook.email.is_empty() { None } else { Some(webhook.email.clone()) },
+                            jira_api_key: Some(webhook.api_key.clone()),
+                            jira_url: Some(webhook.jira_url.clone()),
+                            jira_flavor: webhook.flavor,
+                            webhook_url: Some(webhook_url),
+                            ..site
+                        };
+                        match self.save_site(mongodb, &site).await {
+                                Ok(_) => return Ok("Webhook successfully created".to_string()),
+                                Err(e) => return Err(e)
                         };
                     }
             }
@@ -107,30 +427,67 @@ impl Client{
         }
     }
 
-    pub async fn delete_webhook(&self, mongodb: &mongodb::Client, user: User) -> Result<String, JiraError>{
-        match self.reqwest
-            .delete(user.webhook_url.unwrap())
-            .basic_auth(user.jira_email.unwrap().to_string(), Some(user.jira_api_key.unwrap().to_string()))
+    /// OAuth apps can't use the admin webhook API, they register dynamic webhooks that expire after 30 days.
+    async fn create_dynamic_webhook(&self, mongodb: &mongodb::Client, site: &JiraSite, connection: &Connection) -> Result<i64, JiraError> {
+        let payload = DynamicWebhookInput {
+            url: event_url(site),
+            webhooks: vec![DynamicWebhook {
+                events: webhook_events(),
+                jql_filter: "project != EMPTY".to_string()
+            }]
+        };
+
+        let val: Value = match connection
+            .request(&self.reqwest, reqwest::Method::POST, "/rest/api/3/webhook")
+            .json(&payload)
+            .send()
+            .await {
+                Ok(res) => match res.json().await {
+                    Ok(val) => val,
+                    Err(e) => return Err(JiraError::ErrorMessage(e.to_string()))
+                },
+                Err(e) => return Err(JiraError::ErrorMessage(e.to_string()))
+            };
+
+        let result = val.get("webhookRegistrationResult").and_then(|v| v.get(0));
+        let webhook_id = match result.and_then(|v| v.get("createdWebhookId")).and_then(|v| v.as_i64()) {
+            Some(id) => id,
+            None => {
+                let reason = result.and_then(|v| v.get("errors")).map(|v| v.to_string()).unwrap_or_else(|| val.to_string());
+                return Err(JiraError::ErrorMessage(format!("Jira refused to register the webhook: {}", reason)))
+            }
+        };
+
+        match mongodb
+            .database(&config::get().database)
+            .collection::<JiraSite>("jira_sites")
+            .update_one( doc! { "_id": site.id },
+                doc!{
+                    "$set": {
+                        "webhook_url": format!("{}/rest/api/3/webhook", connection.base),
+                        "webhook_id": webhook_id
+                    }
+                }, None).await{
+                Ok(_) => return Ok(webhook_id),
+                Err(e) => return Err(JiraError::ErrorMessage(e.to_string()))
+        }
+    }
+
+    pub async fn delete_webhook(&self, mongodb: &mongodb::Client, site: JiraSite) -> Result<String, JiraError>{
+        if site.jira_oauth.is_some() {
+            return self.delete_dynamic_webhook(mongodb, site).await
+        }
+
+        let connection = self.connect(mongodb, &site).await?;
+        match connection
+            .authorize(self.reqwest.delete(site.webhook_url.unwrap()))
             .send()
             .await {
         Ok(text) => {
                 match mongodb
-                    .database("telconnect")
-                    .collection::<User>("users")
-                    .update_one( doc! { "_id": ObjectId::from_str(&user.id.to_hex()).unwrap() }, 
-                        doc!{
-                            "$set": {
-                                "jira_email": None::<String>,
-                                "jira_api_key": None::<String>,
-                                "jira_url": None::<String>,
-                                "webhook_url": None::<String>,
-                                "webhook_functional": None::<bool>,
-                                "webhook_last_check": None::<String>
-                            }
-                        }, 
-                UpdateOptions::builder()
-                            .upsert(false) 
-                            .build()).await{
+                    .database(&config::get().database)
+                    .collection::<JiraSite>("jira_sites")
+                    .delete_one(doc! { "_id": site.id }, None).await{
                         Ok(_) => {
                             if text.status() == 404 {
                                 return Ok("Your webhook has been deleted by an unknown party".to_string())
@@ -146,36 +503,55 @@ impl Client{
         }
         Err (_e) => {
             let _res = mongodb
-                .database("telconnect")
-                .collection::<User>("users")
-                .update_one( doc! { "_id": ObjectId::from_str(&user.id.to_hex()).unwrap() }, 
-                    doc!{
-                        "$set": {
-                            "jira_email": None::<String>,
-                            "jira_api_key": None::<String>,
-                            "jira_url": None::<String>,
-                            "webhook_url": None::<String>,
-                            "webhook_functional": None::<bool>,
-                            "webhook_last_check": None::<String>
-                        }
-                    }, 
-            UpdateOptions::builder()
-                        .upsert(false) 
-                        .build()).await;
+                .database(&config::get().database)
+                .collection::<JiraSite>("jira_sites")
+                .delete_one(doc! { "_id": site.id }, None).await;
             return Err(JiraError::ErrorMessage("Error sending request to the url, make sure the url is from an atlassian domain".to_string()))
             }
         }
         
     }
 
-    pub async fn check_webhook(&self, mongodb: &mongodb::Client, user: User) -> Result<String, JiraError>{
-        match self.reqwest
-            .get(user.webhook_url.clone().unwrap())
-            .basic_auth(user.jira_email.clone().unwrap().to_string(), Some(user.jira_api_key.clone().unwrap().to_string()))
+    /// Removes the dynamic webhook, if any, and disconnects the OAuth site.
+    async fn delete_dynamic_webhook(&self, mongodb: &mongodb::Client, site: JiraSite) -> Result<String, JiraError> {
+        let mut message = "Jira site disconnected".to_string();
+        if let Some(webhook_id) = site.webhook_id {
+            message = match self.connect(mongodb, &site).await {
+                Ok(connection) => match connection
+                    .request(&self.reqwest, reqwest::Method::DELETE, "/rest/api/3/webhook")
+                    .json(&json!({ "webhookIds": [webhook_id] }))
+                    .send()
+                    .await {
+                        Ok(res) if res.status() == 404 => "Your webhook has been deleted by an unknown party".to_string(),
+                        Ok(res) if res.status().is_success() => "Webhook successfully deleted".to_string(),
+                        _ => "Unable to confirm deletion of webhook as your jira credentials may have been changed. Please confirm webhook deletion by yourself".to_string()
+                    },
+                Err(_e) => "Unable to confirm deletion of webhook as your jira credentials may have been changed. Please confirm webhook deletion by yourself".to_string()
+            };
+        }
+
+        match mongodb
+            .database(&config::get().database)
+            .collection::<JiraSite>("jira_sites")
+            .delete_one(doc! { "_id": site.id }, None).await{
+                Ok(_) => return Ok(message),
+                Err(e) => return Err(JiraError::ErrorMessage(e.to_string()))
+        }
+    }
+
+    pub async fn check_webhook(&self, mongodb: &mongodb::Client, site: JiraSite) -> Result<String, JiraError>{
+        if site.jira_oauth.is_some() {
+            let connection = self.connect(mongodb, &site).await?;
+            return self.check_dynamic_webhook(mongodb, site, &connection).await
+        }
+
+        let connection = self.connect(mongodb, &site).await?;
+        match connection
+            .authorize(self.reqwest.get(site.webhook_url.clone().unwrap()))
             .send()
             .await {
         Ok(res) => {
-           match self.check_response(res, mongodb, user).await{
+           match self.check_response(res, mongodb, site).await{
                 Ok(o) => return Ok(o),
                 Err(e) => return Err(e)
            }
@@ -184,12 +560,114 @@ impl Client{
         }
     }
 
-    pub async fn repair_webhook(&self, mongodb: &mongodb::Client, user: User) -> Result<String, JiraError>{
+    /// Looks the dynamic webhook up, verifies its events and extends its expiry.
+    async fn check_dynamic_webhook(&self, mongodb: &mongodb::Client, site: JiraSite, connection: &Connection) -> Result<String, JiraError> {
+        let webhook_id = site.webhook_id.unwrap_or_default();
+
+        let val: Value = match connection
+            .request(&self.reqwest, reqwest::Method::GET, "/rest/api/3/webhook")
+            .query(&[("maxResults", "100")])
+            .send()
+            .await {
+                Ok(res) if res.status() == 401 => return Err(JiraError::OAuthExpired),
+                Ok(res) => match res.json().await {
+                    Ok(val) => val,
+                    Err(e) => return Err(JiraError::ErrorMessage(e.to_string()))
+                },
+                Err(_e) => return Err(JiraError::ErrorMessage("Error sending request to the url, make sure the url is from an atlassian domain".to_string()))
+            };
+
+        let found = val.get("values")
+            .and_then(|v| v.as_array())
+            .and_then(|list| list.iter().find(|hook| hook.get("id").and_then(|id| id.as_i64()) == Some(webhook_id)).cloned());
+        let hook = match found {
+            Some(hook) => hook,
+            None => {
+                let _res = mongodb
+                    .database(&config::get().database)
+                    .collection::<JiraSite>("jira_sites")
+                    .update_one( doc! { "_id": site.id },
+                        doc!{
+                            "$set": {
+                                "webhook_url": None::<String>,
+                                "webhook_id": None::<i64>,
+                                "webhook_functional": None::<bool>,
+                                "webhook_last_check": None::<String>
+                            }
+                        }, None).await;
+                return Err(JiraError::ErrorMessage("Your webhook has been deleted by an unknown party".to_string()))
+            }
+        };
+
+        let set1: std::collections::HashSet<String> = hook.get("events")
+            .and_then(|v| v.as_array())
+            .map(|events| events.iter().filter_map(|e| e.as_str().map(String::from)).collect())
+            .unwrap_or_default();
+        let set2: std::collections::HashSet<String> = webhook_events().into_iter().collect();
+
+        let mut err = String::new();
+        if set1 != set2 {
+            err.push_str("Webhook event is not correct,");
+        }
+
+        let refreshed = connection
+            .request(&self.reqwest, reqwest::Method::PUT, "/rest/api/3/webhook/refresh")
+            .json(&json!({ "webhookIds": [webhook_id] }))
+            .send()
+            .await;
+        if !refreshed.is_ok_and(|res| res.status().is_success()) {
+            err.push_str("Webhook expiry could not be extended,");
+        }
+
+        let status = err.is_empty();
+        let now  = chrono::Utc::now()
+            .with_timezone(&chrono::FixedOffset::east_opt(7 * 3600).unwrap())
+            .format("%d %b %Y, %H:%M:%S").to_string();
+
+        match mongodb
+            .database(&config::get().database)
+            .collection::<JiraSite>("jira_sites")
+            .update_one( doc! { "_id": site.id },
+                doc!{
+                    "$set": {
+                        "webhook_functional": status,
+                        "webhook_last_check": now
+                    }
+                }, None).await{
+                Ok(_) => {
+                    if status {
+                        return Ok("Webhook status functional".to_string());
+                    } else {
+                        return Ok(err)
+                    }
+                },
+                Err(e) => return Err(JiraError::ErrorMessage(e.to_string()))
+        }
+    }
+
+    pub async fn repair_webhook(&self, mongodb: &mongodb::Client, site: JiraSite) -> Result<String, JiraError>{
+        if site.jira_oauth.is_some() {
+            // Dynamic webhooks can't be edited, replace it with a fresh registration
+            let connection = self.connect(mongodb, &site).await?;
+            if let Some(webhook_id) = site.webhook_id {
+                let _del = connection
+                    .request(&self.reqwest, reqwest::Method::DELETE, "/rest/api/3/webhook")
+                    .json(&json!({ "webhookIds": [webhook_id] }))
+                    .send()
+                    .await;
+            }
+            let webhook_id = self.create_dynamic_webhook(mongodb, &site, &connection).await?;
+            match self.check_dynamic_webhook(mongodb, JiraSite { webhook_id: Some(webhook_id), ..site }, &connection).await {
+                Ok(o) if o.contains("Webhook status functional") => return Ok("Webhook has been successfully modified, webhook status is now functional".to_string()),
+                Ok(o) => return Ok(o),
+                Err(e) => return Err(e)
+            }
+        }
+
         let fix = Webhook {
-            name: format!("User {}'s webhook", user.id.to_hex()).to_string(),
-            url: format!("https://atlassian-connector-api.dev-domain.site/event/{}", user.id.to_hex()).to_string(),
-            events: vec!["jira:issue_created".to_string(),"jira:issue_updated".to_string(),"jira:issue_deleted".to_string(),
-                    "comment_created".to_string(),"comment_updated".to_string(),"comment_deleted".to_string()],
+            name: format!("Workspace {}'s webhook", site.workspace.to_hex()).to_string(),
+            url: event_url(&site),
+            events: webhook_events(),
             filters: Filter{
                 issue_related_events_section: "".to_string()
             },
@@ -197,14 +675,14 @@ impl Client{
             enabled: true
         };
 
-        match self.reqwest
-            .put(user.webhook_url.clone().unwrap())
-            .basic_auth(user.jira_email.clone().unwrap().to_string(), Some(user.jira_api_key.clone().unwrap().to_string()))
+        let connection = self.connect(mongodb, &site).await?;
+        match connection
+            .authorize(self.reqwest.put(site.webhook_url.clone().unwrap()))
             .json(&fix)
             .send()
             .await {
                 Ok(res) => {
-                    match self.check_response(res, mongodb, user).await {
+                    match self.check_response(res, mongodb, site).await {
                         Ok(o) => {
                             if o.contains("Webhook status functional"){
                                 return Ok("Webhook has been successfully modified, webhook status is now functional".to_string())
@@ -220,25 +698,12 @@ impl Client{
         
     }
 
-    pub async fn check_response(&self, res: reqwest::Response, mongodb: &mongodb::Client, user: User) -> Result<String, JiraError> {
+    pub async fn check_response(&self, res: reqwest::Response, mongodb: &mongodb::Client, site: JiraSite) -> Result<String, JiraError> {
         if res.status() == 404 {
             match mongodb
-                .database("telconnect")
-                .collection::<User>("users")
-                .update_one( doc! { "_id": ObjectId::from_str(&user.id.to_hex()).unwrap() }, 
-                    doc!{
-                        "$set": {
-                            "jira_email": None::<String>,
-                            "jira_api_key": None::<String>,
-                            "jira_url": None::<String>,
-                            "webhook_url": None::<String>,
-                            "webhook_functional": None::<bool>,
-                            "webhook_last_check": None::<String>
-                        }
-                    }, 
-            UpdateOptions::builder()
-                        .upsert(false) 
-                        .build()).await{
+                .database(&config::get().database)
+                .collection::<JiraSite>("jira_sites")
+                .delete_one(doc! { "_id": site.id }, None).await{
                 Ok(_) => {
                     return Err(JiraError::ErrorMessage("Your webhook has been deleted by an unknown party".to_string()))
                 },
@@ -250,16 +715,9 @@ impl Client{
 
             let mut err= String::new();
 
-            let url = format!("https://atlassian-connector-api.dev-domain.site/event/{}", user.id.to_hex());
+            let url = event_url(&site);
             
-            let vector2 = vec![
-                String::from("jira:issue_created"),
-                String::from("jira:issue_updated"),
-                String::from("jira:issue_deleted"),
-                String::from("comment_created"),
-                String::from("comment_updated"),
-                String::from("comment_deleted"),
-            ];
+            let vector2 = webhook_events();
 
             let set1: std::collections::HashSet<_> = check.events.iter().cloned().collect();
             let set2: std::collections::HashSet<_> = vector2.into_iter().collect();
@@ -286,9 +744,9 @@ impl Client{
             else { status = false }   
 
             match mongodb
-                    .database("telconnect")
-                    .collection::<User>("users")
-                    .update_one( doc! { "_id": ObjectId::from_str(&user.id.to_hex()).unwrap() }, 
+                    .database(&config::get().database)
+                    .collection::<JiraSite>("jira_sites")
+                    .update_one( doc! { "_id": ObjectId::from_str(&site.id.to_hex()).unwrap() }, 
                         doc!{
                             "$set": {
                                 "webhook_functional": status,
@@ -310,4 +768,4 @@ impl Client{
         }
     }
     
-}
\ No newline at end of file
+}
@@ -0,0 +1,173 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Component, Path, PathBuf};
+
+/// Lock file of every directory, held across a whole read-check-write of the objects in it
+/// (see `lock`) so several processes can share the same data directory.
+const LOCK: &str = ".lock";
+/// Prefix of the temporary files a write goes through before being renamed into place.
+const TEMP: &str = ".tmp-";
+
+/// Stores objects as files under a data directory: `{id}/{name}.yml` lives at
+/// `{data_dir}/{id}/{name}.yml` and the `{id}/` folder marker is the directory itself.
+/// Writes land in a temporary file that is renamed over the target, so readers see
+/// either the old or the new object and never a partial one.
+#[derive(Clone)]
+pub struct Client {
+    root: PathBuf
+}
+
+async fn blocking<T: Send + 'static>(task: impl FnOnce() -> std::io::Result<T> + Send + 'static) -> Result<T, String> {
+    tokio::task::spawn_blocking(task).await.expect("Task panicked").map_err(|e| e.to_string())
+}
+
+
+fn reserved(name: &str) -> bool {
+    name == LOCK || name.starts_with(TEMP)
+}
+
+fn walk(root: &Path, dir: &Path, keys: &mut Vec<String>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let key = path.strip_prefix(root).unwrap_or(&path).components()
+            .map(|c| c.as_os_str().to_string_lossy().to_string())
+            .collect::<Vec<String>>()
+            .join("/");
+        if path.is_dir() {
+            keys.push(format!("{}/", key));
+            walk(root, &path, keys)?;
+        } else if !reserved(&name) {
+            keys.push(key);
+        }
+    }
+    Ok(())
+}
+
+impl Client {
+    pub fn new(root: &str) -> Self {
+        return Self { root: PathBuf::from(root) }
+    }
+
+    /// Keys are untrusted (connector names end up in them), so only plain path segments are accepted.
+    fn path(&self, key: &str) -> Result<PathBuf, String> {
+        let relative = Path::new(key.trim_end_matches('/'));
+        let invalid = key.contains('\\')
+            || relative.components().any(|c| !matches!(c, Component::Normal(_)))
+            || relative.file_name().is_some_and(|name| reserved(&name.to_string_lossy()));
+        if invalid {
+            return Err(format!("Invalid storage key '{}'", key))
+        }
+        Ok(self.root.join(relative))
+    }
+
+    /// Fails unless the data directory can be created, written and cleaned up.
+    pub async fn check(&self) -> Result<(), String> {
+        let key = format!(".healthcheck/{}", mongodb::bson::oid::ObjectId::new().to_hex());
+        if let Err(e) = self.put(&key, Vec::new()).await {
+            return Err(format!("Data directory {} is not writable: {}", self.root.display(), e))
+        }
+        match self.delete(&key).await {
+            Ok(_) => self.delete(".healthcheck/").await,
+            Err(e) => Err(format!("Data directory {} does not allow deletes: {}", self.root.display(), e))
+        }
+    }
+
+    pub async fn put(&self, key: &str, body: Vec<u8>) -> Result<(), String> {
+        let path = self.path(key)?;
+        if key.ends_with('/') {
+            return blocking(move || fs::create_dir_all(&path)).await
+        }
+        blocking(move || {
+            let dir = path.parent().expect("Object paths are inside the data directory");
+            fs::create_dir_all(dir)?;
+
+            let temp = dir.join(format!("{}{}", TEMP, mongodb::bson::oid::ObjectId::new().to_hex()));
+            let written = File::create(&temp).and_then(|mut file| {
+                file.write_all(&body)?;
+                file.sync_all()
+            }).and_then(|_| fs::rename(&temp, &path));
+            if written.is_err() {
+                let _ = fs::remove_file(&temp);
+            }
+            written
+        }).await
+    }
+
+    pub async fn get(&self, key: &str) -> Result<Vec<u8>, String> {
+        let path = self.path(key)?;
+        let key = key.to_string();
+        blocking(move || {
+            if !path.is_file() {
+                return Err(std::io::Error::new(ErrorKind::NotFound, format!("The specified key does not exist: {}", key)))
+            }
+            fs::read(&path)
+        }).await
+    }
+
+    /// Takes the lock of the `folder` (`{id}/`) exclusively, other processes wait for it
+    /// until the returned file is dropped. Single writes don't need it, the rename is atomic.
+    pub async fn lock(&self, folder: &str) -> Result<File, String> {
+        let dir = self.path(folder)?;
+        blocking(move || {
+            fs::create_dir_all(&dir)?;
+            let file = OpenOptions::new().create(true).truncate(false).write(true).open(dir.join(LOCK))?;
+            file.lock()?;
+            Ok(file)
+        }).await
+    }
+
+    pub async fn exists(&self, key: &str) -> bool {
+        match self.path(key) {
+            Ok(path) if key.ends_with('/') => path.is_dir(),
+            Ok(path) => path.is_file(),
+            Err(_) => false
+        }
+    }
+
+    /// Like S3, deleting a missing object succeeds. A folder marker is only removed once
+    /// everything in it has been deleted.
+    pub async fn delete(&self, key: &str) -> Result<(), String> {
+        let path = self.path(key)?;
+        let folder = key.ends_with('/');
+        blocking(move || {
+            let removed = if folder {
+                match fs::remove_file(path.join(LOCK)) {
+                    Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+                    _ => fs::remove_dir(&path)
+                }
+            } else {
+                fs::remove_file(&path)
+            };
+            match removed {
+                Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+                other => other
+            }
+        }).await
+    }
+
+    /// Every key starting with `prefix` in lexicographic order, folder markers included.
+    pub async fn list(&self, prefix: &str) -> Result<Vec<String>, String> {
+        let folder = match prefix.rfind('/') {
+            Some(end) => &prefix[..end + 1],
+            None => ""
+        };
+        let start = self.path(folder)?;
+        let root = self.root.clone();
+        let folder = folder.to_string();
+        let prefix = prefix.to_string();
+        blocking(move || {
+            let mut keys = vec![];
+            if !start.is_dir() {
+                return Ok(keys)
+            }
+            if !folder.is_empty() {
+                keys.push(folder);
+            }
+            walk(&root, &start, &mut keys)?;
+            keys.retain(|key| key.starts_with(&prefix));
+            keys.sort();
+            Ok(keys)
+        }).await
+    }
+}
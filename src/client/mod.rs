@@ -1,3 +1,7 @@
 pub mod rusoto;
+pub mod local;
+pub mod sql;
+pub mod storage;
 pub mod jira;
+pub mod telegram;
 pub mod mongodb;
\ No newline at end of file
@@ -4,179 +4,1233 @@ use chrono::{Duration, Utc};
 use futures::TryStreamExt;
 use mongodb::bson::doc;
 use bcrypt::{hash, verify};
-use rusoto_s3::{S3Client, PutObjectRequest, S3, DeleteObjectRequest, ListObjectsV2Request};
+use rand::{Rng, distributions::Alphanumeric};
+use teloxide::requests::Requester;
 
-use crate::{models::user::{UserInput, UserNew, User}, middleware::Claims};
+use crate::{client, config, models::{user::{UserInput, UserNew, User, TotpEnrollment}, bot::{BotInput, BotNew, Bot}, site::JiraSite, session::{Session, SessionNew, TokenPair, LoginResponse, MfaChallenge}, audit::Audit, apikey::{ApiKeyInput, ApiKeyNew, ApiKey, ApiKeyCreated, API_KEY_PREFIX, MAX_EXPIRY_DAYS, SCOPES}, workspace::{Workspace, JiraFlavor, Member, Role, InvitationInput, InvitationNew, Invitation}}, middleware::{Claims, MfaClaims}, actions, crypto, password, twofactor, errortype::{BotError, ConnectorError, LoginError}};
 use jsonwebtoken::{encode, Header, EncodingKey};
 
-const BUCKET: &'static str = "atlassian-connector";
-
 #[derive(Clone)]
+/// `sql` is set when `user_store` is `sql`, user accounts then live in the SQL
+/// database and everything else stays in MongoDB.
 pub struct Client {
-    pub mongodb: mongodb::Client
+    pub mongodb: mongodb::Client,
+    pub sql: Option<client::sql::Client>
 }
 
 impl Client {
     pub fn new() -> Self {
         return Self{
-            mongodb: futures::executor::block_on(mongodb::Client::with_uri_str(std::env::var("MONGODB_URI").expect("MONGODB URI must be defined"))).unwrap(),
+            mongodb: futures::executor::block_on(mongodb::Client::with_uri_str(&config::get().mongodb_uri)).unwrap(),
+            sql: match config::get().user_store {
+                config::UserStore::Sql => Some(client::sql::Client::new()),
+                config::UserStore::Mongodb => None
+            }
+        }
+    }
+
+    async fn find_user(&self, id: mongodb::bson::oid::ObjectId) -> Result<Option<User>, String> {
+        if let Some(sql) = &self.sql {
+            return sql.find_user(id).await
+        }
+        self.mongodb
+            .database(&config::get().database)
+            .collection::<User>("users")
+            .find_one(doc! { "_id": id }, None)
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    async fn find_user_by_name(&self, username: &str) -> Result<Option<User>, String> {
+        if let Some(sql) = &self.sql {
+            return sql.find_user_by_name(username).await
         }
+        self.mongodb
+            .database(&config::get().database)
+            .collection::<User>("users")
+            .find_one(doc! { "username": username }, None)
+            .await
+            .map_err(|e| e.to_string())
     }
 
-    pub async fn create_user(&self, db: &S3Client, user: UserInput) -> Result<mongodb::results::InsertOneResult, String>  {
+    /// `$set`s `fields` on the user, whichever store holds it.
+    async fn set_user(&self, id: mongodb::bson::oid::ObjectId, fields: mongodb::bson::Document) -> Result<(), String> {
+        if let Some(sql) = &self.sql {
+            return sql.set_user(id, fields).await
+        }
         match self
             .mongodb
-            .database("telconnect")
+            .database(&config::get().database)
             .collection::<User>("users")
-            .find(doc! {
-                "username": &user.username
-            }, None)
-            .await{
-                Ok(cursor) => {
-                    let doc = cursor.try_collect().await.unwrap_or_else(|_| vec![]);
-                    if !doc.is_empty(){
-                        return Err("Username already taken".to_string())
-                    }
-                },
-                Err(e) => return Err(e.to_owned().to_string())
+            .update_one(doc! { "_id": id }, doc! { "$set": fields }, None)
+            .await {
+                Ok(_) => Ok(()),
+                Err(e) => Err(e.to_string())
             }
+    }
+
+    pub async fn create_user(&self, db: &client::storage::Client, user: UserInput) -> Result<mongodb::bson::oid::ObjectId, String>  {
+        match self.find_user_by_name(&user.username).await {
+            Ok(Some(_)) => return Err("Username already taken".to_string()),
+            Ok(None) => (),
+            Err(e) => return Err(e)
+        }
         
-        match self
-            .mongodb
-            .database("telconnect")
-            .collection::<UserNew>("users")
-            .insert_one(UserNew{
-                username: user.username,
-                password: hash(user.password, 4).unwrap(),
-                
-                created_at: chrono::Utc::now()
-                    .with_timezone(&chrono::FixedOffset::east_opt(7 * 3600).unwrap()),
-                    // .format("%d/%m/%Y %H:%M").to_string(),
+        let new = UserNew{
+            username: user.username,
+            password: hash_password(user.password).await?,
+            
+            created_at: chrono::Utc::now()
+                .with_timezone(&chrono::FixedOffset::east_opt(7 * 3600).unwrap()),
+                // .format("%d/%m/%Y %H:%M").to_string(),
     
-                jira_email: None,
-                jira_api_key: None,
-                jira_url: None,
-                webhook_url: None,
-                webhook_functional: None,
-                webhook_last_check: None
-            }, None)
-            .await {
-                Ok(o) => { 
-                    match db.put_object(PutObjectRequest {
-                        bucket: BUCKET.to_owned(),
-                        key: format!("{}/", o.inserted_id.as_object_id().expect("Failed to get inserted ID").to_hex()),
-                        body: None,
-                        ..Default::default()
-                    }).await {
-                        Ok(_) => return Ok(o),
-                        Err(e) => return Err(e.to_string())
+            jira_email: None,
+            jira_api_key: None,
+            jira_url: None,
+            webhook_url: None,
+            webhook_functional: None,
+            webhook_last_check: None,
+            totp_secret: None,
+            totp_pending: None,
+            recovery_codes: None
+        };
+        let inserted = match &self.sql {
+            Some(sql) => sql.insert_user(new).await,
+            None => match self
+                .mongodb
+                .database(&config::get().database)
+                .collection::<UserNew>("users")
+                .insert_one(new, None)
+                .await {
+                    Ok(o) => Ok(o.inserted_id.as_object_id().expect("Failed to get inserted ID")),
+                    Err(e) => Err(e.to_string())
+                }
+        };
+
+        match inserted {
+            Ok(id) => { 
+                if let Ok(mut users) = self.get_one_user(id.to_hex()).await {
+                    if !users.is_empty() {
+                        self.personal_workspace(&users.remove(0)).await?;
                     }
-                },
-                Err(e) => return Err(e.to_string())
-            };
+                }
+                match db.create_folder(&id.to_hex()).await {
+                    Ok(_) => return Ok(id),
+                    Err(e) => return Err(e)
+                }
+            },
+            Err(e) => return Err(e)
+        };
     }
 
-    pub async fn login(&self, username: String, password: String, secret: &str) -> Result<String, String> {
-        match self
-            .mongodb
-            .database("telconnect")
-            .collection::<User>("users")
-            .find(doc! {
-                "username": username
-            }, None)
-            .await {
-            Ok(cursor) => {
-                let doc = cursor.try_collect().await.unwrap_or_else(|_| vec![]);
+    pub async fn login(&self, username: String, password: String, secret: &str) -> Result<LoginResponse, LoginError> {
+        match self.find_user_by_name(&username).await {
+            Ok(found) => {
+                let doc: Vec<User> = found.into_iter().collect();
                 if doc.is_empty(){
-                    return Err("User Not Found".to_string())
+                    return Err(LoginError::UserNotFound)
                 }
 
-                match verify(password, &doc[0].password){
+                match verify_password(password.clone(), doc[0].password.clone()).await {
                     Ok(ver) => {
                         if ver == true {
-                            let claims = Claims { 
-                                id: doc[0].id,
-                                exp: (Utc::now() + Duration::hours(2)).timestamp() as usize
-                            };
-                            let token: String = encode(
-                                &Header::default(),
-                                &claims,
-                                &EncodingKey::from_secret(secret.as_ref()),
-                            ).unwrap();
-                            return Ok(token)
+                            if password::stored_cost(&doc[0].password).unwrap_or(0) < password::cost() {
+                                if let Err(e) = self.set_password(doc[0].id, password).await {
+                                    println!("{:?}", e);
+                                }
+                            }
+                            if doc[0].totp_secret.is_some() {
+                                return sign_mfa_challenge(&doc[0], secret).map(LoginResponse::SecondFactor).map_err(LoginError::Other)
+                            }
+                            return self.create_session(doc[0].id, secret).await.map(LoginResponse::Tokens).map_err(LoginError::Other)
                         } else {
-                            return Err("Invalid password".to_string())
+                            return Err(LoginError::InvalidPassword)
                         }
                     },
-                    Err(e)=> return Err(e.to_string())
+                    Err(e)=> return Err(LoginError::Other(e.to_string()))
                 }
             },
-            Err(e) => return Err(e.to_owned().to_string())
+            Err(e) => return Err(LoginError::Other(e))
         }
     }
     
-    pub async fn get_one_user(&self, id: String) -> Result<Vec<User>, mongodb::error::Error> {
+    pub async fn login_second_factor(&self, claims: &MfaClaims, code: &str, secret: &str) -> Result<TokenPair, LoginError> {
+        let user = match self.get_one_user(claims.id.to_hex()).await {
+            Ok(mut users) if !users.is_empty() => users.remove(0),
+            Ok(_) => return Err(LoginError::UserNotFound),
+            Err(e) => return Err(LoginError::Other(e))
+        };
+        match self.verify_second_factor(&user, code).await {
+            Ok(true) => self.create_session(user.id, secret).await.map_err(LoginError::Other),
+            Ok(false) => Err(LoginError::InvalidCode),
+            Err(e) => Err(LoginError::Other(e))
+        }
+    }
+
+    /// Accepts either a current TOTP code or an unused recovery code, which is consumed.
+    /// A TOTP code is spent with its time step, so neither it nor an earlier code works again.
+    pub async fn verify_second_factor(&self, user: &User, code: &str) -> Result<bool, String> {
+        let sealed = match &user.totp_secret {
+            Some(sealed) => sealed,
+            None => return Ok(false)
+        };
+        let totp_secret = crypto::decrypt(sealed).map_err(|e| e.to_string())?;
+        if let Some(step) = twofactor::matched_step(&totp_secret, &user.username, code) {
+            let step = step as i64;
+            if let Some(sql) = &self.sql {
+                return sql.spend_totp_step(user.id, step).await
+            }
+            // Only succeeds if no code of this or a later step was accepted in between
+            return match self
+                .mongodb
+                .database(&config::get().database)
+                .collection::<User>("users")
+                .update_one(doc! { "_id": user.id, "totp_step": { "$not": { "$gte": step } } }, doc! { "$set": { "totp_step": step } }, None)
+                .await {
+                    Ok(o) => Ok(o.modified_count == 1),
+                    Err(e) => Err(e.to_string())
+                }
+        }
+
+        let hashed = crypto::digest(code.trim().to_lowercase().as_str());
+        if !user.recovery_codes.as_ref().is_some_and(|codes| codes.contains(&hashed)) {
+            return Ok(false)
+        }
+        if let Some(sql) = &self.sql {
+            return sql.pull_recovery_code(user.id, &hashed).await
+        }
         match self
             .mongodb
-            .database("telconnect")
+            .database(&config::get().database)
             .collection::<User>("users")
-            .find(doc! {
-                "_id": mongodb::bson::oid::ObjectId::from_str(&id).unwrap()
+            .update_one(doc! { "_id": user.id, "recovery_codes": &hashed }, doc! { "$pull": { "recovery_codes": &hashed } }, None)
+            .await {
+                Ok(o) => Ok(o.modified_count == 1),
+                Err(e) => Err(e.to_string())
+            }
+    }
+
+    pub async fn enroll_totp(&self, user: &User) -> Result<TotpEnrollment, String> {
+        if user.totp_secret.is_some() {
+            return Err("Two-factor authentication is already enabled".to_string())
+        }
+        let (secret, otpauth_url) = twofactor::generate(&user.username)?;
+        let sealed = crypto::encrypt(&secret).map_err(|e| e.to_string())?;
+
+        match self.set_user(user.id, doc! { "totp_pending": sealed }).await {
+                Ok(_) => Ok(TotpEnrollment { secret, otpauth_url }),
+                Err(e) => Err(e)
+            }
+    }
+
+    pub async fn confirm_totp(&self, user: &User, code: &str) -> Result<Vec<String>, String> {
+        let pending = match &user.totp_pending {
+            Some(pending) => crypto::decrypt(pending).map_err(|e| e.to_string())?,
+            None => return Err("Start two-factor enrollment first".to_string())
+        };
+        if !twofactor::check(&pending, &user.username, code) {
+            return Err("Invalid two-factor code".to_string())
+        }
+
+        let (codes, hashes) = twofactor::recovery_codes();
+        match self.set_user(user.id,
+                doc! {
+                    "totp_secret": user.totp_pending.as_ref(),
+                    "totp_pending": None::<String>,
+                    "recovery_codes": hashes
+                })
+            .await {
+                Ok(_) => Ok(codes),
+                Err(e) => Err(e)
+            }
+    }
+
+    pub async fn disable_totp(&self, user: &User, code: &str) -> Result<String, String> {
+        if user.totp_secret.is_none() {
+            return Err("Two-factor authentication is not enabled".to_string())
+        }
+        if !self.verify_second_factor(user, code).await? {
+            return Err("Invalid two-factor code".to_string())
+        }
+        match self.set_user(user.id,
+                doc! {
+                    "totp_secret": None::<String>,
+                    "totp_pending": None::<String>,
+                    "recovery_codes": None::<Vec<String>>
+                })
+            .await {
+                Ok(_) => Ok("Two-factor authentication disabled".to_string()),
+                Err(e) => Err(e)
+            }
+    }
+
+    pub async fn change_password(&self, user: &User, sid: mongodb::bson::oid::ObjectId, current: String, new: String) -> Result<String, String> {
+        match verify_password(current, user.password.clone()).await {
+            Ok(true) => (),
+            Ok(false) => return Err("Current password is incorrect".to_string()),
+            Err(e) => return Err(e)
+        }
+
+        self.set_password(user.id, new).await?;
+
+        match self
+            .mongodb
+            .database(&config::get().database)
+            .collection::<Session>("sessions")
+            .update_many(doc! { "user": user.id, "_id": { "$ne": sid } }, doc! { "$set": { "revoked": true } }, None)
+            .await {
+                Ok(_) => Ok("Password successfully changed, other sessions have been logged out".to_string()),
+                Err(e) => Err(e.to_string())
+            }
+    }
+
+    async fn set_password(&self, id: mongodb::bson::oid::ObjectId, password: String) -> Result<(), String> {
+        let hashed = hash_password(password).await?;
+        self.set_user(id, doc! { "password": hashed }).await
+    }
+
+    async fn create_session(&self, user: mongodb::bson::oid::ObjectId, secret: &str) -> Result<TokenPair, String> {
+        let jti = random_string(32);
+        let refresh = random_string(64);
+        let now = chrono::Utc::now()
+            .with_timezone(&chrono::FixedOffset::east_opt(7 * 3600).unwrap());
+
+        match self
+            .mongodb
+            .database(&config::get().database)
+            .collection::<SessionNew>("sessions")
+            .insert_one(SessionNew {
+                user,
+                refresh_hash: crypto::digest(&refresh),
+                previous_hash: None,
+                access_jti: jti.clone(),
+                revoked: false,
+                created_at: now,
+                expires_at: now + Duration::days(refresh_token_days())
             }, None)
             .await {
-            Ok(cursor) => {
-                let doc = cursor.try_collect().await.unwrap_or_else(|_| vec![]);
+                Ok(o) => {
+                    let sid = o.inserted_id.as_object_id().expect("Failed to get inserted ID");
+                    sign_tokens(sid, user, jti, refresh, secret)
+                },
+                Err(e) => Err(e.to_string())
+            }
+    }
+
+    pub async fn refresh_session(&self, refresh_token: &str, secret: &str) -> Result<TokenPair, String> {
+        let (sid, value) = match refresh_token.split_once('.') {
+            Some((sid, value)) => match mongodb::bson::oid::ObjectId::from_str(sid) {
+                Ok(sid) => (sid, value),
+                Err(_) => return Err("Invalid refresh token".to_string())
+            },
+            None => return Err("Invalid refresh token".to_string())
+        };
+
+        let sessions = self.mongodb.database(&config::get().database).collection::<Session>("sessions");
+        let session = match sessions.find_one(doc! { "_id": sid }, None).await {
+            Ok(Some(session)) => session,
+            Ok(None) => return Err("Invalid refresh token".to_string()),
+            Err(e) => return Err(e.to_string())
+        };
+
+        if session.revoked {
+            return Err("Session has been revoked".to_string())
+        }
+        if session.expires_at < Utc::now() {
+            return Err("Refresh token expired".to_string())
+        }
+
+        let presented = crypto::digest(value);
+        if session.previous_hash.as_ref() == Some(&presented) {
+            // An already rotated token was replayed, so the whole session is treated as stolen
+            let _ = self.revoke_session(session.id).await;
+            return Err("Refresh token already used, session revoked".to_string())
+        }
+        if session.refresh_hash != presented {
+            return Err("Invalid refresh token".to_string())
+        }
+
+        let jti = random_string(32);
+        let refresh = random_string(64);
+        let expires_at = chrono::Utc::now()
+            .with_timezone(&chrono::FixedOffset::east_opt(7 * 3600).unwrap()) + Duration::days(refresh_token_days());
+
+        match sessions
+            .update_one(doc! { "_id": sid, "refresh_hash": &presented },
+                doc! {
+                    "$set": {
+                        "refresh_hash": crypto::digest(&refresh),
+                        "previous_hash": &presented,
+                        "access_jti": &jti,
+                        "expires_at": expires_at.to_rfc3339()
+                    }
+                }, None)
+            .await {
+                Ok(o) if o.matched_count == 1 => sign_tokens(sid, session.user, jti, refresh, secret),
+                Ok(_) => Err("Invalid refresh token".to_string()),
+                Err(e) => Err(e.to_string())
+            }
+    }
+
+    pub async fn session_active(&self, sid: &mongodb::bson::oid::ObjectId, jti: &str) -> bool {
+        match self
+            .mongodb
+            .database(&config::get().database)
+            .collection::<Session>("sessions")
+            .find_one(doc! { "_id": sid, "access_jti": jti, "revoked": false }, None)
+            .await {
+                Ok(Some(session)) => session.expires_at > Utc::now(),
+                _ => false
+            }
+    }
+
+    pub async fn revoke_session(&self, sid: mongodb::bson::oid::ObjectId) -> Result<String, String> {
+        match self
+            .mongodb
+            .database(&config::get().database)
+            .collection::<Session>("sessions")
+            .update_one(doc! { "_id": sid }, doc! { "$set": { "revoked": true } }, None)
+            .await {
+                Ok(_) => Ok("Successfully logged out".to_string()),
+                Err(e) => Err(e.to_string())
+            }
+    }
+
+    pub async fn revoke_all_sessions(&self, user: mongodb::bson::oid::ObjectId) -> Result<String, String> {
+        match self
+            .mongodb
+            .database(&config::get().database)
+            .collection::<Session>("sessions")
+            .update_many(doc! { "user": user, "revoked": false }, doc! { "$set": { "revoked": true } }, None)
+            .await {
+                Ok(o) => Ok(format!("Successfully logged out of {} session(s)", o.modified_count)),
+                Err(e) => Err(e.to_string())
+            }
+    }
+
+    pub async fn write_audit(&self, event: &str, username: Option<String>, ip: Option<String>, detail: String) {
+        let res = self
+            .mongodb
+            .database(&config::get().database)
+            .collection::<Audit>("audit")
+            .insert_one(Audit {
+                event: event.to_string(),
+                username,
+                ip,
+                detail,
+                created_at: chrono::Utc::now()
+                    .with_timezone(&chrono::FixedOffset::east_opt(7 * 3600).unwrap())
+            }, None)
+            .await;
+        if let Err(e) = res {
+            println!("{:?}", e);
+        }
+    }
+
+    pub async fn create_api_key(&self, input: ApiKeyInput, owner: mongodb::bson::oid::ObjectId) -> Result<ApiKeyCreated, String> {
+        if input.name.is_empty() {
+            return Err("API key name must not be empty!".to_string())
+        }
+        if input.scopes.is_empty() {
+            return Err("API key needs at least one scope".to_string())
+        }
+        if let Some(scope) = input.scopes.iter().find(|scope| !SCOPES.contains(&scope.as_str())) {
+            return Err(format!("Unknown scope {}, expected one of: {}", scope, SCOPES.join(", ")))
+        }
+        if let Some(days) = input.expires_in_days.filter(|days| !(1..=MAX_EXPIRY_DAYS).contains(days)) {
+            return Err(format!("expires_in_days must be between 1 and {}, got {}", MAX_EXPIRY_DAYS, days))
+        }
+
+        let key = format!("{}{}", API_KEY_PREFIX, random_string(40));
+        let now = chrono::Utc::now()
+            .with_timezone(&chrono::FixedOffset::east_opt(7 * 3600).unwrap());
+        let record = ApiKeyNew {
+            owner,
+            name: input.name,
+            prefix: key[..API_KEY_PREFIX.len() + 6].to_string(),
+            hash: crypto::digest(&key),
+            scopes: input.scopes,
+            created_at: now,
+            expires_at: input.expires_in_days.map(|days| now + Duration::days(days)),
+            last_used_at: None
+        };
+
+        match self
+            .mongodb
+            .database(&config::get().database)
+            .collection::<ApiKeyNew>("api_keys")
+            .insert_one(&record, None)
+            .await {
+                Ok(o) => Ok(ApiKeyCreated {
+                    key,
+                    info: ApiKey {
+                        id: o.inserted_id.as_object_id().expect("Failed to get inserted ID"),
+                        owner: record.owner,
+                        name: record.name,
+                        prefix: record.prefix,
+                        hash: record.hash,
+                        scopes: record.scopes,
+                        created_at: record.created_at,
+                        expires_at: record.expires_at,
+                        last_used_at: None
+                    }.into()
+                }),
+                Err(e) => Err(e.to_string())
+            }
+    }
+
+    pub async fn get_api_keys(&self, owner: mongodb::bson::oid::ObjectId) -> Result<Vec<ApiKey>, String> {
+        match self
+            .mongodb
+            .database(&config::get().database)
+            .collection::<ApiKey>("api_keys")
+            .find(doc! { "owner": owner }, None)
+            .await {
+                Ok(cursor) => Ok(cursor.try_collect().await.unwrap_or_else(|_| vec![])),
+                Err(e) => Err(e.to_string())
+            }
+    }
+
+    pub async fn delete_api_key(&self, id: &str, owner: mongodb::bson::oid::ObjectId) -> Result<bool, String> {
+        let id = match mongodb::bson::oid::ObjectId::from_str(id) {
+            Ok(id) => id,
+            Err(_) => return Ok(false)
+        };
+        match self
+            .mongodb
+            .database(&config::get().database)
+            .collection::<ApiKey>("api_keys")
+            .delete_one(doc! { "_id": id, "owner": owner }, None)
+            .await {
+                Ok(o) => Ok(o.deleted_count == 1),
+                Err(e) => Err(e.to_string())
+            }
+    }
+
+    pub async fn find_api_key(&self, key: &str) -> Option<ApiKey> {
+        let keys = self.mongodb.database(&config::get().database).collection::<ApiKey>("api_keys");
+        let found = match keys.find_one(doc! { "hash": crypto::digest(key) }, None).await {
+            Ok(Some(found)) => found,
+            _ => return None
+        };
+        if found.expires_at.is_some_and(|expires_at| expires_at < Utc::now()) {
+            return None
+        }
+
+        let now = chrono::Utc::now()
+            .with_timezone(&chrono::FixedOffset::east_opt(7 * 3600).unwrap());
+        let _ = keys.update_one(doc! { "_id": found.id }, doc! { "$set": { "last_used_at": now.to_rfc3339() } }, None).await;
+        Some(found)
+    }
+
+    pub async fn get_one_user(&self, id: String) -> Result<Vec<User>, String> {
+        match self.find_user(mongodb::bson::oid::ObjectId::from_str(&id).unwrap()).await {
+            Ok(found) => {
+                let mut doc: Vec<User> = found.into_iter().collect();
+                for user in doc.iter_mut() {
+                    if let Some(key) = &user.jira_api_key {
+                        user.jira_api_key = Some(crypto::decrypt(key).map_err(|e| e.to_string())?);
+                    }
+                }
                 return Ok(doc)
             }
             Err(e) => return Err(e)
         }
     }
 
-    pub async fn delete_user(&self, db: &S3Client, jira: &reqwest::Client, user: User) -> Result<mongodb::results::DeleteResult, String> {
-        if user.webhook_url.is_some(){
-            let _del = jira
-                .delete(user.webhook_url.unwrap())
-                .basic_auth(user.jira_email.unwrap().to_string(), Some(user.jira_api_key.unwrap().to_string()))
-                .send()
-                .await;
+    pub async fn delete_user(&self, db: &client::storage::Client, jira: &client::jira::Client, user: User) -> Result<u64, String> {
+        self.personal_workspace(&user).await?;
+        for workspace in self.get_workspaces(user.id).await? {
+            if workspace.owner == user.id {
+                self.delete_workspace(db, jira, workspace).await?;
+            } else {
+                self.remove_member(workspace.id, user.id).await?;
+            }
         }
         
+        let deleted = match &self.sql {
+            Some(sql) => sql.delete_user(user.id).await,
+            None => self
+                .mongodb
+                .database(&config::get().database)
+                .collection::<User>("users")
+                .delete_one(
+                    doc! {
+                        "_id": mongodb::bson::oid::ObjectId::from_str(&user.id.to_hex()).unwrap()
+                    }, None)
+                .await
+                .map(|o| o.deleted_count)
+                .map_err(|e| e.to_string())
+        };
+        match deleted {
+            Ok(o) => {
+                let _keys = self
+                    .mongodb
+                    .database(&config::get().database)
+                    .collection::<ApiKey>("api_keys")
+                    .delete_many(doc! { "owner": user.id }, None)
+                    .await;
+                let _sessions = self
+                    .mongodb
+                    .database(&config::get().database)
+                    .collection::<Session>("sessions")
+                    .delete_many(doc! { "user": user.id }, None)
+                    .await;
+                let _invitations = self
+                    .mongodb
+                    .database(&config::get().database)
+                    .collection::<Invitation>("invitations")
+                    .delete_many(doc! { "username": &user.username }, None)
+                    .await;
+                return Ok(o)
+            },
+            Err(e) => return Err(e)
+        }
+    }
+
+    /// Returns the user's personal workspace, creating it from the Jira settings
+    /// that used to live on the user document if it doesn't exist yet.
+    pub async fn personal_workspace(&self, user: &User) -> Result<Workspace, String> {
+        if let Some(workspace) = self.get_workspace(user.id).await? {
+            return Ok(workspace)
+        }
+
+        let now = chrono::Utc::now()
+            .with_timezone(&chrono::FixedOffset::east_opt(7 * 3600).unwrap());
+        let jira_api_key = match &user.jira_api_key {
+            Some(key) => Some(crypto::encrypt(key).map_err(|e| e.to_string())?),
+            None => None
+        };
+        let workspace = Workspace {
+            id: user.id,
+            name: format!("{}'s workspace", user.username),
+            owner: user.id,
+            members: vec![Member { user: user.id, username: user.username.clone(), role: Role::Owner, joined_at: now }],
+            created_at: now,
+            jira_email: user.jira_email.clone(),
+            jira_api_key,
+            jira_url: user.jira_url.clone(),
+            webhook_url: user.webhook_url.clone(),
+            webhook_functional: user.webhook_functional,
+            webhook_last_check: user.webhook_last_check.clone(),
+            jira_flavor: JiraFlavor::Cloud,
+            jira_oauth: None,
+            webhook_id: None
+        };
+
+        let workspaces = self.mongodb.database(&config::get().database).collection::<Workspace>("workspaces");
+        match workspaces.insert_one(&workspace, None).await {
+            Ok(_) => (),
+            // Another request migrated the same user first
+            Err(e) if e.to_string().contains("E11000") => (),
+            Err(e) => return Err(e.to_string())
+        }
+
+        let _legacy = self
+            .set_user(user.id,
+                doc! {
+                    "jira_email": None::<String>,
+                    "jira_api_key": None::<String>,
+                    "jira_url": None::<String>,
+                    "webhook_url": None::<String>,
+                    "webhook_functional": None::<bool>,
+                    "webhook_last_check": None::<String>
+                })
+            .await;
+
+        match self.get_workspace(user.id).await? {
+            Some(workspace) => Ok(workspace),
+            None => Err("Failed to create personal workspace".to_string())
+        }
+    }
+
+    pub async fn create_workspace(&self, db: &client::storage::Client, name: String, user: &User) -> Result<Workspace, String> {
+        let now = chrono::Utc::now()
+            .with_timezone(&chrono::FixedOffset::east_opt(7 * 3600).unwrap());
+        let workspace = Workspace {
+            id: mongodb::bson::oid::ObjectId::new(),
+            name,
+            owner: user.id,
+            members: vec![Member { user: user.id, username: user.username.clone(), role: Role::Owner, joined_at: now }],
+            created_at: now,
+            jira_email: None,
+            jira_api_key: None,
+            jira_url: None,
+            webhook_url: None,
+            webhook_functional: None,
+            webhook_last_check: None,
+            jira_flavor: JiraFlavor::Cloud,
+            jira_oauth: None,
+            webhook_id: None
+        };
+
         match self
             .mongodb
-            .database("telconnect")
-            .collection::<User>("users")
-            .delete_one(
+            .database(&config::get().database)
+            .collection::<Workspace>("workspaces")
+            .insert_one(&workspace, None)
+            .await {
+                Ok(_) => {
+                    match db.create_folder(&workspace.id.to_hex()).await {
+                        Ok(_) => Ok(workspace),
+                        Err(e) => Err(e)
+                    }
+                },
+                Err(e) => Err(e.to_string())
+            }
+    }
+
+    pub async fn get_workspace(&self, id: mongodb::bson::oid::ObjectId) -> Result<Option<Workspace>, String> {
+        match self
+            .mongodb
+            .database(&config::get().database)
+            .collection::<Workspace>("workspaces")
+            .find_one(doc! { "_id": id }, None)
+            .await {
+                Ok(Some(mut workspace)) => {
+                    if let Some(key) = &workspace.jira_api_key {
+                        workspace.jira_api_key = Some(crypto::decrypt(key).map_err(|e| e.to_string())?);
+                    }
+                    if let Some(oauth) = workspace.jira_oauth.as_mut() {
+                        oauth.access_token = crypto::decrypt(&oauth.access_token).map_err(|e| e.to_string())?;
+                        oauth.refresh_token = crypto::decrypt(&oauth.refresh_token).map_err(|e| e.to_string())?;
+                    }
+                    Ok(Some(workspace))
+                },
+                Ok(None) => Ok(None),
+                Err(e) => Err(e.to_string())
+            }
+    }
+
+    pub async fn get_workspaces(&self, user: mongodb::bson::oid::ObjectId) -> Result<Vec<Workspace>, String> {
+        match self
+            .mongodb
+            .database(&config::get().database)
+            .collection::<Workspace>("workspaces")
+            .find(doc! { "members.user": user }, None)
+            .await {
+                Ok(cursor) => Ok(cursor.try_collect().await.unwrap_or_else(|_| vec![])),
+                Err(e) => Err(e.to_string())
+            }
+    }
+
+    /// Moves the Jira settings stored on the workspace into its default site.
+    /// The site is built from the stored document so its credentials move over still sealed,
+    /// whether the caller's copy was opened or not.
+    async fn migrate_site(&self, workspace: &Workspace) -> Result<(), String> {
+        if workspace.jira_url.is_none() && workspace.jira_oauth.is_none() && workspace.webhook_url.is_none() {
+            return Ok(())
+        }
+
+        let stored = match self
+            .mongodb
+            .database(&config::get().database)
+            .collection::<Workspace>("workspaces")
+            .find_one(doc! { "_id": workspace.id }, None)
+            .await {
+                Ok(Some(stored)) => stored,
+                Ok(None) => return Ok(()),
+                Err(e) => return Err(e.to_string())
+            };
+        if stored.jira_url.is_none() && stored.jira_oauth.is_none() && stored.webhook_url.is_none() {
+            // Another request migrated the same workspace first
+            return Ok(())
+        }
+
+        let site = JiraSite::legacy(&stored);
+        match self.mongodb.database(&config::get().database).collection::<JiraSite>("jira_sites").insert_one(&site, None).await {
+            Ok(_) => (),
+            // Another request migrated the same workspace first
+            Err(e) if e.to_string().contains("E11000") => (),
+            Err(e) => return Err(e.to_string())
+        }
+
+        match self
+            .mongodb
+            .database(&config::get().database)
+            .collection::<Workspace>("workspaces")
+            .update_one(doc! { "_id": workspace.id },
                 doc! {
-                    "_id": mongodb::bson::oid::ObjectId::from_str(&user.id.to_hex()).unwrap()
+                    "$set": {
+                        "jira_email": None::<String>,
+                        "jira_api_key": None::<String>,
+                        "jira_url": None::<String>,
+                        "jira_oauth": None::<String>,
+                        "webhook_url": None::<String>,
+                        "webhook_id": None::<i64>,
+                        "webhook_functional": None::<bool>,
+                        "webhook_last_check": None::<String>
+                    }
                 }, None)
             .await {
-            Ok(o) => {
-                match db.list_objects_v2(ListObjectsV2Request {
-                    bucket: BUCKET.to_owned(),
-                    prefix: Some(format!("{}/", user.id.to_hex())),
-                    ..Default::default()
-                }).await{
-                    Ok(objects) => {
-                        let list: Vec<String> = objects.contents.unwrap()
-                            .into_iter()
-                            .rev()
-                            .map(|ob| ob.key.unwrap())
-                            .collect();
-                        for li in list {
-                            db.delete_object( DeleteObjectRequest  {
-                                bucket: BUCKET.to_owned(),
-                                key: li.clone(),
-                                ..Default::default()
-                            }).await;
-                        }
-                        return Ok(o)
-                    },
-                    Err(e) => return Err(e.to_string())
-                }
-            },
+                Ok(_) => Ok(()),
+                Err(e) => Err(e.to_string())
+            }
+    }
+
+    pub async fn get_sites(&self, workspace: &Workspace) -> Result<Vec<JiraSite>, String> {
+        self.migrate_site(workspace).await?;
+        let list: Vec<JiraSite> = match self
+            .mongodb
+            .database(&config::get().database)
+            .collection::<JiraSite>("jira_sites")
+            .find(doc! { "workspace": workspace.id }, None)
+            .await {
+                Ok(cursor) => cursor.try_collect().await.unwrap_or_else(|_| vec![]),
+                Err(e) => return Err(e.to_string())
+            };
+        list.into_iter().map(open_site).collect()
+    }
+
+    pub async fn get_site(&self, workspace: &Workspace, id: mongodb::bson::oid::ObjectId) -> Result<Option<JiraSite>, String> {
+        Ok(self.get_sites(workspace).await?.into_iter().find(|site| site.id == id))
+    }
+
+    /// Site an incoming webhook was registered for, credentials stay sealed.
+    pub async fn find_site(&self, id: mongodb::bson::oid::ObjectId) -> Option<JiraSite> {
+        self
+            .mongodb
+            .database(&config::get().database)
+            .collection::<JiraSite>("jira_sites")
+            .find_one(doc! { "_id": id }, None)
+            .await
+            .unwrap_or_default()
+    }
+
+    pub async fn delete_workspace(&self, db: &client::storage::Client, jira: &client::jira::Client, workspace: Workspace) -> Result<String, String> {
+        for site in self.get_sites(&workspace).await? {
+            let _del = jira.delete_webhook(&self.mongodb, site).await;
+        }
+        let _sites = self
+            .mongodb
+            .database(&config::get().database)
+            .collection::<JiraSite>("jira_sites")
+            .delete_many(doc! { "workspace": workspace.id }, None)
+            .await;
+
+        let _bots = self
+            .mongodb
+            .database(&config::get().database)
+            .collection::<Bot>("bots")
+            .delete_many(doc! { "owner": workspace.id }, None)
+            .await;
+        let _invitations = self
+            .mongodb
+            .database(&config::get().database)
+            .collection::<Invitation>("invitations")
+            .delete_many(doc! { "workspace": workspace.id }, None)
+            .await;
+
+        db.delete_folder(&workspace.id.to_hex()).await?;
+        actions::routing::forget(&workspace.id.to_hex());
+
+        match self
+            .mongodb
+            .database(&config::get().database)
+            .collection::<Workspace>("workspaces")
+            .delete_one(doc! { "_id": workspace.id }, None)
+            .await {
+                Ok(_) => Ok("Workspace successfully deleted".to_string()),
+                Err(e) => Err(e.to_string())
+            }
+    }
+
+    pub async fn set_member_role(&self, workspace: mongodb::bson::oid::ObjectId, user: mongodb::bson::oid::ObjectId, role: Role) -> Result<bool, String> {
+        let role = mongodb::bson::to_bson(&role).map_err(|e| e.to_string())?;
+        match self
+            .mongodb
+            .database(&config::get().database)
+            .collection::<Workspace>("workspaces")
+            .update_one(doc! { "_id": workspace, "members.user": user }, doc! { "$set": { "members.$.role": role } }, None)
+            .await {
+                Ok(o) => Ok(o.matched_count == 1),
+                Err(e) => Err(e.to_string())
+            }
+    }
+
+    pub async fn remove_member(&self, workspace: mongodb::bson::oid::ObjectId, user: mongodb::bson::oid::ObjectId) -> Result<bool, String> {
+        match self
+            .mongodb
+            .database(&config::get().database)
+            .collection::<Workspace>("workspaces")
+            .update_one(doc! { "_id": workspace }, doc! { "$pull": { "members": { "user": user } } }, None)
+            .await {
+                Ok(o) => Ok(o.modified_count == 1),
+                Err(e) => Err(e.to_string())
+            }
+    }
+
+    pub async fn invite_member(&self, workspace: &Workspace, input: InvitationInput, invited_by: &User) -> Result<String, String> {
+        if input.role == Role::Owner {
+            return Err("A workspace can only have one owner".to_string())
+        }
+        let invitee = match self.find_user_by_name(&input.username).await {
+                Ok(Some(invitee)) => invitee,
+                Ok(None) => return Err("User Not Found".to_string()),
+                Err(e) => return Err(e)
+            };
+        if workspace.role_of(&invitee.id).is_some() {
+            return Err(format!("{} is already a member of this workspace", invitee.username))
+        }
+
+        let invitations = self.mongodb.database(&config::get().database).collection::<InvitationNew>("invitations");
+        match invitations.find_one(doc! { "workspace": workspace.id, "username": &invitee.username }, None).await {
+            Ok(Some(_)) => return Err(format!("{} has already been invited", invitee.username)),
+            Ok(None) => (),
+            Err(e) => return Err(e.to_string())
+        }
+
+        match invitations
+            .insert_one(InvitationNew {
+                workspace: workspace.id,
+                workspace_name: workspace.name.clone(),
+                username: invitee.username.clone(),
+                role: input.role,
+                invited_by: invited_by.username.clone(),
+                created_at: chrono::Utc::now()
+                    .with_timezone(&chrono::FixedOffset::east_opt(7 * 3600).unwrap())
+            }, None)
+            .await {
+                Ok(_) => Ok(format!("{} has been invited", invitee.username)),
+                Err(e) => Err(e.to_string())
+            }
+    }
+
+    pub async fn get_invitations(&self, username: &str) -> Result<Vec<Invitation>, String> {
+        match self
+            .mongodb
+            .database(&config::get().database)
+            .collection::<Invitation>("invitations")
+            .find(doc! { "username": username }, None)
+            .await {
+                Ok(cursor) => Ok(cursor.try_collect().await.unwrap_or_else(|_| vec![])),
+                Err(e) => Err(e.to_string())
+            }
+    }
+
+    pub async fn answer_invitation(&self, id: &str, user: &User, accept: bool) -> Result<String, String> {
+        let id = match mongodb::bson::oid::ObjectId::from_str(id) {
+            Ok(id) => id,
+            Err(_) => return Err("Invitation not found".to_string())
+        };
+        let invitations = self.mongodb.database(&config::get().database).collection::<Invitation>("invitations");
+        let invitation = match invitations.find_one_and_delete(doc! { "_id": id, "username": &user.username }, None).await {
+            Ok(Some(invitation)) => invitation,
+            Ok(None) => return Err("Invitation not found".to_string()),
             Err(e) => return Err(e.to_string())
+        };
+        if !accept {
+            return Ok("Invitation declined".to_string())
         }
+
+        let member = Member {
+            user: user.id,
+            username: user.username.clone(),
+            role: invitation.role,
+            joined_at: chrono::Utc::now()
+                .with_timezone(&chrono::FixedOffset::east_opt(7 * 3600).unwrap())
+        };
+        let member = mongodb::bson::to_bson(&member).map_err(|e| e.to_string())?;
+        match self
+            .mongodb
+            .database(&config::get().database)
+            .collection::<Workspace>("workspaces")
+            .update_one(doc! { "_id": invitation.workspace, "members.user": { "$ne": user.id } }, doc! { "$push": { "members": member } }, None)
+            .await {
+                Ok(_) => Ok(format!("You have joined {}", invitation.workspace_name)),
+                Err(e) => Err(e.to_string())
+            }
+    }
+
+    pub async fn add_bot(&self, bot: BotInput, owner: mongodb::bson::oid::ObjectId) -> Result<String, BotError> {
+        match self.find_bot_by_name(&bot.name, owner).await {
+            Ok(Some(_)) => return Err(BotError::BotCreateExist),
+            Ok(None) => (),
+            Err(e) => return Err(BotError::DbError(e.to_string()))
+        }
+
+        let username = match teloxide::Bot::new(&bot.token).get_me().await {
+            Ok(me) => me.username().to_string(),
+            Err(_e) => return Err(BotError::TokenInval)
+        };
+        let sealed = crypto::encrypt(&bot.token).map_err(|e| BotError::DbError(e.to_string()))?;
+
+        match self
+            .mongodb
+            .database(&config::get().database)
+            .collection::<BotNew>("bots")
+            .insert_one(BotNew {
+                owner,
+                name: bot.name,
+                token: sealed,
+                username: username.clone(),
+                created_at: chrono::Utc::now()
+                    .with_timezone(&chrono::FixedOffset::east_opt(7 * 3600).unwrap()),
+                updated_at: None
+            }, None)
+            .await {
+                Ok(_) => Ok(format!("Bot @{} successfully registered", username)),
+                Err(e) => Err(BotError::DbError(e.to_string()))
+            }
+    }
+
+    pub async fn get_bots(&self, owner: mongodb::bson::oid::ObjectId) -> Result<Vec<Bot>, BotError> {
+        match self
+            .mongodb
+            .database(&config::get().database)
+            .collection::<Bot>("bots")
+            .find(doc! { "owner": owner }, None)
+            .await {
+            Ok(cursor) => {
+                let bots: Vec<Bot> = cursor.try_collect().await.unwrap_or_else(|_| vec![]);
+                bots.into_iter().map(open_bot).collect()
+            },
+            Err(e) => Err(BotError::DbError(e.to_string()))
+        }
+    }
+
+    pub async fn get_one_bot(&self, id: &str, owner: mongodb::bson::oid::ObjectId) -> Result<Option<Bot>, BotError> {
+        let id = match mongodb::bson::oid::ObjectId::from_str(id) {
+            Ok(id) => id,
+            Err(_) => return Ok(None)
+        };
+        match self
+            .mongodb
+            .database(&config::get().database)
+            .collection::<Bot>("bots")
+            .find_one(doc! { "_id": id, "owner": owner }, None)
+            .await {
+            Ok(bot) => bot.map(open_bot).transpose(),
+            Err(e) => Err(BotError::DbError(e.to_string()))
+        }
+    }
+
+    async fn find_bot_by_name(&self, name: &str, owner: mongodb::bson::oid::ObjectId) -> Result<Option<Bot>, mongodb::error::Error> {
+        self
+            .mongodb
+            .database(&config::get().database)
+            .collection::<Bot>("bots")
+            .find_one(doc! { "name": name, "owner": owner }, None)
+            .await
+    }
+
+    pub async fn update_bot(&self, id: &str, bot: BotInput, owner: mongodb::bson::oid::ObjectId) -> Result<String, BotError> {
+        let current = match self.get_one_bot(id, owner).await {
+            Ok(Some(current)) => current,
+            Ok(None) => return Err(BotError::BotNotFound),
+            Err(e) => return Err(e)
+        };
+
+        if bot.name != current.name {
+            match self.find_bot_by_name(&bot.name, owner).await {
+                Ok(Some(_)) => return Err(BotError::BotCreateExist),
+                Ok(None) => (),
+                Err(e) => return Err(BotError::DbError(e.to_string()))
+            }
+        }
+
+        let token = if crypto::is_unchanged(&bot.token, &current.token) { current.token.clone() } else { bot.token };
+
+        let username = match teloxide::Bot::new(&token).get_me().await {
+            Ok(me) => me.username().to_string(),
+            Err(_e) => return Err(BotError::TokenInval)
+        };
+        let sealed = crypto::encrypt(&token).map_err(|e| BotError::DbError(e.to_string()))?;
+
+        let now = chrono::Utc::now()
+            .with_timezone(&chrono::FixedOffset::east_opt(7 * 3600).unwrap());
+
+        match self
+            .mongodb
+            .database(&config::get().database)
+            .collection::<Bot>("bots")
+            .update_one(doc! { "_id": current.id },
+                doc! {
+                    "$set": {
+                        "name": &bot.name,
+                        "token": &sealed,
+                        "username": &username,
+                        "updated_at": now.to_rfc3339()
+                    }
+                }, None)
+            .await {
+                Ok(_) => Ok(format!("Bot @{} successfully updated", username)),
+                Err(e) => Err(BotError::DbError(e.to_string()))
+            }
+    }
+
+    pub async fn delete_bot(&self, db: &client::storage::Client, id: &str, owner: mongodb::bson::oid::ObjectId) -> Result<String, BotError> {
+        let bot = match self.get_one_bot(id, owner).await {
+            Ok(Some(bot)) => bot,
+            Ok(None) => return Err(BotError::BotNotFound),
+            Err(e) => return Err(e)
+        };
+
+        let users: Vec<String> = match actions::connector::get_connectors(db, owner.to_hex()).await {
+            Ok(cons) => cons.into_iter()
+                .filter(|con| con.bot_id.as_deref() == Some(id))
+                .map(|con| con.name)
+                .collect(),
+            Err(ConnectorError::ConEmpty) => vec![],
+            Err(e) => return Err(BotError::DbError(e.to_string()))
+        };
+        if !users.is_empty() {
+            return Err(BotError::BotInUse(users.join(", ")))
+        }
+
+        match self
+            .mongodb
+            .database(&config::get().database)
+            .collection::<Bot>("bots")
+            .delete_one(doc! { "_id": bot.id }, None)
+            .await {
+                Ok(_) => Ok("Bot successfully removed".to_string()),
+                Err(e) => Err(BotError::DbError(e.to_string()))
+            }
+    }
+
+    pub async fn bot_token(&self, bot_id: &str, owner: mongodb::bson::oid::ObjectId) -> Result<String, ConnectorError> {
+        match self.get_one_bot(bot_id, owner).await {
+            Ok(Some(bot)) => Ok(bot.token),
+            Ok(None) => Err(ConnectorError::BotNotFound),
+            Err(e) => Err(ConnectorError::DbError(e.to_string()))
+        }
+    }
+}
+
+fn open_site(mut site: JiraSite) -> Result<JiraSite, String> {
+    if let Some(key) = &site.jira_api_key {
+        site.jira_api_key = Some(crypto::decrypt(key).map_err(|e| e.to_string())?);
+    }
+    if let Some(oauth) = site.jira_oauth.as_mut() {
+        oauth.access_token = crypto::decrypt(&oauth.access_token).map_err(|e| e.to_string())?;
+        oauth.refresh_token = crypto::decrypt(&oauth.refresh_token).map_err(|e| e.to_string())?;
     }
+    Ok(site)
 }
 
+fn open_bot(mut bot: Bot) -> Result<Bot, BotError> {
+    bot.token = crypto::decrypt(&bot.token).map_err(|e| BotError::DbError(e.to_string()))?;
+    Ok(bot)
+}
 
+async fn hash_password(password: String) -> Result<String, String> {
+    let cost = password::cost();
+    match tokio::task::spawn_blocking(move || hash(password, cost)).await {
+        Ok(Ok(hashed)) => Ok(hashed),
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(e) => Err(e.to_string())
+    }
+}
+
+/// `bcrypt::verify` takes as long as hashing, so it runs off the async workers too.
+async fn verify_password(password: String, hashed: String) -> Result<bool, String> {
+    match tokio::task::spawn_blocking(move || verify(password, &hashed)).await {
+        Ok(Ok(matches)) => Ok(matches),
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(e) => Err(e.to_string())
+    }
+}
+
+fn random_string(len: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(len)
+        .map(char::from)
+        .collect()
+}
+
+fn access_token_minutes() -> i64 {
+    std::env::var("ACCESS_TOKEN_MINUTES").ok().and_then(|v| v.parse().ok()).unwrap_or(15)
+}
+
+fn refresh_token_days() -> i64 {
+    std::env::var("REFRESH_TOKEN_DAYS").ok().and_then(|v| v.parse().ok()).unwrap_or(30)
+}
+
+fn sign_mfa_challenge(user: &User, secret: &str) -> Result<MfaChallenge, String> {
+    let claims = MfaClaims {
+        id: user.id,
+        username: user.username.clone(),
+        purpose: "mfa".to_string(),
+        exp: (Utc::now() + Duration::minutes(5)).timestamp() as usize
+    };
+    match encode(&Header::default(), &claims, &EncodingKey::from_secret(secret.as_ref())) {
+        Ok(mfa_token) => Ok(MfaChallenge {
+            mfa_required: true,
+            mfa_token,
+            expires_in: 5 * 60
+        }),
+        Err(e) => Err(e.to_string())
+    }
+}
+
+fn sign_tokens(sid: mongodb::bson::oid::ObjectId, user: mongodb::bson::oid::ObjectId, jti: String, refresh: String, secret: &str) -> Result<TokenPair, String> {
+    let claims = Claims {
+        id: user,
+        sid,
+        jti,
+        exp: (Utc::now() + Duration::minutes(access_token_minutes())).timestamp() as usize,
+        workspace: None
+    };
+    match encode(&Header::default(), &claims, &EncodingKey::from_secret(secret.as_ref())) {
+        Ok(access_token) => Ok(TokenPair {
+            access_token,
+            refresh_token: format!("{}.{}", sid.to_hex(), refresh),
+            token_type: "Bearer".to_string(),
+            expires_in: access_token_minutes() * 60
+        }),
+        Err(e) => Err(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::workspace::JiraOAuth;
+
+    fn sealed_workspace() -> Workspace {
+        crypto::init_for_tests();
+        let now = chrono::DateTime::parse_from_rfc3339("2026-10-19T08:00:00+07:00").unwrap();
+        let id = mongodb::bson::oid::ObjectId::new();
+        Workspace {
+            id,
+            name: "Ops".to_string(),
+            owner: id,
+            members: vec![],
+            created_at: now,
+            jira_email: Some("ops@example.com".to_string()),
+            jira_api_key: Some(crypto::encrypt("api-key").unwrap()),
+            jira_url: Some("https://ops.atlassian.net".to_string()),
+            webhook_url: None,
+            webhook_functional: None,
+            webhook_last_check: None,
+            jira_flavor: JiraFlavor::Cloud,
+            jira_oauth: Some(JiraOAuth {
+                access_token: crypto::encrypt("access").unwrap(),
+                refresh_token: crypto::encrypt("refresh").unwrap(),
+                expires_at: now,
+                cloud_id: "cloud".to_string(),
+                site_url: "https://ops.atlassian.net".to_string(),
+                scope: String::new(),
+                grant: String::new()
+            }),
+            webhook_id: Some(7)
+        }
+    }
+
+    #[test]
+    fn migrated_site_keeps_credentials_sealed_once() {
+        let workspace = sealed_workspace();
+        let site = JiraSite::legacy(&workspace);
+        assert_eq!(site.jira_api_key, workspace.jira_api_key);
+        assert_eq!(site.jira_oauth, workspace.jira_oauth);
+
+        let opened = open_site(site).unwrap();
+        assert_eq!(opened.jira_api_key.as_deref(), Some("api-key"));
+        let oauth = opened.jira_oauth.unwrap();
+        assert_eq!(oauth.access_token, "access");
+        assert_eq!(oauth.refresh_token, "refresh");
+        assert_eq!(opened.webhook_id, Some(7));
+    }
+}
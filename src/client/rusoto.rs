@@ -1,25 +1,174 @@
+use std::io::Read;
+use std::str::FromStr;
+
+use rusoto_core::{request::HttpClient, Region};
+use rusoto_credential::{AwsCredentials, DefaultCredentialsProvider, StaticProvider};
+use rusoto_s3::{S3, S3Client, Delete, DeleteObjectRequest, DeleteObjectsRequest, GetObjectRequest, ObjectIdentifier, HeadBucketRequest, HeadObjectRequest, ListObjectsV2Request, PutObjectRequest};
+
+use crate::config;
+
 #[derive(Clone)]
 pub struct Client {
     pub s3: rusoto_s3::S3Client
 }
 
+/// A custom `endpoint` targets any S3-compatible store (Spaces, MinIO, ...), without one
+/// `region` must be an AWS region. Requests always use path-style addressing
+/// (`{endpoint}/{bucket}/{key}`), which every one of them accepts.
+pub fn region() -> Result<Region, String> {
+    let config = config::get();
+    if config.endpoint.is_empty() {
+        Region::from_str(&config.region).map_err(|e| format!("Unknown AWS region '{}': {}", config.region, e))
+    } else {
+        Ok(Region::Custom {
+            name: config.region.clone(),
+            endpoint: config.endpoint.clone(),
+        })
+    }
+}
+
 impl Client{
+    /// Uses `BUCKET_KEY`/`BUCKET_SECRET` when both are set, otherwise the standard AWS
+    /// credential chain: environment, profile file, container and instance role.
     pub fn new() -> Self {
-     return Self { 
-            s3: rusoto_s3::S3Client::new_with(
-            rusoto_core::request::HttpClient::new().unwrap(),
-            rusoto_credential::StaticProvider::from(
-                rusoto_credential::AwsCredentials::new(
-                std::env::var("BUCKET_KEY").expect("BUCKET_KEY must be defined"), 
-                std::env::var("BUCKET_SECRET").expect("BUCKET_SECRET must be defined"), 
-                None, 
-                None)
+        let region = region().expect("Storage region must be valid");
+        let s3 = match (std::env::var("BUCKET_KEY"), std::env::var("BUCKET_SECRET")) {
+            (Ok(key), Ok(secret)) => S3Client::new_with(
+                HttpClient::new().unwrap(),
+                StaticProvider::from(AwsCredentials::new(key, secret, None, None)),
+                region
             ),
-            rusoto_core::Region::Custom {
-                name: "sgp1".to_owned(),
-                endpoint: "https://sgp1.digitaloceanspaces.com".to_owned(),
-            },
+            _ => S3Client::new_with(
+                HttpClient::new().unwrap(),
+                DefaultCredentialsProvider::new().expect("Failed to set up the AWS credential chain"),
+                region
             )
+        };
+        return Self { s3 }
+    }
+
+    /// Fails unless the bucket exists and a probe object can be written and removed.
+    pub async fn check(&self) -> Result<(), String> {
+        let bucket = config::get().bucket.clone();
+        if let Err(e) = self.s3.head_bucket(HeadBucketRequest {
+            bucket: bucket.clone(),
+            ..Default::default()
+        }).await {
+            return Err(format!("Bucket {} is not reachable: {}", bucket, e))
+        }
+
+        let key = format!(".healthcheck/{}", mongodb::bson::oid::ObjectId::new().to_hex());
+        if let Err(e) = self.s3.put_object(PutObjectRequest {
+            bucket: bucket.clone(),
+            key: key.clone(),
+            body: Some(Vec::new().into()),
+            ..Default::default()
+        }).await {
+            return Err(format!("Bucket {} is not writable: {}", bucket, e))
+        }
+        match self.s3.delete_object(DeleteObjectRequest {
+            bucket: bucket.clone(),
+            key,
+            ..Default::default()
+        }).await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("Bucket {} does not allow deletes: {}", bucket, e))
+        }
+    }
+
+    pub async fn put(&self, key: &str, body: Vec<u8>) -> Result<(), String> {
+        match self.s3.put_object(PutObjectRequest {
+            bucket: config::get().bucket.clone(),
+            key: key.to_string(),
+            body: Some(body.into()),
+            ..Default::default()
+        }).await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(e.to_string())
         }
     }
-}
\ No newline at end of file
+
+    pub async fn get(&self, key: &str) -> Result<Vec<u8>, String> {
+        match self.s3.get_object(GetObjectRequest {
+            bucket: config::get().bucket.clone(),
+            key: key.to_string(),
+            ..Default::default()
+        }).await {
+            Ok(ob) => {
+                let body = match ob.body {
+                    Some(body) => body,
+                    None => return Ok(Vec::new())
+                };
+                tokio::task::spawn_blocking(move || {
+                    let mut data = Vec::new();
+                    body.into_blocking_read().read_to_end(&mut data).map(|_| data)
+                }).await.expect("Task panicked").map_err(|e| e.to_string())
+            },
+            Err(e) => Err(e.to_string())
+        }
+    }
+
+    pub async fn exists(&self, key: &str) -> bool {
+        self.s3.head_object(HeadObjectRequest {
+            bucket: config::get().bucket.clone(),
+            key: key.to_string(),
+            ..Default::default()
+        }).await.is_ok()
+    }
+
+    pub async fn delete(&self, key: &str) -> Result<(), String> {
+        match self.s3.delete_object(DeleteObjectRequest {
+            bucket: config::get().bucket.clone(),
+            key: key.to_string(),
+            ..Default::default()
+        }).await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(e.to_string())
+        }
+    }
+
+    /// Deletes the keys in batches of 1000, the most a single request accepts.
+    pub async fn delete_many(&self, keys: Vec<String>) -> Result<(), String> {
+        let mut failed = vec![];
+        for batch in keys.chunks(1000) {
+            let deleted = self.s3.delete_objects(DeleteObjectsRequest {
+                bucket: config::get().bucket.clone(),
+                delete: Delete {
+                    objects: batch.iter().map(|key| ObjectIdentifier { key: key.clone(), version_id: None }).collect(),
+                    quiet: Some(true)
+                },
+                ..Default::default()
+            }).await.map_err(|e| e.to_string())?;
+            failed.extend(deleted.errors.unwrap_or_default().into_iter()
+                .map(|e| format!("{}: {}", e.key.unwrap_or_default(), e.message.unwrap_or_default())));
+        }
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("Failed to delete {} object(s): {}", failed.len(), failed.join(", ")))
+        }
+    }
+
+    /// Every key under `prefix` in lexicographic order, folder markers included.
+    pub async fn list(&self, prefix: &str) -> Result<Vec<String>, String> {
+        let mut keys = vec![];
+        let mut continuation_token = None;
+        loop {
+            let page = self.s3.list_objects_v2(ListObjectsV2Request {
+                bucket: config::get().bucket.clone(),
+                prefix: Some(prefix.to_string()),
+                continuation_token: continuation_token.clone(),
+                ..Default::default()
+            }).await.map_err(|e| e.to_string())?;
+            keys.extend(page.contents.unwrap_or_default().into_iter().filter_map(|ob| ob.key));
+
+            if page.is_truncated != Some(true) {
+                return Ok(keys)
+            }
+            match page.next_continuation_token {
+                Some(token) => continuation_token = Some(token),
+                None => return Err(format!("Listing {} was truncated without a continuation token", prefix))
+            }
+        }
+    }
+}
@@ -0,0 +1,546 @@
+use std::collections::BTreeSet;
+use std::sync::OnceLock;
+
+use chrono::{DateTime, FixedOffset};
+use mongodb::bson::{oid::ObjectId, Bson, Document};
+use sqlx::{any::{AnyArguments, AnyPoolOptions, AnyRow}, query::Query, Any, AnyPool, Row};
+
+use crate::config;
+use crate::models::{connector::{self, Connector}, history::Revision, log::Log, user::{User, UserNew}};
+
+static POOL: OnceLock<AnyPool> = OnceLock::new();
+
+/// Connects to `sql_url` and applies the migrations embedded from `migrations/`.
+pub async fn init() -> Result<(), String> {
+    sqlx::any::install_default_drivers();
+    let pool = AnyPoolOptions::new()
+        .connect(&config::get().sql_url)
+        .await.map_err(|e| format!("Failed to connect to the SQL database: {}", e))?;
+    sqlx::migrate!()
+        .run(&pool)
+        .await.map_err(|e| format!("Failed to migrate the SQL database: {}", e))?;
+    assign_ids(&pool).await.map_err(|e| format!("Failed to assign connector ids: {}", e))?;
+    let _ = POOL.set(pool);
+    Ok(())
+}
+
+/// Gives connectors created before the `id` column their legacy id and points their
+/// routes and delivery log at it.
+async fn assign_ids(pool: &AnyPool) -> Result<(), String> {
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+    let rows = sqlx::query("SELECT workspace, name FROM connectors WHERE id IS NULL")
+        .fetch_all(&mut *tx)
+        .await.map_err(|e| e.to_string())?;
+    for row in rows {
+        let (workspace, name): (String, String) = (get(&row, "workspace")?, get(&row, "name")?);
+        for sql in [
+            "UPDATE connectors SET id = $1 WHERE workspace = $2 AND name = $3",
+            "UPDATE connector_routes SET connector = $1 WHERE workspace = $2 AND connector = $3",
+            "UPDATE delivery_logs SET connector = $1 WHERE workspace = $2 AND connector = $3"
+        ] {
+            sqlx::query(sql)
+                .bind(connector::legacy_id(&workspace, &name))
+                .bind(workspace.clone())
+                .bind(name.clone())
+                .execute(&mut *tx)
+                .await.map_err(|e| e.to_string())?;
+        }
+    }
+    tx.commit().await.map_err(|e| e.to_string())
+}
+
+/// User columns `set_user` may change, `id` is immutable.
+const USER_COLUMNS: [&str; 12] = ["username", "password", "created_at", "jira_email", "jira_api_key", "jira_url",
+    "webhook_url", "webhook_functional", "webhook_last_check", "totp_secret", "totp_pending", "recovery_codes"];
+
+const CONNECTOR_COLUMNS: &str = "id, workspace, name, description, token, bot_id, chatid, thread_id, topics, active, schedule, duration, project, event, created_at, updated_at, version";
+
+fn get<T>(row: &AnyRow, column: &str) -> Result<T, String>
+where T: for<'r> sqlx::Decode<'r, Any> + sqlx::Type<Any> {
+    row.try_get(column).map_err(|e| format!("{}: {}", column, e))
+}
+
+fn timestamp(value: String) -> Result<DateTime<FixedOffset>, String> {
+    DateTime::parse_from_rfc3339(&value).map_err(|e| e.to_string())
+}
+
+fn flag(value: i64) -> bool {
+    value != 0
+}
+
+fn json<T: serde::de::DeserializeOwned>(value: String) -> Result<T, String> {
+    serde_json::from_str(&value).map_err(|e| e.to_string())
+}
+
+fn user(row: &AnyRow) -> Result<User, String> {
+    Ok(User {
+        id: ObjectId::parse_str(get::<String>(row, "id")?).map_err(|e| e.to_string())?,
+        username: get(row, "username")?,
+        password: get(row, "password")?,
+        created_at: timestamp(get(row, "created_at")?)?,
+        jira_email: get(row, "jira_email")?,
+        jira_api_key: get(row, "jira_api_key")?,
+        jira_url: get(row, "jira_url")?,
+        webhook_url: get(row, "webhook_url")?,
+        webhook_functional: get::<Option<i64>>(row, "webhook_functional")?.map(flag),
+        webhook_last_check: get(row, "webhook_last_check")?,
+        totp_secret: get(row, "totp_secret")?,
+        totp_pending: get(row, "totp_pending")?,
+        recovery_codes: get::<Option<String>>(row, "recovery_codes")?.map(json).transpose()?,
+        totp_step: get(row, "totp_step")?
+    })
+}
+
+fn revision(row: &AnyRow) -> Result<Revision, String> {
+    Ok(Revision {
+        version: get(row, "version")?,
+        change: serde_json::from_value(serde_json::Value::String(get(row, "change")?)).map_err(|e| e.to_string())?,
+        author: get(row, "author")?,
+        created_at: timestamp(get(row, "created_at")?)?,
+        connector: json(get(row, "snapshot")?)?
+    })
+}
+
+fn connector(row: &AnyRow) -> Result<Connector, String> {
+    Ok(Connector {
+        id: get(row, "id")?,
+        name: get(row, "name")?,
+        description: get(row, "description")?,
+        token: get(row, "token")?,
+        bot_id: get(row, "bot_id")?,
+        chatid: get(row, "chatid")?,
+        thread_id: get::<Option<i64>>(row, "thread_id")?.map(|id| id as i32),
+        topics: json(get(row, "topics")?)?,
+        active: flag(get(row, "active")?),
+        schedule: flag(get(row, "schedule")?),
+        duration: get(row, "duration")?,
+        project: json(get(row, "project")?)?,
+        event: json(get(row, "event")?)?,
+        created_at: timestamp(get(row, "created_at")?)?,
+        updated_at: get::<Option<String>>(row, "updated_at")?.map(timestamp).transpose()?,
+        version: get(row, "version")?
+    })
+}
+
+/// Binds the connector columns in `CONNECTOR_COLUMNS` order.
+fn bind_connector<'q>(query: Query<'q, Any, AnyArguments<'q>>, workspace: &str, con: &Connector) -> Query<'q, Any, AnyArguments<'q>> {
+    query
+        .bind(con.id.clone())
+        .bind(workspace.to_string())
+        .bind(con.name.clone())
+        .bind(con.description.clone())
+        .bind(con.token.clone())
+        .bind(con.bot_id.clone())
+        .bind(con.chatid.clone())
+        .bind(con.thread_id.map(i64::from))
+        .bind(serde_json::to_string(&con.topics).unwrap())
+        .bind(i64::from(con.active))
+        .bind(i64::from(con.schedule))
+        .bind(con.duration.clone())
+        .bind(serde_json::to_string(&con.project).unwrap())
+        .bind(serde_json::to_string(&con.event).unwrap())
+        .bind(con.created_at.to_rfc3339())
+        .bind(con.updated_at.map(|time| time.to_rfc3339()))
+        .bind(con.version)
+}
+
+/// The (project, event, site) triples an event must match for `con` to receive it.
+fn routes(workspace: &str, con: &Connector) -> BTreeSet<(String, String, String)> {
+    con.project.iter()
+        .flat_map(|project| con.event.iter().map(move |event| (
+            project.id.clone(),
+            event.clone(),
+            project.site.clone().unwrap_or_else(|| workspace.to_string())
+        )))
+        .collect()
+}
+
+/// Users, connectors and delivery logs kept in PostgreSQL or SQLite. Connector tokens
+/// arrive here already sealed, like the objects the other storage backends write.
+#[derive(Clone)]
+pub struct Client {
+    pool: AnyPool
+}
+
+impl Client {
+    pub fn new() -> Self {
+        return Self {
+            pool: POOL.get().expect("SQL database must be initialised at startup").clone()
+        }
+    }
+
+    pub async fn check(&self) -> Result<(), String> {
+        match sqlx::query("SELECT 1").execute(&self.pool).await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("SQL database is not reachable: {}", e))
+        }
+    }
+
+    pub async fn find_user(&self, id: ObjectId) -> Result<Option<User>, String> {
+        match sqlx::query("SELECT * FROM users WHERE id = $1")
+            .bind(id.to_hex())
+            .fetch_optional(&self.pool)
+            .await {
+                Ok(row) => row.as_ref().map(user).transpose(),
+                Err(e) => Err(e.to_string())
+            }
+    }
+
+    pub async fn find_user_by_name(&self, username: &str) -> Result<Option<User>, String> {
+        match sqlx::query("SELECT * FROM users WHERE username = $1")
+            .bind(username.to_string())
+            .fetch_optional(&self.pool)
+            .await {
+                Ok(row) => row.as_ref().map(user).transpose(),
+                Err(e) => Err(e.to_string())
+            }
+    }
+
+    pub async fn users(&self) -> Result<Vec<User>, String> {
+        match sqlx::query("SELECT * FROM users").fetch_all(&self.pool).await {
+            Ok(rows) => rows.iter().map(user).collect(),
+            Err(e) => Err(e.to_string())
+        }
+    }
+
+    pub async fn insert_user(&self, new: UserNew) -> Result<ObjectId, String> {
+        let id = ObjectId::new();
+        match sqlx::query("INSERT INTO users (id, username, password, created_at, jira_email, jira_api_key, jira_url, webhook_url, webhook_functional, webhook_last_check, totp_secret, totp_pending, recovery_codes) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)")
+            .bind(id.to_hex())
+            .bind(new.username)
+            .bind(new.password)
+            .bind(new.created_at.to_rfc3339())
+            .bind(new.jira_email)
+            .bind(new.jira_api_key)
+            .bind(new.jira_url)
+            .bind(new.webhook_url)
+            .bind(new.webhook_functional.map(i64::from))
+            .bind(new.webhook_last_check)
+            .bind(new.totp_secret)
+            .bind(new.totp_pending)
+            .bind(new.recovery_codes.map(|codes| serde_json::to_string(&codes).unwrap()))
+            .execute(&self.pool)
+            .await {
+                Ok(_) => Ok(id),
+                Err(e) => Err(e.to_string())
+            }
+    }
+
+    /// Applies the same fields a MongoDB `$set` on the user document would.
+    pub async fn set_user(&self, id: ObjectId, fields: Document) -> Result<(), String> {
+        let mut assignments = vec![];
+        for (position, column) in fields.keys().enumerate() {
+            if !USER_COLUMNS.contains(&column.as_str()) {
+                return Err(format!("Unknown user field {}", column))
+            }
+            assignments.push(format!("{} = ${}", column, position + 1));
+        }
+        if assignments.is_empty() {
+            return Ok(())
+        }
+
+        let sql = format!("UPDATE users SET {} WHERE id = ${}", assignments.join(", "), fields.len() + 1);
+        let mut query = sqlx::query(&sql);
+        for (column, value) in fields.iter() {
+            query = match value {
+                Bson::String(text) => query.bind(text.clone()),
+                Bson::Boolean(flag) => query.bind(i64::from(*flag)),
+                Bson::Null if column == "webhook_functional" => query.bind(None::<i64>),
+                Bson::Null => query.bind(None::<String>),
+                Bson::Array(items) => {
+                    let items: Vec<&str> = items.iter().filter_map(|item| item.as_str()).collect();
+                    query.bind(serde_json::to_string(&items).unwrap())
+                },
+                other => return Err(format!("Unsupported value for user field {}: {}", column, other))
+            };
+        }
+        match query.bind(id.to_hex()).execute(&self.pool).await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(e.to_string())
+        }
+    }
+
+    /// Removes one recovery code, false when it was already used.
+    pub async fn pull_recovery_code(&self, id: ObjectId, hashed: &str) -> Result<bool, String> {
+        let user = match self.find_user(id).await? {
+            Some(user) => user,
+            None => return Ok(false)
+        };
+        let codes = user.recovery_codes.unwrap_or_default();
+        if !codes.iter().any(|code| code == hashed) {
+            return Ok(false)
+        }
+        let remaining: Vec<&String> = codes.iter().filter(|code| *code != hashed).collect();
+
+        // Only succeeds if nobody consumed a code in between
+        match sqlx::query("UPDATE users SET recovery_codes = $1 WHERE id = $2 AND recovery_codes = $3")
+            .bind(serde_json::to_string(&remaining).unwrap())
+            .bind(id.to_hex())
+            .bind(serde_json::to_string(&codes).unwrap())
+            .execute(&self.pool)
+            .await {
+                Ok(result) => Ok(result.rows_affected() == 1),
+                Err(e) => Err(e.to_string())
+            }
+    }
+
+    pub async fn spend_totp_step(&self, id: ObjectId, step: i64) -> Result<bool, String> {
+        // Only succeeds if no code of this or a later step was accepted in between
+        match sqlx::query("UPDATE users SET totp_step = $1 WHERE id = $2 AND (totp_step IS NULL OR totp_step < $3)")
+            .bind(step)
+            .bind(id.to_hex())
+            .bind(step)
+            .execute(&self.pool)
+            .await {
+                Ok(result) => Ok(result.rows_affected() == 1),
+                Err(e) => Err(e.to_string())
+            }
+    }
+
+    pub async fn delete_user(&self, id: ObjectId) -> Result<u64, String> {
+        match sqlx::query("DELETE FROM users WHERE id = $1")
+            .bind(id.to_hex())
+            .execute(&self.pool)
+            .await {
+                Ok(result) => Ok(result.rows_affected()),
+                Err(e) => Err(e.to_string())
+            }
+    }
+
+    /// The connector with id or, failing that, name `target`.
+    pub async fn find_connector(&self, workspace: &str, target: &str) -> Result<Option<Connector>, String> {
+        match sqlx::query("SELECT * FROM connectors WHERE workspace = $1 AND (id = $2 OR name = $2) ORDER BY CASE WHEN id = $2 THEN 0 ELSE 1 END")
+            .bind(workspace.to_string())
+            .bind(target.to_string())
+            .fetch_optional(&self.pool)
+            .await {
+                Ok(row) => row.as_ref().map(connector).transpose(),
+                Err(e) => Err(e.to_string())
+            }
+    }
+
+    pub async fn get_connectors(&self, workspace: &str) -> Result<Vec<Connector>, String> {
+        match sqlx::query("SELECT * FROM connectors WHERE workspace = $1 ORDER BY name")
+            .bind(workspace.to_string())
+            .fetch_all(&self.pool)
+            .await {
+                Ok(rows) => rows.iter().map(connector).collect(),
+                Err(e) => Err(e.to_string())
+            }
+    }
+
+    /// Active connectors of the workspace listening to `event` on the project, one indexed query.
+    pub async fn route_connectors(&self, workspace: &str, project_id: &str, event: &str, site: &str) -> Result<Vec<Connector>, String> {
+        match sqlx::query("SELECT c.* FROM connector_routes r JOIN connectors c ON c.workspace = r.workspace AND c.id = r.connector WHERE r.workspace = $1 AND r.project_id = $2 AND r.event = $3 AND r.site = $4 AND c.active = $5")
+            .bind(workspace.to_string())
+            .bind(project_id.to_string())
+            .bind(event.to_string())
+            .bind(site.to_string())
+            .bind(1_i64)
+            .fetch_all(&self.pool)
+            .await {
+                Ok(rows) => rows.iter().map(connector).collect(),
+                Err(e) => Err(e.to_string())
+            }
+    }
+
+    pub async fn insert_connector(&self, workspace: &str, con: &Connector) -> Result<(), String> {
+        let mut tx = self.pool.begin().await.map_err(|e| e.to_string())?;
+        let sql = format!("INSERT INTO connectors ({}) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17)", CONNECTOR_COLUMNS);
+        bind_connector(sqlx::query(&sql), workspace, con)
+            .execute(&mut *tx)
+            .await.map_err(|e| e.to_string())?;
+        insert_routes(&mut tx, workspace, con).await?;
+        tx.commit().await.map_err(|e| e.to_string())
+    }
+
+    /// Replaces the connector with `con`'s id and rebuilds its routes in one transaction,
+    /// provided it is still at version `expected`. Returns false when it isn't.
+    pub async fn replace_connector(&self, workspace: &str, con: &Connector, expected: i64) -> Result<bool, String> {
+        let mut tx = self.pool.begin().await.map_err(|e| e.to_string())?;
+        let updated = bind_connector(sqlx::query("UPDATE connectors SET name = $3, description = $4, token = $5, bot_id = $6, chatid = $7, thread_id = $8, topics = $9, active = $10, schedule = $11, duration = $12, project = $13, event = $14, created_at = $15, updated_at = $16, version = $17 WHERE id = $1 AND workspace = $2 AND version = $18"), workspace, con)
+            .bind(expected)
+            .execute(&mut *tx)
+            .await.map_err(|e| e.to_string())?;
+        if updated.rows_affected() == 0 {
+            return Ok(false)
+        }
+        sqlx::query("DELETE FROM connector_routes WHERE workspace = $1 AND connector = $2")
+            .bind(workspace.to_string())
+            .bind(con.id.clone())
+            .execute(&mut *tx)
+            .await.map_err(|e| e.to_string())?;
+        insert_routes(&mut tx, workspace, con).await?;
+        tx.commit().await.map(|_| true).map_err(|e| e.to_string())
+    }
+
+    /// Removes the connector with its routes and delivery log, its history stays.
+    pub async fn delete_connector(&self, workspace: &str, id: &str) -> Result<(), String> {
+        let mut tx = self.pool.begin().await.map_err(|e| e.to_string())?;
+        for sql in [
+            "DELETE FROM connector_routes WHERE workspace = $1 AND connector = $2",
+            "DELETE FROM delivery_logs WHERE workspace = $1 AND connector = $2",
+            "DELETE FROM connectors WHERE workspace = $1 AND id = $2"
+        ] {
+            sqlx::query(sql)
+                .bind(workspace.to_string())
+                .bind(id.to_string())
+                .execute(&mut *tx)
+                .await.map_err(|e| e.to_string())?;
+        }
+        tx.commit().await.map_err(|e| e.to_string())
+    }
+
+    /// Removes every connector, delivery log and connector history of a workspace.
+    pub async fn delete_workspace(&self, workspace: &str) -> Result<(), String> {
+        let mut tx = self.pool.begin().await.map_err(|e| e.to_string())?;
+        for sql in [
+            "DELETE FROM connector_routes WHERE workspace = $1",
+            "DELETE FROM delivery_logs WHERE workspace = $1",
+            "DELETE FROM connector_history WHERE workspace = $1",
+            "DELETE FROM connectors WHERE workspace = $1"
+        ] {
+            sqlx::query(sql)
+                .bind(workspace.to_string())
+                .execute(&mut *tx)
+                .await.map_err(|e| e.to_string())?;
+        }
+        tx.commit().await.map_err(|e| e.to_string())
+    }
+
+    /// `(workspace, id, sealed token)` of every connector, for key rotation.
+    pub async fn connector_tokens(&self) -> Result<Vec<(String, String, String)>, String> {
+        match sqlx::query("SELECT workspace, id, token FROM connectors").fetch_all(&self.pool).await {
+            Ok(rows) => rows.iter()
+                .map(|row| Ok((get(row, "workspace")?, get(row, "id")?, get(row, "token")?)))
+                .collect(),
+            Err(e) => Err(e.to_string())
+        }
+    }
+
+    pub async fn set_connector_token(&self, workspace: &str, id: &str, token: &str) -> Result<(), String> {
+        match sqlx::query("UPDATE connectors SET token = $1 WHERE workspace = $2 AND id = $3")
+            .bind(token.to_string())
+            .bind(workspace.to_string())
+            .bind(id.to_string())
+            .execute(&self.pool)
+            .await {
+                Ok(_) => Ok(()),
+                Err(e) => Err(e.to_string())
+            }
+    }
+
+    /// Revisions of a connector, oldest first.
+    pub async fn get_history(&self, workspace: &str, connector: &str) -> Result<Vec<Revision>, String> {
+        match sqlx::query("SELECT * FROM connector_history WHERE workspace = $1 AND connector = $2 ORDER BY version")
+            .bind(workspace.to_string())
+            .bind(connector.to_string())
+            .fetch_all(&self.pool)
+            .await {
+                Ok(rows) => rows.iter().map(revision).collect(),
+                Err(e) => Err(e.to_string())
+            }
+    }
+
+    pub async fn add_revision(&self, workspace: &str, revision: &Revision) -> Result<(), String> {
+        match insert_revision(&self.pool, workspace, revision).await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(e.to_string())
+        }
+    }
+
+    /// Replaces the whole history of a connector.
+    pub async fn replace_history(&self, workspace: &str, connector: &str, revisions: Vec<Revision>) -> Result<(), String> {
+        let mut tx = self.pool.begin().await.map_err(|e| e.to_string())?;
+        sqlx::query("DELETE FROM connector_history WHERE workspace = $1 AND connector = $2")
+            .bind(workspace.to_string())
+            .bind(connector.to_string())
+            .execute(&mut *tx)
+            .await.map_err(|e| e.to_string())?;
+        for revision in &revisions {
+            insert_revision(&mut *tx, workspace, revision).await.map_err(|e| e.to_string())?;
+        }
+        tx.commit().await.map_err(|e| e.to_string())
+    }
+
+    pub async fn get_logs(&self, workspace: &str, connector: &str) -> Result<Vec<Log>, String> {
+        match sqlx::query("SELECT event, status, attempt, time FROM delivery_logs WHERE workspace = $1 AND connector = $2 ORDER BY id")
+            .bind(workspace.to_string())
+            .bind(connector.to_string())
+            .fetch_all(&self.pool)
+            .await {
+                Ok(rows) => rows.iter()
+                    .map(|row| Ok(Log {
+                        event: get(row, "event")?,
+                        status: get(row, "status")?,
+                        attempt: get::<i64>(row, "attempt")? as i32,
+                        time: get(row, "time")?
+                    }))
+                    .collect(),
+                Err(e) => Err(e.to_string())
+            }
+    }
+
+    pub async fn add_log(&self, workspace: &str, connector: &str, log: Log) -> Result<(), String> {
+        match insert_log(&self.pool, workspace, connector, log).await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(e.to_string())
+        }
+    }
+
+    /// Replaces the whole delivery log of a connector.
+    pub async fn replace_logs(&self, workspace: &str, connector: &str, logs: Vec<Log>) -> Result<(), String> {
+        let mut tx = self.pool.begin().await.map_err(|e| e.to_string())?;
+        sqlx::query("DELETE FROM delivery_logs WHERE workspace = $1 AND connector = $2")
+            .bind(workspace.to_string())
+            .bind(connector.to_string())
+            .execute(&mut *tx)
+            .await.map_err(|e| e.to_string())?;
+        for log in logs {
+            insert_log(&mut *tx, workspace, connector, log).await.map_err(|e| e.to_string())?;
+        }
+        tx.commit().await.map_err(|e| e.to_string())
+    }
+}
+
+async fn insert_routes(tx: &mut sqlx::Transaction<'_, Any>, workspace: &str, con: &Connector) -> Result<(), String> {
+    for (project_id, event, site) in routes(workspace, con) {
+        sqlx::query("INSERT INTO connector_routes (workspace, connector, project_id, event, site) VALUES ($1, $2, $3, $4, $5)")
+            .bind(workspace.to_string())
+            .bind(con.id.clone())
+            .bind(project_id)
+            .bind(event)
+            .bind(site)
+            .execute(&mut **tx)
+            .await.map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+async fn insert_log<'c, E: sqlx::Executor<'c, Database = Any>>(executor: E, workspace: &str, connector: &str, log: Log) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT INTO delivery_logs (id, workspace, connector, event, status, attempt, time) VALUES ($1, $2, $3, $4, $5, $6, $7)")
+        .bind(ObjectId::new().to_hex())
+        .bind(workspace.to_string())
+        .bind(connector.to_string())
+        .bind(log.event)
+        .bind(log.status)
+        .bind(i64::from(log.attempt))
+        .bind(log.time)
+        .execute(executor)
+        .await
+        .map(|_| ())
+}
+
+async fn insert_revision<'c, E: sqlx::Executor<'c, Database = Any>>(executor: E, workspace: &str, revision: &Revision) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT INTO connector_history (workspace, connector, version, change, author, created_at, snapshot) VALUES ($1, $2, $3, $4, $5, $6, $7)")
+        .bind(workspace.to_string())
+        .bind(revision.connector.id.clone())
+        .bind(revision.version)
+        .bind(serde_json::to_value(revision.change).unwrap().as_str().unwrap_or_default().to_string())
+        .bind(revision.author.clone())
+        .bind(revision.created_at.to_rfc3339())
+        .bind(serde_json::to_string(&revision.connector).unwrap())
+        .execute(executor)
+        .await
+        .map(|_| ())
+}
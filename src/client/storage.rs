@@ -0,0 +1,104 @@
+use crate::{client, config};
+
+/// Backend holding connectors (`{id}/{name}.yml`), delivery logs (`{id}/{name}.csv`)
+/// and the `{id}/` folder of every user and workspace, picked by `storage` in the config.
+/// The SQL backend keeps connectors and logs in tables rather than objects, so the
+/// object methods below are only meaningful for the other two.
+#[derive(Clone)]
+pub enum Client {
+    S3(client::rusoto::Client),
+    Local(client::local::Client),
+    Sql(client::sql::Client)
+}
+
+const NO_OBJECTS: &str = "SQL storage does not keep objects";
+
+impl Client {
+    pub fn new() -> Self {
+        let config = config::get();
+        match config.storage {
+            config::Storage::S3 => Client::S3(client::rusoto::Client::new()),
+            config::Storage::Local => Client::Local(client::local::Client::new(&config.data_dir)),
+            config::Storage::Sql => Client::Sql(client::sql::Client::new())
+        }
+    }
+
+    pub async fn check(&self) -> Result<(), String> {
+        match self {
+            Client::S3(s3) => s3.check().await,
+            Client::Local(local) => local.check().await,
+            Client::Sql(sql) => sql.check().await
+        }
+    }
+
+    /// Creates the `{id}/` folder a user or workspace keeps its connectors in.
+    pub async fn create_folder(&self, id: &str) -> Result<(), String> {
+        match self {
+            Client::Sql(_) => Ok(()),
+            _ => self.put(&format!("{}/", id), Vec::new()).await
+        }
+    }
+
+    /// Deletes the `{id}/` folder with every connector and log in it, however many there are.
+    pub async fn delete_folder(&self, id: &str) -> Result<(), String> {
+        match self {
+            Client::Sql(sql) => sql.delete_workspace(id).await,
+            Client::S3(s3) => s3.delete_many(self.list(&format!("{}/", id)).await?).await,
+            Client::Local(local) => {
+                // Deepest first so folder markers are removed once emptied
+                for key in self.list(&format!("{}/", id)).await?.into_iter().rev() {
+                    local.delete(&key).await?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Lock of the `{id}/` folder shared with other processes, only the local backend has one.
+    pub async fn lock_folder(&self, id: &str) -> Result<Option<std::fs::File>, String> {
+        match self {
+            Client::Local(local) => local.lock(&format!("{}/", id)).await.map(Some),
+            _ => Ok(None)
+        }
+    }
+
+    pub async fn put(&self, key: &str, body: Vec<u8>) -> Result<(), String> {
+        match self {
+            Client::S3(s3) => s3.put(key, body).await,
+            Client::Local(local) => local.put(key, body).await,
+            Client::Sql(_) => Err(NO_OBJECTS.to_string())
+        }
+    }
+
+    pub async fn get(&self, key: &str) -> Result<Vec<u8>, String> {
+        match self {
+            Client::S3(s3) => s3.get(key).await,
+            Client::Local(local) => local.get(key).await,
+            Client::Sql(_) => Err(NO_OBJECTS.to_string())
+        }
+    }
+
+    pub async fn exists(&self, key: &str) -> bool {
+        match self {
+            Client::S3(s3) => s3.exists(key).await,
+            Client::Local(local) => local.exists(key).await,
+            Client::Sql(_) => false
+        }
+    }
+
+    pub async fn delete(&self, key: &str) -> Result<(), String> {
+        match self {
+            Client::S3(s3) => s3.delete(key).await,
+            Client::Local(local) => local.delete(key).await,
+            Client::Sql(_) => Err(NO_OBJECTS.to_string())
+        }
+    }
+
+    pub async fn list(&self, prefix: &str) -> Result<Vec<String>, String> {
+        match self {
+            Client::S3(s3) => s3.list(prefix).await,
+            Client::Local(local) => local.list(prefix).await,
+            Client::Sql(_) => Err(NO_OBJECTS.to_string())
+        }
+    }
+}
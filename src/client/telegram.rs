@@ -0,0 +1,23 @@
+use std::sync::{Mutex, OnceLock};
+
+use lru_cache::LruCache;
+use teloxide::Bot;
+
+/// Bot tokens whose client is kept around, beyond that the least recently used is dropped.
+const CACHED_BOTS: usize = 1000;
+
+static BOTS: OnceLock<Mutex<LruCache<String, Bot>>> = OnceLock::new();
+
+/// A `Bot` for the token. `Bot::new` builds a fresh HTTP client each time, reusing one
+/// per token keeps its connections to the Bot API open between messages.
+pub fn bot(token: &str) -> Bot {
+    let mut bots = BOTS.get_or_init(|| Mutex::new(LruCache::new(CACHED_BOTS)))
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(bot) = bots.get_mut(token) {
+        return bot.clone()
+    }
+    let bot = Bot::new(token);
+    bots.insert(token.to_string(), bot.clone());
+    bot
+}
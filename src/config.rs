@@ -0,0 +1,222 @@
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+
+use crate::errortype::ConfigError;
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Where connectors, delivery logs and workspace folders are kept.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Storage {
+    /// An S3-compatible bucket, see `bucket`, `region` and `endpoint`. Writes can't be made
+    /// conditional on the object they replace, so only one instance may serve a bucket.
+    #[default]
+    S3,
+    /// Files under `data_dir`, for single-node installs. Processes sharing the directory
+    /// take turns through its folder locks.
+    Local,
+    /// Tables in the `sql_url` database, any number of instances can share it.
+    Sql,
+}
+
+impl FromStr for Storage {
+    type Err = ConfigError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "s3" => Ok(Storage::S3),
+            "local" => Ok(Storage::Local),
+            "sql" => Ok(Storage::Sql),
+            _ => Err(ConfigError::Invalid(format!("storage '{}' must be s3, local or sql", value)))
+        }
+    }
+}
+
+/// Where user accounts are kept.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UserStore {
+    #[default]
+    Mongodb,
+    /// The `users` table in the `sql_url` database.
+    Sql,
+}
+
+impl FromStr for UserStore {
+    type Err = ConfigError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "mongodb" => Ok(UserStore::Mongodb),
+            "sql" => Ok(UserStore::Sql),
+            _ => Err(ConfigError::Invalid(format!("user_store '{}' must be mongodb or sql", value)))
+        }
+    }
+}
+
+/// Deployment settings. Values come from the YAML file named by `CONFIG_FILE`
+/// (`config.yml` when present), then environment variables override them.
+/// Secrets such as `JWT_SECRET` and the bucket keys stay in the environment.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Public base url of this API, Jira webhooks are pointed at `{public_url}/event/{id}`.
+    /// There is no default, every deployment has to name its own.
+    pub public_url: String,
+    pub bind_ip: String,
+    pub bind_port: u16,
+    pub mongodb_uri: String,
+    pub database: String,
+    pub storage: Storage,
+    /// Directory used by the local storage backend.
+    pub data_dir: String,
+    pub user_store: UserStore,
+    /// `postgres://...` or `sqlite://...`, used when `storage` or `user_store` is `sql`.
+    pub sql_url: String,
+    pub bucket: String,
+    pub region: String,
+    /// Leave empty for AWS, otherwise the url of the S3-compatible store.
+    pub endpoint: String,
+    /// Probe the storage backend for read and write access before serving requests.
+    pub check_storage: bool,
+    /// Number of (workspace, project, event, site) routes kept in memory, 0 disables the index.
+    pub route_cache_size: usize,
+    /// Seconds a route is trusted, bounds how long another instance's connector changes go unseen.
+    /// Several instances need `sql` storage, or `local` with a shared `data_dir` on one host.
+    pub route_cache_ttl: u64,
+    /// Addresses of reverse proxies whose `X-Forwarded-For`/`Forwarded` headers name the
+    /// client, for everyone else the connection's own address is the client's.
+    pub trusted_proxies: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            public_url: String::new(),
+            bind_ip: String::new(),
+            bind_port: 8082,
+            mongodb_uri: String::new(),
+            database: "telconnect".to_string(),
+            storage: Storage::S3,
+            data_dir: "data".to_string(),
+            user_store: UserStore::Mongodb,
+            sql_url: String::new(),
+            bucket: "atlassian-connector".to_string(),
+            region: "sgp1".to_string(),
+            endpoint: "https://sgp1.digitaloceanspaces.com".to_string(),
+            check_storage: true,
+            route_cache_size: 10000,
+            route_cache_ttl: 300,
+            trusted_proxies: vec![],
+        }
+    }
+}
+
+fn env(name: &str, target: &mut String) {
+    if let Ok(value) = std::env::var(name) {
+        *target = value;
+    }
+}
+
+impl Config {
+    pub fn load() -> Result<Self, ConfigError> {
+        let (path, required) = match std::env::var("CONFIG_FILE") {
+            Ok(path) => (path, true),
+            Err(_) => ("config.yml".to_string(), false)
+        };
+        let mut config = match std::fs::read_to_string(&path) {
+            Ok(text) => serde_yaml::from_str::<Config>(&text).map_err(|e| ConfigError::File(path.clone(), e.to_string()))?,
+            Err(e) if required => return Err(ConfigError::File(path, e.to_string())),
+            Err(_) => Config::default()
+        };
+
+        env("PUBLIC_URL", &mut config.public_url);
+        env("BIND_IP", &mut config.bind_ip);
+        env("MONGODB_URI", &mut config.mongodb_uri);
+        env("DATABASE_NAME", &mut config.database);
+        env("DATA_DIR", &mut config.data_dir);
+        env("DATABASE_URL", &mut config.sql_url);
+        env("BUCKET_NAME", &mut config.bucket);
+        env("BUCKET_REGION", &mut config.region);
+        env("BUCKET_ENDPOINT", &mut config.endpoint);
+        if let Ok(storage) = std::env::var("STORAGE") {
+            config.storage = storage.parse()?;
+        }
+        if let Ok(store) = std::env::var("USER_STORE") {
+            config.user_store = store.parse()?;
+        }
+        if let Ok(check) = std::env::var("CHECK_STORAGE") {
+            config.check_storage = check.parse().map_err(|_| ConfigError::Invalid(format!("CHECK_STORAGE '{}' must be true or false", check)))?;
+        }
+        if let Ok(size) = std::env::var("ROUTE_CACHE_SIZE") {
+            config.route_cache_size = size.parse().map_err(|_| ConfigError::Invalid(format!("ROUTE_CACHE_SIZE '{}' is not a number", size)))?;
+        }
+        if let Ok(ttl) = std::env::var("ROUTE_CACHE_TTL") {
+            config.route_cache_ttl = ttl.parse().map_err(|_| ConfigError::Invalid(format!("ROUTE_CACHE_TTL '{}' is not a number of seconds", ttl)))?;
+        }
+        if let Ok(proxies) = std::env::var("TRUSTED_PROXIES") {
+            config.trusted_proxies = proxies.split(',').map(|proxy| proxy.trim().to_string()).filter(|proxy| !proxy.is_empty()).collect();
+        }
+        if let Ok(port) = std::env::var("BIND_PORT") {
+            config.bind_port = port.parse().map_err(|_| ConfigError::Invalid(format!("BIND_PORT '{}' is not a port number", port)))?;
+        }
+
+        config.public_url = config.public_url.trim_end_matches('/').to_string();
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn uses_sql(&self) -> bool {
+        self.storage == Storage::Sql || self.user_store == UserStore::Sql
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.public_url.is_empty() {
+            return Err(ConfigError::Invalid("public_url must be set, Jira webhooks are registered against it".to_string()))
+        }
+        if !self.public_url.starts_with("https://") && !self.public_url.starts_with("http://") {
+            return Err(ConfigError::Invalid(format!("public_url '{}' must be an http(s) url", self.public_url)))
+        }
+        if self.storage == Storage::S3 {
+            if !self.endpoint.is_empty() && !self.endpoint.starts_with("https://") && !self.endpoint.starts_with("http://") {
+                return Err(ConfigError::Invalid(format!("endpoint '{}' must be an http(s) url", self.endpoint)))
+            }
+            if self.endpoint.is_empty() && rusoto_core::Region::from_str(&self.region).is_err() {
+                return Err(ConfigError::Invalid(format!("region '{}' is not an AWS region, set endpoint for other S3-compatible stores", self.region)))
+            }
+        }
+        if self.uses_sql() && !["postgres://", "postgresql://", "sqlite:"].iter().any(|scheme| self.sql_url.starts_with(scheme)) {
+            return Err(ConfigError::Invalid("sql_url must be a postgres:// or sqlite: url when storage or user_store is sql".to_string()))
+        }
+        let required = match self.storage {
+            Storage::S3 => vec![("bucket", &self.bucket), ("region", &self.region)],
+            Storage::Local => vec![("data_dir", &self.data_dir)],
+            Storage::Sql => vec![]
+        };
+        for (name, value) in [("bind_ip", &self.bind_ip), ("mongodb_uri", &self.mongodb_uri), ("database", &self.database)].into_iter().chain(required) {
+            if value.trim().is_empty() {
+                return Err(ConfigError::Invalid(format!("{} must be set", name)))
+            }
+        }
+        if let Some(proxy) = self.trusted_proxies.iter().find(|proxy| proxy.parse::<std::net::IpAddr>().is_err()) {
+            return Err(ConfigError::Invalid(format!("trusted_proxies entry '{}' is not an IP address", proxy)))
+        }
+        if self.database.contains(['/', '\\', '.', ' ', '"', '$']) {
+            return Err(ConfigError::Invalid(format!("database '{}' is not a valid MongoDB database name", self.database)))
+        }
+        Ok(())
+    }
+}
+
+pub fn init() -> Result<(), ConfigError> {
+    let config = Config::load()?;
+    let _ = CONFIG.set(config);
+    Ok(())
+}
+
+pub fn get() -> &'static Config {
+    CONFIG.get().expect("Configuration must be initialised at startup")
+}
@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use aes_gcm::{Aes256Gcm, Key, Nonce, aead::{Aead, AeadCore, KeyInit, OsRng}};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use sha2::{Digest, Sha256};
+
+use crate::errortype::CryptoError;
+
+const PREFIX: &str = "enc";
+
+static KEYRING: OnceLock<Keyring> = OnceLock::new();
+
+pub struct Keyring {
+    pub active: String,
+    keys: HashMap<String, Key<Aes256Gcm>>
+}
+
+impl Keyring {
+    /// Reads `ENCRYPTION_KEYS` (`id:base64key,...`) and the active `ENCRYPTION_KEY_ID`.
+    /// Keys that are no longer active stay in the list so older records can still be read.
+    pub fn from_env() -> Result<Self, CryptoError> {
+        let list = std::env::var("ENCRYPTION_KEYS").map_err(|_| CryptoError::Config("ENCRYPTION_KEYS must be defined".to_string()))?;
+        let active = std::env::var("ENCRYPTION_KEY_ID").map_err(|_| CryptoError::Config("ENCRYPTION_KEY_ID must be defined".to_string()))?;
+
+        let mut keys = HashMap::new();
+        for entry in list.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            let (id, encoded) = entry.split_once(':')
+                .ok_or_else(|| CryptoError::Config(format!("Encryption key entry '{}' must be id:base64key", entry)))?;
+            let bytes = STANDARD.decode(encoded)
+                .map_err(|_| CryptoError::Config(format!("Encryption key '{}' is not valid base64", id)))?;
+            if bytes.len() != 32 {
+                return Err(CryptoError::Config(format!("Encryption key '{}' must be 32 bytes", id)))
+            }
+            keys.insert(id.to_string(), *Key::<Aes256Gcm>::from_slice(&bytes));
+        }
+
+        if !keys.contains_key(&active) {
+            return Err(CryptoError::Config(format!("Active encryption key '{}' is not in ENCRYPTION_KEYS", active)))
+        }
+        Ok(Self { active, keys })
+    }
+
+    fn seal(key: &Key<Aes256Gcm>, plain: &[u8]) -> Result<String, CryptoError> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let mut sealed = nonce.to_vec();
+        sealed.extend(Aes256Gcm::new(key).encrypt(&nonce, plain).map_err(|_| CryptoError::Encrypt)?);
+        Ok(STANDARD.encode(sealed))
+    }
+
+    fn open(key: &Key<Aes256Gcm>, sealed: &str) -> Result<Vec<u8>, CryptoError> {
+        let bytes = STANDARD.decode(sealed).map_err(|_| CryptoError::Malformed)?;
+        if bytes.len() < 12 {
+            return Err(CryptoError::Malformed)
+        }
+        let (nonce, data) = bytes.split_at(12);
+        Aes256Gcm::new(key).decrypt(Nonce::from_slice(nonce), data).map_err(|_| CryptoError::Decrypt)
+    }
+
+    /// Encrypts with a fresh data key, which is itself wrapped by the active master key.
+    pub fn encrypt(&self, plain: &str) -> Result<String, CryptoError> {
+        let master = &self.keys[&self.active];
+        let data_key = Aes256Gcm::generate_key(&mut OsRng);
+
+        Ok(format!("{}:{}:{}:{}",
+            PREFIX,
+            self.active,
+            Self::seal(master, data_key.as_slice())?,
+            Self::seal(&data_key, plain.as_bytes())?
+        ))
+    }
+
+    /// Values without the `enc:` prefix are returned as-is so records written before encryption stay readable.
+    pub fn decrypt(&self, value: &str) -> Result<String, CryptoError> {
+        let parts: Vec<&str> = value.splitn(4, ':').collect();
+        if parts.len() != 4 || parts[0] != PREFIX {
+            return Ok(value.to_string())
+        }
+
+        let master = self.keys.get(parts[1]).ok_or_else(|| CryptoError::UnknownKey(parts[1].to_string()))?;
+        let data_key = Self::open(master, parts[2])?;
+        if data_key.len() != 32 {
+            return Err(CryptoError::Malformed)
+        }
+        let plain = Self::open(Key::<Aes256Gcm>::from_slice(&data_key), parts[3])?;
+        String::from_utf8(plain).map_err(|_| CryptoError::Malformed)
+    }
+
+    /// True when the value is plaintext or was sealed under a key other than the active one.
+    pub fn needs_rotation(&self, value: &str) -> bool {
+        match value.splitn(3, ':').collect::<Vec<&str>>()[..] {
+            [PREFIX, id, _] => id != self.active,
+            _ => !value.is_empty()
+        }
+    }
+}
+
+pub fn init() -> Result<(), CryptoError> {
+    let keyring = Keyring::from_env()?;
+    let _ = KEYRING.set(keyring);
+    Ok(())
+}
+
+/// Installs a throwaway keyring so tests elsewhere in the crate can seal and open values.
+#[cfg(test)]
+pub fn init_for_tests() {
+    let _ = KEYRING.set(Keyring { active: "test".to_string(), keys: HashMap::from([("test".to_string(), Aes256Gcm::generate_key(&mut OsRng))]) });
+}
+
+pub fn keyring() -> &'static Keyring {
+    KEYRING.get().expect("Encryption keyring must be initialised at startup")
+}
+
+pub fn encrypt(plain: &str) -> Result<String, CryptoError> {
+    if plain.is_empty() {
+        return Ok(String::new())
+    }
+    keyring().encrypt(plain)
+}
+
+pub fn decrypt(value: &str) -> Result<String, CryptoError> {
+    keyring().decrypt(value)
+}
+
+/// One-way hash for high-entropy values such as refresh tokens and recovery codes.
+pub fn digest(secret: &str) -> String {
+    hex::encode(Sha256::digest(secret.as_bytes()))
+}
+
+/// Masks a secret for display, keeping the bot id of Telegram tokens (`123456:AB…yz`).
+pub fn mask(secret: &str) -> String {
+    if secret.is_empty() {
+        return String::new()
+    }
+    let (prefix, rest) = match secret.split_once(':') {
+        Some((id, rest)) => (format!("{}:", id), rest),
+        None => (String::new(), secret)
+    };
+    let chars: Vec<char> = rest.chars().collect();
+    if chars.len() <= 4 {
+        return format!("{}…", prefix)
+    }
+    format!("{}{}…{}",
+        prefix,
+        chars[..2].iter().collect::<String>(),
+        chars[chars.len() - 2..].iter().collect::<String>()
+    )
+}
+
+/// An update keeps the stored secret when the client sends it back empty or in its masked form.
+pub fn is_unchanged(value: &str, current: &str) -> bool {
+    value.is_empty() || value == mask(current)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyring(active: &str, ids: &[&str]) -> Keyring {
+        Keyring {
+            active: active.to_string(),
+            keys: ids.iter().map(|id| (id.to_string(), *Key::<Aes256Gcm>::from_slice(&Sha256::digest(id.as_bytes())))).collect()
+        }
+    }
+
+    /// `enc:{key id}:{wrapped data key}:{sealed value}`
+    fn split(sealed: &str) -> Vec<String> {
+        sealed.splitn(4, ':').map(str::to_string).collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let keys = keyring("k1", &["k1"]);
+        let sealed = keys.encrypt("123456:secret-token").unwrap();
+        assert!(sealed.starts_with("enc:k1:"));
+        assert!(!sealed.contains("secret-token"));
+        assert_eq!(keys.decrypt(&sealed).unwrap(), "123456:secret-token");
+        // A fresh data key and nonce every time
+        assert_ne!(keys.encrypt("123456:secret-token").unwrap(), sealed);
+    }
+
+    #[test]
+    fn retired_keys_still_decrypt() {
+        let sealed = keyring("k1", &["k1"]).encrypt("value").unwrap();
+        let rotated = keyring("k2", &["k1", "k2"]);
+        assert_eq!(rotated.decrypt(&sealed).unwrap(), "value");
+        assert!(rotated.encrypt("value").unwrap().starts_with("enc:k2:"));
+    }
+
+    #[test]
+    fn unknown_key_id() {
+        let sealed = keyring("k1", &["k1"]).encrypt("value").unwrap();
+        assert_eq!(keyring("k2", &["k2"]).decrypt(&sealed), Err(CryptoError::UnknownKey("k1".to_string())));
+    }
+
+    #[test]
+    fn wrong_key_under_the_same_id() {
+        let sealed = keyring("k1", &["k1"]).encrypt("value").unwrap();
+        let other = Keyring { active: "k1".to_string(), keys: HashMap::from([("k1".to_string(), Aes256Gcm::generate_key(&mut OsRng))]) };
+        assert_eq!(other.decrypt(&sealed), Err(CryptoError::Decrypt));
+    }
+
+    #[test]
+    fn tampered_ciphertext_fails() {
+        let keys = keyring("k1", &["k1"]);
+        let mut parts = split(&keys.encrypt("value").unwrap());
+        let mut bytes = STANDARD.decode(&parts[3]).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        parts[3] = STANDARD.encode(bytes);
+        assert_eq!(keys.decrypt(&parts.join(":")), Err(CryptoError::Decrypt));
+
+        // Swapping in the data key of another value fails too
+        let mut other = split(&keys.encrypt("value").unwrap());
+        other[2] = split(&keys.encrypt("other").unwrap())[2].clone();
+        assert_eq!(keys.decrypt(&other.join(":")), Err(CryptoError::Decrypt));
+    }
+
+    #[test]
+    fn malformed_ciphertext() {
+        let keys = keyring("k1", &["k1"]);
+        assert_eq!(keys.decrypt("enc:k1:not base64:also not"), Err(CryptoError::Malformed));
+        assert_eq!(keys.decrypt(&format!("enc:k1:{}:{}", STANDARD.encode([0u8; 4]), STANDARD.encode([0u8; 4]))), Err(CryptoError::Malformed));
+    }
+
+    #[test]
+    fn plaintext_passes_through() {
+        let keys = keyring("k1", &["k1"]);
+        for legacy in ["123456:legacy-token", "plain", "", "enc:k1:only-three"] {
+            assert_eq!(keys.decrypt(legacy).unwrap(), legacy);
+        }
+    }
+
+    #[test]
+    fn rotation_is_needed_for_plaintext_and_retired_keys() {
+        let old = keyring("k1", &["k1"]).encrypt("value").unwrap();
+        let keys = keyring("k2", &["k1", "k2"]);
+        assert!(keys.needs_rotation(&old));
+        assert!(keys.needs_rotation("123456:legacy-token"));
+        assert!(!keys.needs_rotation(&keys.encrypt("value").unwrap()));
+        assert!(!keys.needs_rotation(""));
+    }
+
+    #[test]
+    fn masking_keeps_the_bot_id() {
+        assert_eq!(mask("123456:ABCDEFGHIJ"), "123456:AB…IJ");
+        assert_eq!(mask("123456:ABCD"), "123456:…");
+        assert_eq!(mask("secretvalue"), "se…ue");
+        assert_eq!(mask(""), "");
+    }
+
+    #[test]
+    fn masked_or_empty_values_are_unchanged() {
+        let current = "123456:ABCDEFGHIJ";
+        assert!(is_unchanged("", current));
+        assert!(is_unchanged(&mask(current), current));
+        assert!(!is_unchanged("123456:ABCDEFGHIK", current));
+        assert!(!is_unchanged("654321:AB…IJ", current));
+    }
+}
@@ -9,6 +9,9 @@ pub enum JiraError {
     #[error("{0}")] ErrorMessage(String),
     #[error("Incorect email address")] EmailError,
     #[error("Incorect api key")] ApiKeyError,
+    #[error("Jira OAuth is not configured on this server")] OAuthNotConfigured,
+    #[error("Jira authorization has expired, please connect your Jira site again")] OAuthExpired,
+    #[error("Jira credentials are not set up for this workspace")] NoCredentials,
 }
 
 #[derive(Error, Debug, PartialEq, Eq)]
@@ -17,7 +20,48 @@ pub enum ConnectorError {
     #[error("Connector name already exist")] ConCreateExist,
     #[error("Cannot update to new name [Connector name already exist]")] ConUpdateExist,
     #[error("Connectors list is empty")] ConEmpty,
+    #[error("Connector name invalid [must not be empty or shaped like a connector id]")] ConNameInval,
+    #[error("Connector was changed by someone else [fetch it again and retry]")] ConConflict,
+    #[error("Connector patch invalid [{0}]")] ConPatchInval(String),
+    #[error("Version {0} not found in the connector history")] ConVersionNotFound(i64),
     #[error("Bot token invalid")] TokenInval,
     #[error("Chatid invalid [Bot not invited to chatid]")] ChatidInval,
+    #[error("Chat is not a forum [Topics must be enabled to use a thread id]")] ChatNotForum,
+    #[error("Topic {0} invalid [Topic not found or bot cannot post in it]")] TopicInval(i32),
+    #[error("Bot not found in registry")] BotNotFound,
     #[error("Bucket error {0}")] RusError(String),
+    #[error("Database error {0}")] DbError(String),
+    #[error("Secret error {0}")] SecretError(String),
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum BotError {
+    #[error("Bot not found")] BotNotFound,
+    #[error("Bot name already exist")] BotCreateExist,
+    #[error("Bot token invalid")] TokenInval,
+    #[error("Bot is still used by connector(s): {0}")] BotInUse(String),
+    #[error("Database error {0}")] DbError(String),
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ConfigError {
+    #[error("Config file {0}: {1}")] File(String, String),
+    #[error("Invalid config: {0}")] Invalid(String),
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum CryptoError {
+    #[error("Encryption config error: {0}")] Config(String),
+    #[error("Encryption key {0} not found")] UnknownKey(String),
+    #[error("Failed to encrypt secret")] Encrypt,
+    #[error("Failed to decrypt secret")] Decrypt,
+    #[error("Encrypted secret is malformed")] Malformed,
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum LoginError {
+    #[error("User Not Found")] UserNotFound,
+    #[error("Invalid password")] InvalidPassword,
+    #[error("Invalid two-factor code")] InvalidCode,
+    #[error("{0}")] Other(String),
 }
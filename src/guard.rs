@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use actix_web::HttpRequest;
+
+use crate::config;
+
+struct Attempts {
+    failures: u32,
+    last: Instant,
+    locked_until: Option<Instant>
+}
+
+pub struct Lockout {
+    pub scope: &'static str,
+    pub key: String,
+    pub duration: Duration
+}
+
+/// An attempt `begin` let through. It already counts as a failure, `record_success` takes
+/// that back. `lockouts` are the lockouts it triggered, in effect unless it succeeds.
+pub struct Attempt {
+    pub delay: Duration,
+    pub lockouts: Vec<Lockout>
+}
+
+pub struct LoginGuard {
+    attempts: Mutex<HashMap<String, Attempts>>,
+    max_user_failures: u32,
+    max_ip_failures: u32,
+    max_code_failures: u32,
+    lockout: Duration
+}
+
+fn env_or(name: &str, default: u64) -> u64 {
+    std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+/// The address of the client. Forwarded headers are only believed when the connection
+/// comes from one of the configured `trusted_proxies`, anyone else could pick their own.
+pub fn client_ip(req: &HttpRequest) -> String {
+    let peer = match req.peer_addr() {
+        Some(peer) => peer.ip(),
+        None => return "unknown".to_string()
+    };
+    let trusted = config::get().trusted_proxies.iter().any(|proxy| proxy.parse::<IpAddr>().is_ok_and(|proxy| proxy == peer));
+    if trusted {
+        if let Some(ip) = req.connection_info().realip_remote_addr() {
+            return ip.to_string()
+        }
+    }
+    peer.to_string()
+}
+
+/// What a lockout is reported as, a challenge is a credential and is named by its user.
+fn display(prefix: &str, key: &str, username: &str) -> String {
+    match prefix {
+        "mfa" => username.to_string(),
+        _ => key.to_string()
+    }
+}
+
+impl LoginGuard {
+    pub fn new() -> Self {
+        Self {
+            attempts: Mutex::new(HashMap::new()),
+            max_user_failures: env_or("LOGIN_MAX_FAILURES", 5) as u32,
+            max_ip_failures: env_or("LOGIN_MAX_IP_FAILURES", 20) as u32,
+            max_code_failures: env_or("LOGIN_MAX_CODE_FAILURES", 3) as u32,
+            lockout: Duration::from_secs(env_or("LOGIN_LOCKOUT_MINUTES", 15) * 60)
+        }
+    }
+
+    /// Checks and counts an attempt in one step, so concurrent attempts can't all get in
+    /// before the first failure is recorded. `challenge` is the two-factor challenge the
+    /// attempt answers, it is given up after `LOGIN_MAX_CODE_FAILURES` wrong codes. Returns
+    /// the lockout in effect, with its remaining time, when locked.
+    pub fn begin(&self, username: &str, ip: &str, challenge: Option<&str>) -> Result<Attempt, Lockout> {
+        let now = Instant::now();
+        let mut attempts = self.attempts.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let lockout = self.lockout;
+        attempts.retain(|_, entry| now.duration_since(entry.last) < lockout || entry.locked_until.is_some_and(|until| until > now));
+
+        let mut keys = vec![("user", "username", username, self.max_user_failures), ("ip", "ip", ip, self.max_ip_failures)];
+        if let Some(challenge) = challenge {
+            keys.push(("mfa", "challenge", challenge, self.max_code_failures));
+        }
+        for (prefix, scope, key, _) in &keys {
+            if let Some(until) = attempts.get(&format!("{}:{}", prefix, key)).and_then(|entry| entry.locked_until).filter(|until| *until > now) {
+                return Err(Lockout { scope, key: display(prefix, key, username), duration: until - now })
+            }
+        }
+
+        let mut failures = 0;
+        let mut lockouts = Vec::new();
+        for (prefix, scope, key, max) in keys {
+            let entry = attempts.entry(format!("{}:{}", prefix, key)).or_insert(Attempts {
+                failures: 0,
+                last: now,
+                locked_until: None
+            });
+            if now.duration_since(entry.last) >= lockout || entry.locked_until.is_some() {
+                entry.failures = 0;
+                entry.locked_until = None;
+            }
+            failures = failures.max(entry.failures);
+            entry.failures += 1;
+            entry.last = now;
+
+            if entry.failures >= max {
+                entry.locked_until = Some(now + lockout);
+                lockouts.push(Lockout { scope, key: display(prefix, key, username), duration: lockout });
+            }
+        }
+
+        // 0, 0.5s, 1s, 2s, ... capped at 8s
+        Ok(Attempt {
+            delay: match failures {
+                0 => Duration::ZERO,
+                n => Duration::from_millis(500 * 2u64.pow((n - 1).min(4)))
+            },
+            lockouts
+        })
+    }
+
+    /// Clears the user's failures and takes back the failure `begin` counted for the address.
+    pub fn record_success(&self, username: &str, ip: &str) {
+        let mut attempts = self.attempts.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        attempts.remove(&format!("user:{}", username));
+        self.refund(&mut attempts, format!("ip:{}", ip), self.max_ip_failures);
+    }
+
+    /// Takes back the failures `begin` counted for an attempt that didn't fail, like a
+    /// correct password still waiting for its second factor. Earlier failures stay.
+    pub fn release(&self, username: &str, ip: &str) {
+        let mut attempts = self.attempts.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        self.refund(&mut attempts, format!("user:{}", username), self.max_user_failures);
+        self.refund(&mut attempts, format!("ip:{}", ip), self.max_ip_failures);
+    }
+
+    fn refund(&self, attempts: &mut HashMap<String, Attempts>, key: String, max: u32) {
+        if let Some(entry) = attempts.get_mut(&key) {
+            entry.failures = entry.failures.saturating_sub(1);
+            if entry.failures < max {
+                entry.locked_until = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn guard() -> LoginGuard {
+        LoginGuard {
+            attempts: Mutex::new(HashMap::new()),
+            max_user_failures: 3,
+            max_ip_failures: 5,
+            max_code_failures: 2,
+            lockout: Duration::from_secs(60)
+        }
+    }
+
+    #[test]
+    fn attempts_count_before_their_outcome() {
+        let guard = guard();
+        // A burst in flight at once, none has failed yet
+        let burst: Vec<_> = (0..5).map(|_| guard.begin("alice", "10.0.0.1", None)).collect();
+        assert_eq!(burst.iter().filter(|attempt| attempt.is_ok()).count(), 3);
+        assert_eq!(burst[2].as_ref().ok().unwrap().lockouts[0].scope, "username");
+        assert_eq!(burst[3].as_ref().err().unwrap().scope, "username");
+    }
+
+    #[test]
+    fn delay_grows_with_failures() {
+        let guard = guard();
+        assert_eq!(guard.begin("alice", "10.0.0.1", None).ok().unwrap().delay, Duration::ZERO);
+        assert_eq!(guard.begin("alice", "10.0.0.1", None).ok().unwrap().delay, Duration::from_millis(500));
+    }
+
+    #[test]
+    fn success_and_release_take_the_attempt_back() {
+        let guard = guard();
+        guard.begin("alice", "10.0.0.1", None).ok().unwrap();
+        guard.begin("alice", "10.0.0.1", None).ok().unwrap();
+        // A correct password waiting for its second factor keeps the earlier failure
+        guard.release("alice", "10.0.0.1");
+        assert_eq!(guard.begin("alice", "10.0.0.1", None).ok().unwrap().delay, Duration::from_millis(500));
+        // Clears the user, the address keeps its first failure
+        guard.record_success("alice", "10.0.0.1");
+        assert_eq!(guard.begin("alice", "10.0.0.2", None).ok().unwrap().delay, Duration::ZERO);
+        assert_eq!(guard.begin("bob", "10.0.0.1", None).ok().unwrap().delay, Duration::from_millis(500));
+    }
+
+    #[test]
+    fn addresses_lock_across_usernames() {
+        let guard = guard();
+        for user in ["a", "b", "c", "d", "e"] {
+            assert!(guard.begin(user, "10.0.0.1", None).is_ok());
+        }
+        assert_eq!(guard.begin("f", "10.0.0.1", None).err().unwrap().scope, "ip");
+        assert!(guard.begin("f", "10.0.0.2", None).is_ok());
+    }
+
+    #[test]
+    fn challenges_are_given_up_after_wrong_codes() {
+        let guard = guard();
+        let attempt = guard.begin("alice", "10.0.0.1", Some("token")).ok().unwrap();
+        assert!(attempt.lockouts.is_empty());
+        let attempt = guard.begin("alice", "10.0.0.2", Some("token")).ok().unwrap();
+        // Reported by user, the challenge itself is a credential
+        assert_eq!((attempt.lockouts[0].scope, attempt.lockouts[0].key.as_str()), ("challenge", "alice"));
+        assert_eq!(guard.begin("alice", "10.0.0.3", Some("token")).err().unwrap().scope, "challenge");
+        // A new challenge from logging in again is still answerable
+        assert!(guard.begin("alice", "10.0.0.3", Some("other")).is_ok());
+    }
+}
@@ -7,9 +7,14 @@ mod errortype;
 mod actions;
 mod client;
 mod middleware;
+mod config;
+mod crypto;
+mod password;
+mod guard;
+mod twofactor;
 extern crate serde_json;
 
-use routes::{jira, connector, event, user, log};
+use routes::{jira, connector, event, user, log, history, bot, apikey, workspace, site};
 use dotenv::dotenv;
 use actix_cors::Cors;
 use middleware::validator;
@@ -21,22 +26,98 @@ async fn main() -> std::io::Result<()>{
     std::env::set_var("RUST_LOG", "debug");
     env_logger::init();
 
+    if let Err(e) = config::init() {
+        panic!("{}", e)
+    }
+
+    if let Err(e) = crypto::init() {
+        panic!("{}", e)
+    }
+
+    if config::get().uses_sql() {
+        if let Err(e) = client::sql::init().await {
+            panic!("{}", e)
+        }
+    }
+
+    if std::env::args().nth(1).as_deref() == Some("rotate-keys") {
+        let db = client::storage::Client::new();
+        let mongodb = mongodb::Client::with_uri_str(&config::get().mongodb_uri).await
+            .expect("Failed to connect to MongoDB");
+        match actions::secret::rotate_keys(&db, &mongodb).await {
+            Ok(summary) => println!("{}", summary),
+            Err(e) => return Err(std::io::Error::other(e))
+        }
+        return Ok(())
+    }
+
+    // migrate-storage [--dry-run] [--from s3|local] copies connectors, logs and history into the SQL backend
+    if std::env::args().nth(1).as_deref() == Some("migrate-storage") {
+        let args: Vec<String> = std::env::args().skip(2).collect();
+        if config::get().storage != config::Storage::Sql {
+            return Err(std::io::Error::other("migrate-storage copies into the SQL backend, set storage to sql"))
+        }
+        let source = match args.iter().position(|arg| arg == "--from").map(|at| args.get(at + 1).map(String::as_str)) {
+            None | Some(Some("s3")) => client::storage::Client::S3(client::rusoto::Client::new()),
+            Some(Some("local")) => client::storage::Client::Local(client::local::Client::new(&config::get().data_dir)),
+            Some(other) => return Err(std::io::Error::other(format!("Unknown source {:?}, use s3 or local", other.unwrap_or_default())))
+        };
+        let dry_run = args.iter().any(|arg| arg == "--dry-run");
+        match actions::migrate::migrate_storage(&source, &client::sql::Client::new(), dry_run).await {
+            Ok(summary) => println!("{}", summary),
+            Err(e) => return Err(std::io::Error::other(e))
+        }
+        return Ok(())
+    }
+
+    // bench-delivery [connectors] [latency_ms] times connector loading and fan-out delivery
+    if std::env::args().nth(1).as_deref() == Some("bench-delivery") {
+        let count = std::env::args().nth(2).map(|arg| arg.parse::<usize>()).unwrap_or(Ok(50))
+            .map_err(|_| std::io::Error::other("connectors must be a number"))?;
+        let latency = std::env::args().nth(3).map(|arg| arg.parse::<u64>()).unwrap_or(Ok(50))
+            .map_err(|_| std::io::Error::other("latency_ms must be a number"))?;
+        match actions::bench::bench_delivery(&client::storage::Client::new(), count, std::time::Duration::from_millis(latency)).await {
+            Ok(summary) => println!("{}", summary),
+            Err(e) => return Err(std::io::Error::other(e))
+        }
+        return Ok(())
+    }
+
+    if config::get().check_storage {
+        if let Err(e) = client::storage::Client::new().check().await {
+            panic!("{}", e)
+        }
+    }
+
+    // Connectors still under name-based keys stay unlisted until a later start moves them
+    match actions::connector::upgrade_keys(&client::storage::Client::new()).await {
+        Ok(0) => {},
+        Ok(moved) => println!("Moved {} connector(s) to id-based storage keys", moved),
+        Err(e) => println!("Failed to move connectors to id-based storage keys, retrying on the next start: {}", e)
+    }
+
     let jwt_secret = std::env::var("JWT_SECRET").expect("JWT_SECRET must be defined");
     
-    let bind_ip = std::env::var("BIND_IP").expect("BIND_IP must be defined");
-    let bind_port = std::env::var("BIND_PORT").expect("BIND_PORT must be defined").parse::<u16>().unwrap_or(8082);
+    let bind_ip = config::get().bind_ip.clone();
+    let bind_port = config::get().bind_port;
+
+    let login_guard = Data::new(guard::LoginGuard::new());
 
     HttpServer::new(move || {
         let middleware = HttpAuthentication::bearer(validator);
         App::new()
             .wrap(Cors::permissive())
             .wrap(Logger::default())
-            .app_data(Data::new(client::rusoto::Client::new()))
+            .app_data(Data::new(client::storage::Client::new()))
             .app_data(Data::new(client::jira::Client::new()))
             .app_data(Data::new(client::mongodb::Client::new()))
             .app_data(Data::new(String::from(&jwt_secret)))
+            .app_data(login_guard.clone())
             .service(resource("/register").route(web::post().to(user::register)))
             .service(resource("/login").route(web::get().to(user::login)))
+            .service(resource("/login/2fa").route(web::post().to(user::login_second_factor)))
+            .service(resource("/refresh").route(web::post().to(user::refresh)))
+            .service(resource("/jira/oauth/callback").route(web::get().to(jira::oauth_callback)))
 
             .service( web::scope("/event")
                 .route("{id}", web::post().to(event::post))
@@ -50,10 +131,28 @@ async fn main() -> std::io::Result<()>{
                     .route("{name}", web::get().to(connector::get_one))
                     .route("{name}", web::delete().to(connector::delete))
                     .route("{name}", web::put().to(connector::update))
+                    .route("{name}", web::patch().to(connector::patch))
+                )
+                .service( web::scope("/bot")
+                    .route("", web::post().to(bot::post))
+                    .route("", web::get().to(bot::get))
+                    .route("{id}", web::get().to(bot::get_one))
+                    .route("{id}", web::put().to(bot::update))
+                    .route("{id}", web::delete().to(bot::delete))
+                )
+                .service( web::scope("/apikey")
+                    .route("", web::post().to(apikey::post))
+                    .route("", web::get().to(apikey::get))
+                    .route("{id}", web::delete().to(apikey::delete))
                 )
                 .service( web::scope("/log")
                     .route("{name}", web::get().to(log::get))
                 )
+                .service( web::scope("/history")
+                    .route("{name}", web::get().to(history::get))
+                    .route("{name}/diff", web::get().to(history::diff))
+                    .route("{name}/restore/{version}", web::post().to(history::restore))
+                )
                 .service(resource("/projects").route(web::get().to(jira::get_project)))
                 .service(web::scope("/webhook")
                     .route("", web::post().to(jira::post_webhook))
@@ -61,9 +160,44 @@ async fn main() -> std::io::Result<()>{
                     .route("", web::get().to(jira::check_webhook))
                     .route("/repair", web::get().to(jira::put_webhook))
                 )
+                .service( web::scope("/site")
+                    .route("", web::get().to(site::get))
+                    .route("", web::post().to(site::post))
+                    .route("{id}", web::get().to(site::get_one))
+                    .route("{id}", web::delete().to(site::delete))
+                    .route("{id}/projects", web::get().to(site::get_projects))
+                    .route("{id}/webhook", web::get().to(site::check_webhook))
+                    .route("{id}/repair", web::get().to(site::put_webhook))
+                )
+                .service(web::scope("/jira/oauth")
+                    .route("/authorize", web::get().to(jira::oauth_authorize))
+                    .route("", web::delete().to(jira::oauth_disconnect))
+                )
+                .service( web::scope("/logout")
+                    .route("", web::post().to(user::logout))
+                    .route("/all", web::post().to(user::logout_all))
+                )
+                .service( web::scope("/workspace")
+                    .route("", web::get().to(workspace::get))
+                    .route("", web::post().to(workspace::post))
+                    .route("{id}", web::get().to(workspace::get_one))
+                    .route("{id}", web::delete().to(workspace::delete))
+                    .route("{id}/members/{member}", web::put().to(workspace::update_member))
+                    .route("{id}/members/{member}", web::delete().to(workspace::delete_member))
+                    .route("{id}/invitations", web::post().to(workspace::invite))
+                )
+                .service( web::scope("/invitation")
+                    .route("", web::get().to(workspace::get_invitations))
+                    .route("{id}/accept", web::post().to(workspace::accept_invitation))
+                    .route("{id}", web::delete().to(workspace::decline_invitation))
+                )
                 .service( web::scope("/user")
                     .route("", web::get().to(user::get))
                     .route("", web::delete().to(user::delete))
+                    .route("/password", web::put().to(user::change_password))
+                    .route("/2fa", web::post().to(user::enroll_totp))
+                    .route("/2fa/verify", web::post().to(user::confirm_totp))
+                    .route("/2fa", web::delete().to(user::disable_totp))
                 )
             )
             
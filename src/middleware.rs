@@ -1,4 +1,4 @@
-use actix_web::{dev::ServiceRequest, error::Error, HttpMessage, HttpResponse, web::{ReqData, self}};
+use actix_web::{dev::ServiceRequest, error::{Error, ErrorBadRequest, ErrorForbidden}, http::Method, HttpMessage, HttpResponse, web::{ReqData, self}};
 use actix_web_httpauth::{extractors::{  
     bearer::{self, BearerAuth},
     AuthenticationError,}
@@ -7,16 +7,110 @@ use actix_web_httpauth::{extractors::{
 use jsonwebtoken::{decode, Algorithm, Validation, DecodingKey, TokenData, errors::Error as JwtError};
 use serde::{Serialize, Deserialize};
 
-use crate::{client, models::user::User};
+use std::str::FromStr;
+
+use crate::{client, models::{user::User, apikey::API_KEY_PREFIX, workspace::{Workspace, Role}}};
 
 #[derive(Debug, Serialize, Deserialize, Clone)] 
 pub struct Claims{
     pub id: mongodb::bson::oid::ObjectId,
+    pub sid: mongodb::bson::oid::ObjectId,
+    pub jti: String,
+    pub exp: usize,
+
+    /// Workspace picked with the `X-Workspace` header, never part of the signed token
+    #[serde(default, skip_serializing)]
+    pub workspace: Option<mongodb::bson::oid::ObjectId>
+}
+
+pub struct Access{
+    pub user: User,
+    pub workspace: Workspace,
+    pub role: Role
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MfaClaims{
+    pub id: mongodb::bson::oid::ObjectId,
+    pub username: String,
+    pub purpose: String,
     pub exp: usize
 }
 
+pub fn decode_mfa(token: &str, secret: &str) -> Option<MfaClaims> {
+    match decode::<MfaClaims>(token, &DecodingKey::from_secret(secret.as_ref()), &Validation::new(Algorithm::HS256)) {
+        Ok(data) if data.claims.purpose == "mfa" => Some(data.claims),
+        _ => None
+    }
+}
+
+/// Cookie holding the nonce of the OAuth flow the browser started.
+pub const OAUTH_COOKIE: &str = "jira_oauth_nonce";
+
+/// `state` parameter of the Jira OAuth authorize redirect, ties the callback to a workspace.
+/// `nonce` must match the `OAUTH_COOKIE` set on the browser that started the flow.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OAuthState{
+    pub workspace: mongodb::bson::oid::ObjectId,
+    pub user: mongodb::bson::oid::ObjectId,
+    pub nonce: String,
+    pub purpose: String,
+    pub exp: usize
+}
+
+pub fn decode_oauth_state(token: &str, secret: &str) -> Option<OAuthState> {
+    match decode::<OAuthState>(token, &DecodingKey::from_secret(secret.as_ref()), &Validation::new(Algorithm::HS256)) {
+        Ok(data) if data.claims.purpose == "jira_oauth" => Some(data.claims),
+        _ => None
+    }
+}
+
+/// Scope an API key needs for a route; `None` means the route is only reachable with a login token.
+fn required_scope(method: &Method, path: &str) -> Option<&'static str> {
+    let read = method == Method::GET;
+    match path.trim_start_matches('/').split('/').next().unwrap_or("") {
+        "connector" => Some(if read { "connectors:read" } else { "connectors:write" }),
+        "log" if read => Some("logs:read"),
+        "history" => Some(if read { "connectors:read" } else { "connectors:write" }),
+        "bot" => Some(if read { "bots:read" } else { "bots:write" }),
+        "projects" if read => Some("jira:read"),
+        "webhook" => Some(if read && path.trim_end_matches('/') == "/webhook" { "jira:read" } else { "jira:write" }),
+        "site" => Some(if read && !path.trim_end_matches('/').ends_with("/repair") { "jira:read" } else { "jira:write" }),
+        _ => None
+    }
+}
+
 pub async fn validator(req: ServiceRequest, credentials: BearerAuth) -> Result<ServiceRequest, (Error, ServiceRequest)> {
     let token_string = credentials.token();
+    let mongodb = req.app_data::<web::Data<client::mongodb::Client>>().cloned();
+    let workspace = match req.headers().get("X-Workspace").map(|value| value.to_str().ok().and_then(|v| mongodb::bson::oid::ObjectId::from_str(v).ok())) {
+        Some(Some(workspace)) => Some(workspace),
+        Some(None) => return Err((ErrorBadRequest("X-Workspace must be a workspace id"), req)),
+        None => None
+    };
+
+    if token_string.starts_with(API_KEY_PREFIX) {
+        let key = match &mongodb {
+            Some(mongodb) => mongodb.find_api_key(token_string).await,
+            None => None
+        };
+        if let Some(key) = key {
+            return match required_scope(req.method(), req.path()) {
+                Some(scope) if key.scopes.iter().any(|s| s == scope) => {
+                    req.extensions_mut().insert(Claims {
+                        id: key.owner,
+                        sid: key.id,
+                        jti: "api_key".to_string(),
+                        exp: 0,
+                        workspace
+                    });
+                    Ok(req)
+                },
+                Some(scope) => Err((ErrorForbidden(format!("API key is missing the {} scope", scope)), req)),
+                None => Err((ErrorForbidden("API keys cannot access this resource"), req))
+            }
+        }
+    }
     let secret = std::env::var("JWT_SECRET").expect("JWT_SECRET must be defined");
 
     let decoded: Result<TokenData<Claims>, JwtError> = decode::<Claims>(
@@ -24,23 +118,28 @@ pub async fn validator(req: ServiceRequest, credentials: BearerAuth) -> Result<S
         &DecodingKey::from_secret(secret.as_str().as_ref()),
         &Validation::new(Algorithm::HS256),
     );
-    match decoded {
-        Ok(token) => {
-            req.extensions_mut().insert(token.claims);
-            Ok(req)
-        }
-        Err(_) => {
-            let config = req
-                .app_data::<bearer::Config>()
-                .cloned()
-                .unwrap_or_default()
-                .scope("");
-            Err((AuthenticationError::from(config).into(), req))
+    if let Ok(token) = decoded {
+        let active = match &mongodb {
+            Some(mongodb) => mongodb.session_active(&token.claims.sid, &token.claims.jti).await,
+            None => false
+        };
+        if active {
+            let mut claims = token.claims;
+            claims.workspace = workspace;
+            req.extensions_mut().insert(claims);
+            return Ok(req)
         }
     }
+
+    let config = req
+        .app_data::<bearer::Config>()
+        .cloned()
+        .unwrap_or_default()
+        .scope("");
+    Err((AuthenticationError::from(config).into(), req))
 }
 
-pub async fn validate(req_user: Option<ReqData<Claims>>, mongodb: &web::Data<client::mongodb::Client>) -> Result<User, actix_web::HttpResponse> {
+pub async fn validate_user(req_user: Option<ReqData<Claims>>, mongodb: &web::Data<client::mongodb::Client>) -> Result<User, actix_web::HttpResponse> {
     match req_user {
         Some(user) => {
             match mongodb.get_one_user(user.id.to_hex()).await {
@@ -56,4 +155,30 @@ pub async fn validate(req_user: Option<ReqData<Claims>>, mongodb: &web::Data<cli
         }, 
         None => return Err(HttpResponse::Unauthorized().json("Must be logged in"))
     }    
-}
\ No newline at end of file
+}
+
+/// Resolves the workspace selected for this request (the personal one by default)
+/// and checks the user holds at least `required` in it.
+pub async fn validate(req_user: Option<ReqData<Claims>>, mongodb: &web::Data<client::mongodb::Client>, required: Role) -> Result<Access, actix_web::HttpResponse> {
+    let workspace = req_user.as_ref().and_then(|claims| claims.workspace);
+    let user = validate_user(req_user, mongodb).await?;
+    let workspace = workspace.unwrap_or(user.id);
+    authorize(mongodb, user, workspace, required).await
+}
+
+pub async fn authorize(mongodb: &web::Data<client::mongodb::Client>, user: User, workspace: mongodb::bson::oid::ObjectId, required: Role) -> Result<Access, actix_web::HttpResponse> {
+    let found = if workspace == user.id {
+        mongodb.personal_workspace(&user).await.map(Some)
+    } else {
+        mongodb.get_workspace(workspace).await
+    };
+    match found {
+        Ok(Some(workspace)) => match workspace.role_of(&user.id) {
+            Some(role) if role >= required => Ok(Access { user, workspace, role }),
+            Some(_) => Err(HttpResponse::Forbidden().json(format!("This action requires the {} role or higher", format!("{:?}", required).to_lowercase()))),
+            None => Err(HttpResponse::NotFound().json("Workspace not found"))
+        },
+        Ok(None) => Err(HttpResponse::NotFound().json("Workspace not found")),
+        Err(e) => Err(HttpResponse::InternalServerError().json(e))
+    }
+}
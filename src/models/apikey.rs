@@ -0,0 +1,84 @@
+use chrono::{DateTime, FixedOffset};
+use serde::{Serialize, Deserialize};
+
+pub const API_KEY_PREFIX: &str = "tck_";
+
+pub const SCOPES: [&str; 7] = [
+    "connectors:read",
+    "connectors:write",
+    "logs:read",
+    "bots:read",
+    "bots:write",
+    "jira:read",
+    "jira:write",
+];
+
+/// Longest lifetime `expires_in_days` may ask for.
+pub const MAX_EXPIRY_DAYS: i64 = 3650;
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct ApiKeyInput{
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct ApiKeyNew{
+    pub owner: mongodb::bson::oid::ObjectId,
+    pub name: String,
+    pub prefix: String,
+    pub hash: String,
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<FixedOffset>,
+    pub expires_at: Option<DateTime<FixedOffset>>,
+    pub last_used_at: Option<DateTime<FixedOffset>>
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct ApiKey{
+    #[serde(rename="_id")]
+    pub id: mongodb::bson::oid::ObjectId,
+
+    pub owner: mongodb::bson::oid::ObjectId,
+    pub name: String,
+    pub prefix: String,
+    pub hash: String,
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<FixedOffset>,
+    pub expires_at: Option<DateTime<FixedOffset>>,
+    pub last_used_at: Option<DateTime<FixedOffset>>
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct ApiKeyResponse{
+    pub id: String,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<FixedOffset>,
+    pub expires_at: Option<DateTime<FixedOffset>>,
+    pub last_used_at: Option<DateTime<FixedOffset>>
+}
+
+impl From<ApiKey> for ApiKeyResponse {
+    fn from(key: ApiKey) -> Self {
+        Self {
+            id: key.id.to_hex(),
+            name: key.name,
+            prefix: key.prefix,
+            scopes: key.scopes,
+            created_at: key.created_at,
+            expires_at: key.expires_at,
+            last_used_at: key.last_used_at
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct ApiKeyCreated{
+    pub key: String,
+
+    #[serde(flatten)]
+    pub info: ApiKeyResponse,
+}
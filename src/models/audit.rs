@@ -0,0 +1,11 @@
+use chrono::{DateTime, FixedOffset};
+use serde::{Serialize, Deserialize};
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct Audit{
+    pub event: String,
+    pub username: Option<String>,
+    pub ip: Option<String>,
+    pub detail: String,
+    pub created_at: DateTime<FixedOffset>
+}
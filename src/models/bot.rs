@@ -0,0 +1,56 @@
+use chrono::{DateTime, FixedOffset};
+use serde::{Serialize, Deserialize};
+
+use crate::crypto;
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct BotInput{
+    pub name: String,
+    pub token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct BotNew{
+    pub owner: mongodb::bson::oid::ObjectId,
+    pub name: String,
+    pub token: String,
+    pub username: String,
+    pub created_at: DateTime<FixedOffset>,
+    pub updated_at: Option<DateTime<FixedOffset>>
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct Bot{
+    #[serde(rename="_id")]
+    pub id: mongodb::bson::oid::ObjectId,
+
+    pub owner: mongodb::bson::oid::ObjectId,
+    pub name: String,
+    pub token: String,
+    pub username: String,
+    pub created_at: DateTime<FixedOffset>,
+    pub updated_at: Option<DateTime<FixedOffset>>
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct BotResponse{
+    pub id: String,
+    pub name: String,
+    pub token: String,
+    pub username: String,
+    pub created_at: DateTime<FixedOffset>,
+    pub updated_at: Option<DateTime<FixedOffset>>
+}
+
+impl From<Bot> for BotResponse {
+    fn from(bot: Bot) -> Self {
+        Self {
+            id: bot.id.to_hex(),
+            name: bot.name,
+            token: crypto::mask(&bot.token),
+            username: bot.username,
+            created_at: bot.created_at,
+            updated_at: bot.updated_at
+        }
+    }
+}
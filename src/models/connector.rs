@@ -1,12 +1,28 @@
+use std::collections::HashMap;
+
 use serde::{Serialize, Deserialize};
 use chrono::{DateTime, FixedOffset};
 
+use crate::crypto;
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
 pub struct ConnectorInput{
     pub name: String,
     pub description: String,
+    #[serde(default)]
     pub token: String,
+
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub bot_id: Option<String>,
+
     pub chatid: String,
+
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub thread_id: Option<i32>,
+
+    #[serde(default, skip_serializing_if="HashMap::is_empty")]
+    pub topics: HashMap<String, i32>,
+
     pub active: bool,
     pub schedule: bool,
     pub duration: String,
@@ -14,23 +30,113 @@ pub struct ConnectorInput{
     pub event: Vec<String>
 }
 
+/// `id` is the immutable UUID the connector is stored, logged and addressed by, `name` is a
+/// display label unique within the workspace. Connectors stored before ids existed get
+/// theirs from `legacy_id`. `version` is bumped by every write and served as the ETag.
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
 pub struct Connector{
+    #[serde(default)]
+    pub id: String,
     pub name: String,
     pub description: String,
+    #[serde(default)]
     pub token: String,
+
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub bot_id: Option<String>,
+
     pub chatid: String,
+
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub thread_id: Option<i32>,
+
+    #[serde(default, skip_serializing_if="HashMap::is_empty")]
+    pub topics: HashMap<String, i32>,
+
     pub active: bool,
     pub schedule: bool,
     pub duration: String,
     pub project: Vec<Project>,
     pub event: Vec<String>,
     pub created_at: DateTime<FixedOffset>,
-    pub updated_at: Option<DateTime<FixedOffset>>
+    pub updated_at: Option<DateTime<FixedOffset>>,
+    #[serde(default)]
+    pub version: i64
 }
 
+/// `site` is the Jira site the project belongs to, projects without one belong to the default site.
 #[derive(Debug, Serialize, Deserialize,  PartialEq, Eq, Clone)]
 pub struct Project{
     pub id: String,
-    pub name: String
-}
\ No newline at end of file
+    pub name: String,
+
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub site: Option<String>
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct ConnectorResponse{
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub token: String,
+
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub bot_id: Option<String>,
+
+    pub chatid: String,
+
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub thread_id: Option<i32>,
+
+    #[serde(skip_serializing_if="HashMap::is_empty")]
+    pub topics: HashMap<String, i32>,
+
+    pub active: bool,
+    pub schedule: bool,
+    pub duration: String,
+    pub project: Vec<Project>,
+    pub event: Vec<String>,
+    pub created_at: DateTime<FixedOffset>,
+    pub updated_at: Option<DateTime<FixedOffset>>,
+    pub version: i64
+}
+
+impl From<Connector> for ConnectorResponse {
+    fn from(con: Connector) -> Self {
+        Self {
+            id: con.id,
+            name: con.name,
+            description: con.description,
+            token: crypto::mask(&con.token),
+            bot_id: con.bot_id,
+            chatid: con.chatid,
+            thread_id: con.thread_id,
+            topics: con.topics,
+            active: con.active,
+            schedule: con.schedule,
+            duration: con.duration,
+            project: con.project,
+            event: con.event,
+            created_at: con.created_at,
+            updated_at: con.updated_at,
+            version: con.version
+        }
+    }
+}
+
+/// Fresh id for a new connector.
+pub fn new_id() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+/// Id of a connector created before ids existed, derived from its workspace and name so
+/// every backend and every upgrade run agree on it.
+pub fn legacy_id(workspace: &str, name: &str) -> String {
+    uuid::Uuid::new_v3(&uuid::Uuid::NAMESPACE_URL, format!("connector:{}/{}", workspace, name).as_bytes()).to_string()
+}
+
+/// Whether `value` is a connector id rather than a name.
+pub fn is_id(value: &str) -> bool {
+    uuid::Uuid::parse_str(value).is_ok_and(|id| id.to_string() == value)
+}
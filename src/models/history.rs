@@ -0,0 +1,47 @@
+use chrono::{DateTime, FixedOffset};
+use serde::{Serialize, Deserialize};
+
+use crate::models::connector::Connector;
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+    Created,
+    Updated,
+    Deleted,
+    Restored
+}
+
+/// A connector as one change left it, `version` is the connector version it produced.
+/// A deletion is recorded as the next version, holding the connector as it was when
+/// deleted. Snapshots never keep the token.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct Revision{
+    pub version: i64,
+    pub change: Change,
+    pub author: String,
+    pub created_at: DateTime<FixedOffset>,
+    pub connector: Connector
+}
+
+/// A top-level connector field that differs between two revisions, `None` where it is unset.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct FieldChange{
+    pub field: String,
+    pub from: Option<serde_json::Value>,
+    pub to: Option<serde_json::Value>
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct DiffQuery{
+    pub from: i64,
+    pub to: i64
+}
+
+/// Token for a restored connector with its own bot, history doesn't keep tokens. Defaults
+/// to the connector's current token.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+pub struct RestoreInput{
+    #[serde(default)]
+    pub token: String
+}
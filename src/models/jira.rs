@@ -62,22 +62,65 @@ pub struct Project{
     pub name: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Default)]
 pub struct Filter{
-    #[serde(rename="issue-related-events-section")]
+    #[serde(rename="issue-related-events-section", default)]
     pub issue_related_events_section: String
 }
 
+/// Older Server releases leave out `filters` and `enabled` and call `excludeBody` `excludeIssueDetails`.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Webhook{
     pub name: String,
     pub url: String,
     pub events: Vec<String>,
+    #[serde(default)]
     pub filters: Filter,
 
-    #[serde(rename="excludeBody")]
+    #[serde(rename="excludeBody", alias="excludeIssueDetails", default)]
     pub exclude_body: bool,
+    #[serde(default="enabled")]
     pub enabled: bool
 }
 
+fn enabled() -> bool {
+    true
+}
+
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OAuthCallback{
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OAuthToken{
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    pub expires_in: i64,
+    #[serde(default)]
+    pub scope: String
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AccessibleResource{
+    pub id: String,
+    pub url: String,
+    pub name: String
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DynamicWebhook{
+    pub events: Vec<String>,
 
+    #[serde(rename="jqlFilter")]
+    pub jql_filter: String
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DynamicWebhookInput{
+    pub url: String,
+    pub webhooks: Vec<DynamicWebhook>
+}
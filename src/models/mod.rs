@@ -6,4 +6,18 @@ pub mod log;
 
 pub mod user;
 
-pub mod event;
\ No newline at end of file
+pub mod event;
+
+pub mod bot;
+
+pub mod session;
+
+pub mod audit;
+
+pub mod apikey;
+
+pub mod workspace;
+
+pub mod site;
+
+pub mod history;
\ No newline at end of file
@@ -0,0 +1,60 @@
+use chrono::{DateTime, FixedOffset};
+use serde::{Serialize, Deserialize};
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct RefreshInput{
+    pub refresh_token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct TokenPair{
+    pub access_token: String,
+    pub refresh_token: String,
+    pub token_type: String,
+    pub expires_in: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct MfaInput{
+    pub mfa_token: String,
+    pub code: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct MfaChallenge{
+    pub mfa_required: bool,
+    pub mfa_token: String,
+    pub expires_in: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+#[serde(untagged)]
+pub enum LoginResponse{
+    Tokens(TokenPair),
+    SecondFactor(MfaChallenge),
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct SessionNew{
+    pub user: mongodb::bson::oid::ObjectId,
+    pub refresh_hash: String,
+    pub previous_hash: Option<String>,
+    pub access_jti: String,
+    pub revoked: bool,
+    pub created_at: DateTime<FixedOffset>,
+    pub expires_at: DateTime<FixedOffset>
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct Session{
+    #[serde(rename="_id")]
+    pub id: mongodb::bson::oid::ObjectId,
+
+    pub user: mongodb::bson::oid::ObjectId,
+    pub refresh_hash: String,
+    pub previous_hash: Option<String>,
+    pub access_jti: String,
+    pub revoked: bool,
+    pub created_at: DateTime<FixedOffset>,
+    pub expires_at: DateTime<FixedOffset>
+}
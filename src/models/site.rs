@@ -0,0 +1,113 @@
+use chrono::{DateTime, FixedOffset};
+use serde::{Serialize, Deserialize};
+
+use crate::crypto;
+use crate::models::workspace::{Workspace, JiraFlavor, JiraOAuth};
+use crate::routes::jira::WebhookInput;
+
+/// One Jira site connected to a workspace. The site a workspace had before sites
+/// existed keeps the workspace's `_id`, so its `/event/{id}` webhook still routes.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct JiraSite{
+    #[serde(rename="_id")]
+    pub id: mongodb::bson::oid::ObjectId,
+
+    pub workspace: mongodb::bson::oid::ObjectId,
+    pub name: String,
+    pub created_at: DateTime<FixedOffset>,
+
+    pub jira_email: Option<String>,
+    pub jira_api_key: Option<String>,
+    pub jira_url: Option<String>,
+    #[serde(default)]
+    pub jira_flavor: JiraFlavor,
+    #[serde(default)]
+    pub jira_oauth: Option<JiraOAuth>,
+
+    pub webhook_url: Option<String>,
+    #[serde(default)]
+    pub webhook_id: Option<i64>,
+    pub webhook_functional: Option<bool>,
+    pub webhook_last_check: Option<String>
+}
+
+impl JiraSite {
+    /// Site that isn't connected yet, `create_webhook` fills in the credentials.
+    pub fn new(id: mongodb::bson::oid::ObjectId, workspace: mongodb::bson::oid::ObjectId, input: &WebhookInput) -> Self {
+        Self {
+            id,
+            workspace,
+            name: if input.name.is_empty() { input.jira_url.clone() } else { input.name.clone() },
+            created_at: chrono::Utc::now().with_timezone(&chrono::FixedOffset::east_opt(7 * 3600).unwrap()),
+            jira_email: None,
+            jira_api_key: None,
+            jira_url: None,
+            jira_flavor: input.flavor,
+            jira_oauth: None,
+            webhook_url: None,
+            webhook_id: None,
+            webhook_functional: None,
+            webhook_last_check: None
+        }
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.id == self.workspace
+    }
+
+    /// Site built from the Jira settings stored on the workspace document.
+    pub fn legacy(workspace: &Workspace) -> Self {
+        Self {
+            id: workspace.id,
+            workspace: workspace.id,
+            name: workspace.jira_url.clone().unwrap_or_else(|| workspace.name.clone()),
+            created_at: workspace.created_at,
+            jira_email: workspace.jira_email.clone(),
+            jira_api_key: workspace.jira_api_key.clone(),
+            jira_url: workspace.jira_url.clone(),
+            jira_flavor: workspace.jira_flavor,
+            jira_oauth: workspace.jira_oauth.clone(),
+            webhook_url: workspace.webhook_url.clone(),
+            webhook_id: workspace.webhook_id,
+            webhook_functional: workspace.webhook_functional,
+            webhook_last_check: workspace.webhook_last_check.clone()
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct SiteResponse{
+    pub id: String,
+    pub name: String,
+    pub created_at: DateTime<FixedOffset>,
+    pub jira_email: Option<String>,
+    pub jira_api_key: Option<String>,
+    pub jira_url: Option<String>,
+    pub jira_flavor: JiraFlavor,
+    pub jira_auth: Option<String>,
+    pub webhook_url: Option<String>,
+    pub webhook_functional: Option<bool>,
+    pub webhook_last_check: Option<String>
+}
+
+impl From<JiraSite> for SiteResponse {
+    fn from(site: JiraSite) -> Self {
+        Self {
+            id: site.id.to_hex(),
+            name: site.name,
+            created_at: site.created_at,
+            jira_email: site.jira_email,
+            jira_api_key: site.jira_api_key.as_deref().map(crypto::mask),
+            jira_url: site.jira_url,
+            jira_flavor: site.jira_flavor,
+            jira_auth: if site.jira_oauth.is_some() {
+                Some("oauth".to_string())
+            } else {
+                site.jira_api_key.as_ref().map(|_| "basic".to_string())
+            },
+            webhook_url: site.webhook_url,
+            webhook_functional: site.webhook_functional,
+            webhook_last_check: site.webhook_last_check
+        }
+    }
+}
@@ -1,12 +1,31 @@
 use chrono::{DateTime, FixedOffset};
 use serde::{Serialize, Deserialize};
 
+use crate::crypto;
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
 pub struct UserInput{
     pub username: String,
     pub password: String,
 }
 
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct PasswordInput{
+    pub current_password: String,
+    pub new_password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct TotpInput{
+    pub code: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct TotpEnrollment{
+    pub secret: String,
+    pub otpauth_url: String,
+}
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
 pub struct UserNew{
     pub username: String,
@@ -18,7 +37,11 @@ pub struct UserNew{
     pub jira_url: Option<String>,
     pub webhook_url: Option<String>,
     pub webhook_functional: Option<bool>,
-    pub webhook_last_check: Option<String>
+    pub webhook_last_check: Option<String>,
+
+    pub totp_secret: Option<String>,
+    pub totp_pending: Option<String>,
+    pub recovery_codes: Option<Vec<String>>
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
@@ -35,7 +58,44 @@ pub struct User{
     pub jira_url: Option<String>,
     pub webhook_url: Option<String>,
     pub webhook_functional: Option<bool>,
-    pub webhook_last_check: Option<String>
+    pub webhook_last_check: Option<String>,
+
+    pub totp_secret: Option<String>,
+    pub totp_pending: Option<String>,
+    pub recovery_codes: Option<Vec<String>>,
+    /// Last TOTP time step a code was accepted for, older and equal steps are refused.
+    #[serde(default)]
+    pub totp_step: Option<i64>
 }
 
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct UserResponse{
+    pub id: String,
+    pub username: String,
+    pub created_at: DateTime<FixedOffset>,
 
+    pub jira_email: Option<String>,
+    pub jira_api_key: Option<String>,
+    pub jira_url: Option<String>,
+    pub webhook_url: Option<String>,
+    pub webhook_functional: Option<bool>,
+    pub webhook_last_check: Option<String>,
+    pub two_factor_enabled: bool
+}
+
+impl From<User> for UserResponse {
+    fn from(user: User) -> Self {
+        Self {
+            id: user.id.to_hex(),
+            username: user.username,
+            created_at: user.created_at,
+            jira_email: user.jira_email,
+            jira_api_key: user.jira_api_key.as_deref().map(crypto::mask),
+            jira_url: user.jira_url,
+            webhook_url: user.webhook_url,
+            webhook_functional: user.webhook_functional,
+            webhook_last_check: user.webhook_last_check,
+            two_factor_enabled: user.totp_secret.is_some()
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use chrono::{DateTime, FixedOffset};
+use serde::{Serialize, Deserialize};
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+#[serde(rename_all="lowercase")]
+pub enum Role{
+    Viewer,
+    Editor,
+    Admin,
+    Owner,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct Member{
+    pub user: mongodb::bson::oid::ObjectId,
+    pub username: String,
+    pub role: Role,
+    pub joined_at: DateTime<FixedOffset>
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct WorkspaceInput{
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct RoleInput{
+    pub role: Role,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct InvitationInput{
+    pub username: String,
+    pub role: Role,
+}
+
+/// Which kind of Jira deployment the workspace talks to. Data Center is the same API as Server.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Default)]
+#[serde(rename_all="lowercase")]
+pub enum JiraFlavor{
+    #[default]
+    Cloud,
+    #[serde(alias="datacenter")]
+    Server,
+}
+
+impl JiraFlavor {
+    /// Cloud serves the ADF based v3 API, Server and Data Center only have v2.
+    pub fn api_version(&self) -> &'static str {
+        match self {
+            JiraFlavor::Cloud => "3",
+            JiraFlavor::Server => "2",
+        }
+    }
+}
+
+/// Tokens from the Atlassian OAuth 2.0 (3LO) flow; both tokens are stored sealed.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct JiraOAuth{
+    pub access_token: String,
+    pub refresh_token: String,
+    pub expires_at: DateTime<FixedOffset>,
+    pub cloud_id: String,
+    pub site_url: String,
+    pub scope: String,
+    /// Shared by every site authorized in the same consent, they rotate one refresh token.
+    #[serde(default)]
+    pub grant: String
+}
+
+/// A personal workspace shares its `_id` with the user that owns it, so the
+/// existing `{id}/` bucket prefix and `/event/{id}` webhook keep working.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct Workspace{
+    #[serde(rename="_id")]
+    pub id: mongodb::bson::oid::ObjectId,
+
+    pub name: String,
+    pub owner: mongodb::bson::oid::ObjectId,
+    pub members: Vec<Member>,
+    pub created_at: DateTime<FixedOffset>,
+
+    // Jira settings from before sites existed, moved to the default `JiraSite` on first access
+    pub jira_email: Option<String>,
+    pub jira_api_key: Option<String>,
+    pub jira_url: Option<String>,
+    pub webhook_url: Option<String>,
+    pub webhook_functional: Option<bool>,
+    pub webhook_last_check: Option<String>,
+
+    #[serde(default)]
+    pub jira_flavor: JiraFlavor,
+    #[serde(default)]
+    pub jira_oauth: Option<JiraOAuth>,
+    #[serde(default)]
+    pub webhook_id: Option<i64>
+}
+
+impl Workspace {
+    pub fn role_of(&self, user: &mongodb::bson::oid::ObjectId) -> Option<Role> {
+        self.members.iter().find(|member| &member.user == user).map(|member| member.role)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct WorkspaceResponse{
+    pub id: String,
+    pub name: String,
+    pub role: Option<Role>,
+    pub members: Vec<Member>,
+    pub created_at: DateTime<FixedOffset>
+}
+
+impl WorkspaceResponse {
+    pub fn new(workspace: Workspace, user: &mongodb::bson::oid::ObjectId) -> Self {
+        Self {
+            id: workspace.id.to_hex(),
+            name: workspace.name.clone(),
+            role: workspace.role_of(user),
+            members: workspace.members,
+            created_at: workspace.created_at
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct InvitationNew{
+    pub workspace: mongodb::bson::oid::ObjectId,
+    pub workspace_name: String,
+    pub username: String,
+    pub role: Role,
+    pub invited_by: String,
+    pub created_at: DateTime<FixedOffset>
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct Invitation{
+    #[serde(rename="_id")]
+    pub id: mongodb::bson::oid::ObjectId,
+
+    pub workspace: mongodb::bson::oid::ObjectId,
+    pub workspace_name: String,
+    pub username: String,
+    pub role: Role,
+    pub invited_by: String,
+    pub created_at: DateTime<FixedOffset>
+}
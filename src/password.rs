@@ -0,0 +1,43 @@
+const DEFAULT_COST: u32 = 12;
+
+pub fn cost() -> u32 {
+    std::env::var("BCRYPT_COST").ok()
+        .and_then(|v| v.parse::<u32>().ok())
+        .map(|v| v.clamp(4, 31))
+        .unwrap_or(DEFAULT_COST)
+}
+
+/// Reads the cost out of a stored hash such as `$2b$04$...`.
+pub fn stored_cost(hash: &str) -> Option<u32> {
+    hash.split('$').nth(2).and_then(|c| c.parse().ok())
+}
+
+pub fn check_username(username: &str) -> Vec<String> {
+    let mut errors = Vec::new();
+    let len = username.chars().count();
+    if !(3..=32).contains(&len) {
+        errors.push("Username must be between 3 and 32 characters".to_string());
+    }
+    if !username.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-') {
+        errors.push("Username may only contain letters, digits, '_', '.' and '-'".to_string());
+    }
+    errors
+}
+
+pub fn check_password(password: &str, username: &str) -> Vec<String> {
+    let mut errors = Vec::new();
+    if password.chars().count() < 8 {
+        errors.push("Password must be at least 8 characters".to_string());
+    }
+    // bcrypt silently ignores everything past 72 bytes
+    if password.len() > 72 {
+        errors.push("Password must be at most 72 bytes".to_string());
+    }
+    if !password.chars().any(|c| c.is_alphabetic()) || !password.chars().any(|c| c.is_ascii_digit()) {
+        errors.push("Password must contain at least one letter and one digit".to_string());
+    }
+    if !username.is_empty() && password.to_lowercase().contains(&username.to_lowercase()) {
+        errors.push("Password must not contain the username".to_string());
+    }
+    errors
+}
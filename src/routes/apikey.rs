@@ -0,0 +1,40 @@
+use actix_web::{web::{self, ReqData}, HttpResponse};
+
+use crate::{client, models::apikey::{ApiKeyInput, ApiKeyResponse}, middleware, middleware::Claims};
+
+pub async fn post(mongodb: web::Data<client::mongodb::Client>, payload: web::Json<ApiKeyInput>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
+    match middleware::validate_user(req_user, &mongodb).await {
+        Ok(user) => {
+            match mongodb.create_api_key(payload.into_inner(), user.id).await {
+                Ok(created) => return HttpResponse::Ok().json(created),
+                Err(e) => return HttpResponse::BadRequest().json(e)
+            };
+        },
+        Err(error) => return error
+    }
+}
+
+pub async fn get(mongodb: web::Data<client::mongodb::Client>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
+    match middleware::validate_user(req_user, &mongodb).await {
+        Ok(user) => {
+            match mongodb.get_api_keys(user.id).await {
+                Ok(keys) => return HttpResponse::Ok().json(keys.into_iter().map(ApiKeyResponse::from).collect::<Vec<_>>()),
+                Err(e) => return HttpResponse::InternalServerError().json(e)
+            }
+        },
+        Err(error) => return error
+    }
+}
+
+pub async fn delete(mongodb: web::Data<client::mongodb::Client>, id: web::Path<String>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
+    match middleware::validate_user(req_user, &mongodb).await {
+        Ok(user) => {
+            match mongodb.delete_api_key(&id, user.id).await {
+                Ok(true) => return HttpResponse::Ok().json("API key revoked"),
+                Ok(false) => return HttpResponse::NotFound().json(format!("No API key with id: {} found", id)),
+                Err(e) => return HttpResponse::InternalServerError().json(e)
+            }
+        },
+        Err(error) => return error
+    }
+}
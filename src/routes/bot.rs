@@ -0,0 +1,75 @@
+use actix_web::{web::{self, ReqData}, HttpResponse};
+
+use crate::{client, models::bot::{BotInput, BotResponse}, errortype::BotError, middleware, middleware::Claims, models::workspace::Role};
+
+pub async fn post(mongodb: web::Data<client::mongodb::Client>, payload: web::Json<BotInput>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
+    match middleware::validate(req_user, &mongodb, Role::Admin).await {
+        Ok(access) => {
+            if payload.name.is_empty() {
+                return HttpResponse::BadRequest().json("Bot name must not be empty!")
+            }
+            match mongodb.add_bot(payload.into_inner(), access.workspace.id).await {
+                Ok(ok) => return HttpResponse::Ok().json(ok),
+                Err(BotError::DbError(e)) => return HttpResponse::InternalServerError().json(e),
+                Err(e) => return HttpResponse::BadRequest().json(format!("{}",e))
+            };
+        },
+        Err(error) => return error
+    }
+}
+
+pub async fn get(mongodb: web::Data<client::mongodb::Client>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
+    match middleware::validate(req_user, &mongodb, Role::Viewer).await {
+        Ok(access) => {
+            match mongodb.get_bots(access.workspace.id).await {
+                Ok(bots) => return HttpResponse::Ok().json(bots.into_iter().map(BotResponse::from).collect::<Vec<_>>()),
+                Err(e) => return HttpResponse::InternalServerError().json(e.to_string())
+            }
+        },
+        Err(error) => return error
+    }
+}
+
+pub async fn get_one(mongodb: web::Data<client::mongodb::Client>, id: web::Path<String>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
+    match middleware::validate(req_user, &mongodb, Role::Viewer).await {
+        Ok(access) => {
+            match mongodb.get_one_bot(&id, access.workspace.id).await {
+                Ok(Some(bot)) => return HttpResponse::Ok().json(BotResponse::from(bot)),
+                Ok(None) => return HttpResponse::NotFound().json(format!("No bot with id: {} found", id)),
+                Err(e) => return HttpResponse::InternalServerError().json(e.to_string())
+            }
+        },
+        Err(error) => return error
+    }
+}
+
+pub async fn update(mongodb: web::Data<client::mongodb::Client>, id: web::Path<String>, payload: web::Json<BotInput>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
+    match middleware::validate(req_user, &mongodb, Role::Admin).await {
+        Ok(access) => {
+            if payload.name.is_empty() {
+                return HttpResponse::BadRequest().json("Bot name must not be empty!")
+            }
+            match mongodb.update_bot(&id, payload.into_inner(), access.workspace.id).await {
+                Ok(ok) => return HttpResponse::Ok().json(ok),
+                Err(BotError::BotNotFound) => return HttpResponse::NotFound().json(format!("{}", BotError::BotNotFound)),
+                Err(BotError::DbError(e)) => return HttpResponse::InternalServerError().json(e),
+                Err(e) => return HttpResponse::BadRequest().json(format!("{}",e))
+            };
+        },
+        Err(error) => return error
+    }
+}
+
+pub async fn delete(db: web::Data<client::storage::Client>, mongodb: web::Data<client::mongodb::Client>, id: web::Path<String>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
+    match middleware::validate(req_user, &mongodb, Role::Admin).await {
+        Ok(access) => {
+            match mongodb.delete_bot(&db, &id, access.workspace.id).await {
+                Ok(ok) => return HttpResponse::Ok().json(ok),
+                Err(BotError::BotNotFound) => return HttpResponse::NotFound().json(format!("{}", BotError::BotNotFound)),
+                Err(BotError::DbError(e)) => return HttpResponse::InternalServerError().json(e),
+                Err(e) => return HttpResponse::BadRequest().json(format!("{}",e))
+            };
+        },
+        Err(error) => return error
+    }
+}
@@ -1,17 +1,66 @@
-use actix_web::{web::{self, ReqData}, HttpResponse};
+use actix_web::{http::header::{ETag, EntityTag, IfMatch}, web::{self, ReqData}, HttpResponse};
 
-use crate::{client, actions, models::{connector::{ConnectorInput, Connector}}, errortype::ConnectorError, middleware, middleware::Claims};
+use crate::{client, actions, models::{connector::{ConnectorInput, Connector, ConnectorResponse, Project}, site::JiraSite}, errortype::ConnectorError, middleware, middleware::{Access, Claims}, models::workspace::Role};
 
-pub async fn post(db: web::Data<client::rusoto::Client>, mongodb: web::Data<client::mongodb::Client>, payload: web::Json<ConnectorInput>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
-    match middleware::validate(req_user, &mongodb).await {
-        Ok(user) => {
-            if user.webhook_url.is_none(){
-                return HttpResponse::BadRequest().json("You must set up a webhook first")
-            }
+/// Every project has to come from a Jira site connected to the workspace. A project without
+/// a site belongs to the site migrated from the workspace, when there is none it is pinned
+/// to the workspace's only site so a site added later can't take it over.
+pub fn check_sites(sites: &[JiraSite], access: &Access, projects: &mut [Project]) -> Result<(), String> {
+    let only = match sites {
+        [site] if !site.is_default() => Some(site.id.to_hex()),
+        _ => None
+    };
+    for project in projects {
+        if project.site.is_none() {
+            project.site = only.clone();
+        }
+        let site = project.site.clone().unwrap_or_else(|| access.workspace.id.to_hex());
+        if !sites.iter().any(|known| known.id.to_hex() == site) {
+            return Err(format!("Jira site {} of project {} is not connected to this workspace", site, project.name))
+        }
+    }
+    Ok(())
+}
+
+pub fn etag(version: i64) -> ETag {
+    ETag(EntityTag::new_strong(version.to_string()))
+}
+
+/// The connector version an `If-Match` header asks for, `*` or no header match any version.
+pub fn expected_version(if_match: Option<web::Header<IfMatch>>) -> Result<Option<i64>, &'static str> {
+    match if_match.map(|header| header.into_inner()) {
+        Some(IfMatch::Items(tags)) => match tags.first().map(|tag| tag.tag().parse::<i64>()) {
+            Some(Ok(version)) => Ok(Some(version)),
+            _ => Err("If-Match must be the ETag of the connector")
+        },
+        _ => Ok(None)
+    }
+}
+
+pub async fn post(db: web::Data<client::storage::Client>, mongodb: web::Data<client::mongodb::Client>, mut payload: web::Json<ConnectorInput>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
+    match middleware::validate(req_user, &mongodb, Role::Editor).await {
+        Ok(access) => {
             if payload.name.is_empty() {
                 return HttpResponse::BadRequest().json("Connector name must not be empty!")
             }
-            match actions::connector::add_connector(&db.s3, payload.into_inner(), user.id.to_hex()).await {
+            match mongodb.get_sites(&access.workspace).await {
+                Ok(sites) if sites.iter().any(|site| site.webhook_url.is_some()) => {
+                    if let Err(e) = check_sites(&sites, &access, &mut payload.project) {
+                        return HttpResponse::BadRequest().json(e)
+                    }
+                },
+                Ok(_) => return HttpResponse::BadRequest().json("You must set up a webhook first"),
+                Err(e) => return HttpResponse::InternalServerError().json(e)
+            }
+            let mut payload = payload.into_inner();
+            if let Some(bot_id) = &payload.bot_id {
+                match mongodb.bot_token(bot_id, access.workspace.id).await {
+                    Ok(token) => payload.token = token,
+                    Err(ConnectorError::DbError(e)) => return HttpResponse::InternalServerError().json(e),
+                    Err(e) => return HttpResponse::BadRequest().json(format!("{}",e))
+                }
+            }
+            match actions::connector::add_connector(&db, payload, access.workspace.id.to_hex(), &access.user.username).await {
                 Ok(ok) => return HttpResponse::Ok().json(ok),
                 Err(ConnectorError::RusError(e)) => return HttpResponse::InternalServerError().json(format!("{}", e)),
                 Err(e) => return HttpResponse::BadRequest().json(format!("{}",e))
@@ -21,11 +70,11 @@ pub async fn post(db: web::Data<client::rusoto::Client>, mongodb: web::Data<clie
     }   
 }
 
-pub async fn get(db: web::Data<client::rusoto::Client>, mongodb: web::Data<client::mongodb::Client>, req_user: Option<ReqData<Claims>>) -> HttpResponse { 
-    match middleware::validate(req_user, &mongodb).await {
-        Ok(user) => {
-            match actions::connector::get_connectors(&db.s3, user.id.to_hex()).await {
-                Ok(ok) => return HttpResponse::Ok().json(ok),
+pub async fn get(db: web::Data<client::storage::Client>, mongodb: web::Data<client::mongodb::Client>, req_user: Option<ReqData<Claims>>) -> HttpResponse { 
+    match middleware::validate(req_user, &mongodb, Role::Viewer).await {
+        Ok(access) => {
+            match actions::connector::get_connectors(&db, access.workspace.id.to_hex()).await {
+                Ok(ok) => return HttpResponse::Ok().json(ok.into_iter().map(ConnectorResponse::from).collect::<Vec<_>>()),
                 Err(_)=> return HttpResponse::NotFound().json("Connector list empty")
             }
         },
@@ -33,22 +82,24 @@ pub async fn get(db: web::Data<client::rusoto::Client>, mongodb: web::Data<clien
     }   
 }
 
-pub async fn get_one(db: web::Data<client::rusoto::Client>, mongodb: web::Data<client::mongodb::Client>, name: web::Path<String>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
-    match middleware::validate(req_user, &mongodb).await {
-        Ok(user) => {
-            match actions::connector::get_one_connector(&db.s3, name.to_string(), user.id.to_hex()).await {
-                Some(ok) => return HttpResponse::Ok().json(ok),
-                None => return HttpResponse::NotFound().json(format!("No connector with name: {} found", name))
+pub async fn get_one(db: web::Data<client::storage::Client>, mongodb: web::Data<client::mongodb::Client>, name: web::Path<String>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
+    match middleware::validate(req_user, &mongodb, Role::Viewer).await {
+        Ok(access) => {
+            match actions::connector::get_one_connector(&db, name.to_string(), access.workspace.id.to_hex()).await {
+                Some(ok) => return HttpResponse::Ok()
+                    .insert_header(etag(ok.version))
+                    .json(ConnectorResponse::from(ok)),
+                None => return HttpResponse::NotFound().json(format!("No connector with id or name: {} found", name))
             };
         },
         Err(error) => return error
     }   
 }
 
-pub async fn delete(db: web::Data<client::rusoto::Client>, mongodb: web::Data<client::mongodb::Client>, name: web::Path<String>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
-    match middleware::validate(req_user, &mongodb).await {
-        Ok(user) => {
-            match actions::connector::delete_connector(&db.s3, name.to_string(), user.id.to_hex()).await {
+pub async fn delete(db: web::Data<client::storage::Client>, mongodb: web::Data<client::mongodb::Client>, name: web::Path<String>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
+    match middleware::validate(req_user, &mongodb, Role::Editor).await {
+        Ok(access) => {
+            match actions::connector::delete_connector(&db, name.to_string(), access.workspace.id.to_hex(), &access.user.username).await {
                 Ok(ok) => return HttpResponse::Ok().json(ok),
                 Err(ConnectorError::RusError(e)) => return HttpResponse::InternalServerError().json(format!("{}", e)),
                 Err(e) => return HttpResponse::NotFound().json(format!("{}",e))
@@ -58,16 +109,36 @@ pub async fn delete(db: web::Data<client::rusoto::Client>, mongodb: web::Data<cl
     }   
 }
 
-pub async fn update(db: web::Data<client::rusoto::Client>, mongodb: web::Data<client::mongodb::Client>, name: web::Path<String>, payload: web::Json<Connector>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
-    match middleware::validate(req_user, &mongodb).await {
-        Ok(user) => {
+pub async fn update(db: web::Data<client::storage::Client>, mongodb: web::Data<client::mongodb::Client>, name: web::Path<String>, mut payload: web::Json<Connector>, if_match: Option<web::Header<IfMatch>>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
+    match middleware::validate(req_user, &mongodb, Role::Editor).await {
+        Ok(access) => {
+            let expected = match expected_version(if_match) {
+                Ok(expected) => expected,
+                Err(e) => return HttpResponse::BadRequest().json(e)
+            };
             if payload.name.is_empty() {
                 return HttpResponse::BadRequest().json("Connector name must not be empty!")
             }
+            match mongodb.get_sites(&access.workspace).await {
+                Ok(sites) => {
+                    if let Err(e) = check_sites(&sites, &access, &mut payload.project) {
+                        return HttpResponse::BadRequest().json(e)
+                    }
+                },
+                Err(e) => return HttpResponse::InternalServerError().json(e)
+            }
             let mut connector = payload.into_inner();
-            match actions::connector::update_connector(&db.s3, name.to_string(), &mut connector, user.id.to_hex()).await {
-                Ok(ok) => return HttpResponse::Ok().json(ok),
+            if let Some(bot_id) = &connector.bot_id {
+                match mongodb.bot_token(bot_id, access.workspace.id).await {
+                    Ok(token) => connector.token = token,
+                    Err(ConnectorError::DbError(e)) => return HttpResponse::InternalServerError().json(e),
+                    Err(e) => return HttpResponse::BadRequest().json(format!("{}",e))
+                }
+            }
+            match actions::connector::update_connector(&db, name.to_string(), &mut connector, access.workspace.id.to_hex(), expected, &access.user.username).await {
+                Ok(ok) => return HttpResponse::Ok().insert_header(etag(connector.version)).json(ok),
                 Err(ConnectorError::ConNotFound) => return HttpResponse::NotFound().json(format!("{}", ConnectorError::ConNotFound)),
+                Err(e @ (ConnectorError::ConConflict | ConnectorError::ConUpdateExist)) => return HttpResponse::Conflict().json(format!("{}", e)),
                 Err(ConnectorError::RusError(e)) => return HttpResponse::InternalServerError().json(format!("{}", e)),
                 Err(e) => return HttpResponse::BadRequest().json(format!("{}",e))
             };
@@ -77,3 +148,47 @@ pub async fn update(db: web::Data<client::rusoto::Client>, mongodb: web::Data<cl
 }
 
 
+pub async fn patch(db: web::Data<client::storage::Client>, mongodb: web::Data<client::mongodb::Client>, name: web::Path<String>, payload: web::Json<serde_json::Value>, if_match: Option<web::Header<IfMatch>>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
+    match middleware::validate(req_user, &mongodb, Role::Editor).await {
+        Ok(access) => {
+            let expected = match expected_version(if_match) {
+                Ok(expected) => expected,
+                Err(e) => return HttpResponse::BadRequest().json(e)
+            };
+            let current = match actions::connector::find_connector(&db, &name, &access.workspace.id.to_hex()).await {
+                Ok(Some(con)) => con,
+                Ok(None) => return HttpResponse::NotFound().json(format!("{}", ConnectorError::ConNotFound)),
+                Err(e) => return HttpResponse::InternalServerError().json(format!("{}", e))
+            };
+            let mut connector = match actions::connector::merge_patch(&current, &payload) {
+                Ok(connector) => connector,
+                Err(e) => return HttpResponse::BadRequest().json(format!("{}", e))
+            };
+            if connector.project != current.project {
+                match mongodb.get_sites(&access.workspace).await {
+                    Ok(sites) => {
+                        if let Err(e) = check_sites(&sites, &access, &mut connector.project) {
+                            return HttpResponse::BadRequest().json(e)
+                        }
+                    },
+                    Err(e) => return HttpResponse::InternalServerError().json(e)
+                }
+            }
+            if let Some(bot_id) = connector.bot_id.as_ref().filter(|_| actions::connector::bot_changed(&current, &connector)) {
+                match mongodb.bot_token(bot_id, access.workspace.id).await {
+                    Ok(token) => connector.token = token,
+                    Err(ConnectorError::DbError(e)) => return HttpResponse::InternalServerError().json(e),
+                    Err(e) => return HttpResponse::BadRequest().json(format!("{}",e))
+                }
+            }
+            match actions::connector::patch_connector(&db, &current, &mut connector, access.workspace.id.to_hex(), expected, &access.user.username).await {
+                Ok(ok) => return HttpResponse::Ok().insert_header(etag(connector.version)).json(ok),
+                Err(ConnectorError::ConNotFound) => return HttpResponse::NotFound().json(format!("{}", ConnectorError::ConNotFound)),
+                Err(e @ (ConnectorError::ConConflict | ConnectorError::ConUpdateExist)) => return HttpResponse::Conflict().json(format!("{}", e)),
+                Err(ConnectorError::RusError(e)) => return HttpResponse::InternalServerError().json(format!("{}", e)),
+                Err(e) => return HttpResponse::BadRequest().json(format!("{}",e))
+            };
+        },
+        Err(error) => return error
+    }   
+}
@@ -4,7 +4,7 @@ use serde_json::Value;
 use crate::actions::event;
 use crate::client;
 
-pub async fn post(db: web::Data<client::rusoto::Client>, req: HttpRequest, payload: web::Json<Value>, id: web::Path<String>) -> HttpResponse {
+pub async fn post(db: web::Data<client::storage::Client>, mongodb: web::Data<client::mongodb::Client>, req: HttpRequest, payload: web::Json<Value>, id: web::Path<String>) -> HttpResponse {
     let event_key = req
         .headers()
         .get("user-agent")
@@ -12,7 +12,7 @@ pub async fn post(db: web::Data<client::rusoto::Client>, req: HttpRequest, paylo
         .unwrap_or("");
 
     if event_key.starts_with("Atlassian") {
-        match event::process_event(&db.s3, payload.into_inner(), id.to_string()).await{
+        match event::process_event(&db, &mongodb, payload.into_inner(), id.to_string()).await{
             Ok(o)=>println!("{:?}", o),
             Err(e)=>println!("{:?}", e)
         };
@@ -0,0 +1,74 @@
+use actix_web::{http::header::IfMatch, web::{self, ReqData}, HttpResponse};
+
+use crate::{client, actions, errortype::ConnectorError, middleware, middleware::Claims, models::{history::{DiffQuery, RestoreInput}, workspace::Role}};
+use crate::routes::connector::{check_sites, etag, expected_version};
+
+pub async fn get(db: web::Data<client::storage::Client>, mongodb: web::Data<client::mongodb::Client>, req_user: Option<ReqData<Claims>>, name: web::Path<String>) -> HttpResponse {
+    match middleware::validate(req_user, &mongodb, Role::Viewer).await {
+        Ok(access) => {
+            match actions::history::get_history(&db, name.to_string(), access.workspace.id.to_hex()).await {
+                Ok(history) => return HttpResponse::Ok().json(history),
+                Err(ConnectorError::ConNotFound) => return HttpResponse::NotFound().json(format!("No connector with id or name: {} found", name)),
+                Err(e) => return HttpResponse::InternalServerError().json(format!("{}", e))
+            }
+        },
+        Err(error) => return error
+    }
+}
+
+pub async fn diff(db: web::Data<client::storage::Client>, mongodb: web::Data<client::mongodb::Client>, req_user: Option<ReqData<Claims>>, name: web::Path<String>, query: web::Query<DiffQuery>) -> HttpResponse {
+    match middleware::validate(req_user, &mongodb, Role::Viewer).await {
+        Ok(access) => {
+            match actions::history::diff(&db, name.to_string(), query.from, query.to, access.workspace.id.to_hex()).await {
+                Ok(changes) => return HttpResponse::Ok().json(changes),
+                Err(ConnectorError::ConNotFound) => return HttpResponse::NotFound().json(format!("No connector with id or name: {} found", name)),
+                Err(e @ ConnectorError::ConVersionNotFound(_)) => return HttpResponse::NotFound().json(format!("{}", e)),
+                Err(e) => return HttpResponse::InternalServerError().json(format!("{}", e))
+            }
+        },
+        Err(error) => return error
+    }
+}
+
+pub async fn restore(db: web::Data<client::storage::Client>, mongodb: web::Data<client::mongodb::Client>, path: web::Path<(String, i64)>, payload: Option<web::Json<RestoreInput>>, if_match: Option<web::Header<IfMatch>>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
+    match middleware::validate(req_user, &mongodb, Role::Editor).await {
+        Ok(access) => {
+            let expected = match expected_version(if_match) {
+                Ok(expected) => expected,
+                Err(e) => return HttpResponse::BadRequest().json(e)
+            };
+            let (name, version) = path.into_inner();
+            let (current, revision) = match actions::history::get_revision(&db, name.clone(), version, access.workspace.id.to_hex()).await {
+                Ok(found) => found,
+                Err(ConnectorError::ConNotFound) => return HttpResponse::NotFound().json(format!("No connector with id or name: {} found", name)),
+                Err(e @ ConnectorError::ConVersionNotFound(_)) => return HttpResponse::NotFound().json(format!("{}", e)),
+                Err(e) => return HttpResponse::InternalServerError().json(format!("{}", e))
+            };
+            let mut connector = revision.connector;
+            // Sites may have been disconnected since the revision was taken
+            match mongodb.get_sites(&access.workspace).await {
+                Ok(sites) => {
+                    if let Err(e) = check_sites(&sites, &access, &mut connector.project) {
+                        return HttpResponse::BadRequest().json(e)
+                    }
+                },
+                Err(e) => return HttpResponse::InternalServerError().json(e)
+            }
+            connector.token = payload.map(|payload| payload.into_inner().token).unwrap_or_default();
+            if let Some(bot_id) = &connector.bot_id {
+                match mongodb.bot_token(bot_id, access.workspace.id).await {
+                    Ok(token) => connector.token = token,
+                    Err(ConnectorError::DbError(e)) => return HttpResponse::InternalServerError().json(e),
+                    Err(e) => return HttpResponse::BadRequest().json(format!("{}",e))
+                }
+            }
+            match actions::connector::restore_connector(&db, current.as_ref(), &mut connector, access.workspace.id.to_hex(), expected, &access.user.username).await {
+                Ok(ok) => return HttpResponse::Ok().insert_header(etag(connector.version)).json(ok),
+                Err(e @ (ConnectorError::ConConflict | ConnectorError::ConUpdateExist | ConnectorError::ConCreateExist)) => return HttpResponse::Conflict().json(format!("{}", e)),
+                Err(ConnectorError::RusError(e)) => return HttpResponse::InternalServerError().json(format!("{}", e)),
+                Err(e) => return HttpResponse::BadRequest().json(format!("{}",e))
+            };
+        },
+        Err(error) => return error
+    }
+}
@@ -1,39 +1,91 @@
-
-use actix_web::{ web::{self, ReqData}, web::Data, HttpResponse};
+use actix_web::{ web::{self, ReqData}, web::Data, cookie::{Cookie, SameSite, time::Duration}, HttpRequest, HttpResponse};
+use rand::{Rng, distributions::Alphanumeric};
 use serde::{Serialize, Deserialize};
 
-use crate::{client, middleware, middleware::Claims};
+use mongodb::bson::oid::ObjectId;
+
+use crate::{client, config, middleware, middleware::{Claims, OAUTH_COOKIE}, models::{workspace::{Role, JiraFlavor, Workspace}, site::JiraSite, jira::OAuthCallback}, routes::site};
 
+/// Credentials are only needed for email + API token sites, OAuth workspaces post an empty body.
+/// On Server and Data Center `api_key` is a personal access token and `email` is ignored.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct WebhookInput{
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
     pub email: String,
+    #[serde(default)]
     pub api_key: String,
-    pub jira_url: String
+    #[serde(default)]
+    pub jira_url: String,
+    #[serde(default)]
+    pub flavor: JiraFlavor
+}
+
+impl WebhookInput {
+    pub fn missing(&self) -> Option<&'static str> {
+        if self.flavor == JiraFlavor::Server && (self.api_key.is_empty() || self.jira_url.is_empty()) {
+            return Some("api_key (a personal access token) and jira_url are required for Jira Server")
+        }
+        if self.flavor == JiraFlavor::Cloud && (self.email.is_empty() || self.api_key.is_empty() || self.jira_url.is_empty()) {
+            return Some("email, api_key and jira_url are required unless the workspace is connected to Jira with OAuth")
+        }
+        None
+    }
+}
+
+/// The routes below predate sites and act on the workspace's default site.
+/// The site migrated from the workspace, or else the workspace's only site.
+async fn default_site(mongodb: &client::mongodb::Client, workspace: &Workspace) -> Result<Option<JiraSite>, HttpResponse> {
+    match mongodb.get_sites(workspace).await {
+        Ok(mut sites) => match sites.iter().position(|site| site.is_default()) {
+            Some(index) => Ok(Some(sites.swap_remove(index))),
+            None if sites.len() == 1 => Ok(sites.pop()),
+            None => Ok(None)
+        },
+        Err(e) => Err(HttpResponse::InternalServerError().json(e))
+    }
 }
 
 pub async fn get_project(client: Data<client::jira::Client>, mongodb: web::Data<client::mongodb::Client>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
-    match middleware::validate(req_user, &mongodb).await {
-        Ok(user) => {
-            if user.webhook_url.is_none() {
-                return HttpResponse::BadRequest().json("You must set up a webhook first")
+    match middleware::validate(req_user, &mongodb, Role::Viewer).await {
+        Ok(access) => {
+            match default_site(&mongodb, &access.workspace).await {
+                Ok(Some(site)) => {
+                    match client.get_projects(&mongodb.mongodb, site).await {
+                        Ok(projects) => return HttpResponse::Ok().json(projects),
+                        Err(e)=> return HttpResponse::BadRequest().json(format!("{}",e))
+                    };
+                },
+                Ok(None) => return HttpResponse::BadRequest().json("You must set up a webhook first"),
+                Err(error) => return error
             }
-            match client.get_projects(user).await {
-                Ok(projects) => return HttpResponse::Ok().json(projects),
-                Err(e)=> return HttpResponse::BadRequest().json(format!("{}",e))
-            };
         },
         Err(error) => return error
     }   
 }
 
 pub async fn post_webhook(client: Data<client::jira::Client>, mongodb: web::Data<client::mongodb::Client>, webhook: web::Json<WebhookInput>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
-    match middleware::validate(req_user, &mongodb).await {
-        Ok(user) => {
-            if user.webhook_url.is_some(){
+    match middleware::validate(req_user, &mongodb, Role::Admin).await {
+        Ok(access) => {
+            let site = match default_site(&mongodb, &access.workspace).await {
+                Ok(site) => site,
+                Err(error) => return error
+            };
+            if site.as_ref().is_some_and(|site| site.webhook_url.is_some()) {
                 return HttpResponse::BadRequest().json("You have already created a webhook for this account")
             }
+            let site = match site {
+                Some(site) => site,
+                None => {
+                    if let Some(missing) = webhook.missing() {
+                        return HttpResponse::BadRequest().json(missing)
+                    }
+                    JiraSite::new(ObjectId::new(), access.workspace.id, &webhook)
+                }
+            };
 
-            match client.create_webhook(&mongodb.mongodb, &webhook, user).await {
+            match client.create_webhook(&mongodb.mongodb, &webhook, site).await {
                 Ok(message) => return HttpResponse::Ok().json(message),
                 Err(e)=> return HttpResponse::BadRequest().json(format!("{}",e))
             };
@@ -42,17 +94,24 @@ pub async fn post_webhook(client: Data<client::jira::Client>, mongodb: web::Data
     }   
 }
 
-pub async fn delete_webhook(client: Data<client::jira::Client>, mongodb: web::Data<client::mongodb::Client>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
-    match middleware::validate(req_user, &mongodb).await {
-        Ok(user) => {
-            if user.webhook_url.is_none(){
-                return HttpResponse::BadRequest().json("You haven't created a webhook")
-            } 
-
-            match client.delete_webhook(&mongodb.mongodb, user).await {
-                Ok(message) => return HttpResponse::Ok().json(message),
-                Err(e)=> return HttpResponse::BadRequest().json(format!("{}",e))
-            };
+pub async fn delete_webhook(client: Data<client::jira::Client>, db: web::Data<client::storage::Client>, mongodb: web::Data<client::mongodb::Client>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
+    match middleware::validate(req_user, &mongodb, Role::Admin).await {
+        Ok(access) => {
+            match default_site(&mongodb, &access.workspace).await {
+                Ok(Some(site)) if site.webhook_url.is_some() => {
+                    match site::site_users(&db, &site).await {
+                        Ok(users) if !users.is_empty() => return HttpResponse::BadRequest().json(format!("Jira site is still used by connector(s): {}", users.join(", "))),
+                        Ok(_) => (),
+                        Err(error) => return error
+                    }
+                    match client.delete_webhook(&mongodb.mongodb, site).await {
+                        Ok(message) => return HttpResponse::Ok().json(message),
+                        Err(e)=> return HttpResponse::BadRequest().json(format!("{}",e))
+                    };
+                },
+                Ok(_) => return HttpResponse::BadRequest().json("You haven't created a webhook"),
+                Err(error) => return error
+            }
         },
         Err(error) => return error
     }   
@@ -60,40 +119,144 @@ pub async fn delete_webhook(client: Data<client::jira::Client>, mongodb: web::Da
 
 
 pub async fn check_webhook(client: Data<client::jira::Client>, mongodb: web::Data<client::mongodb::Client>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
-    match middleware::validate(req_user, &mongodb).await {
-        Ok(user) => {
-            if user.webhook_url.is_none(){
-                return HttpResponse::BadRequest().json("You haven't created a webhook")
-            } 
-            match client.check_webhook(&mongodb.mongodb, user).await {
-                Ok(message) => return HttpResponse::Ok().json(message),
-                Err(e)=> return HttpResponse::BadRequest().json(format!("{}",e))
-            };
+    match middleware::validate(req_user, &mongodb, Role::Viewer).await {
+        Ok(access) => {
+            match default_site(&mongodb, &access.workspace).await {
+                Ok(Some(site)) if site.webhook_url.is_some() => {
+                    match client.check_webhook(&mongodb.mongodb, site).await {
+                        Ok(message) => return HttpResponse::Ok().json(message),
+                        Err(e)=> return HttpResponse::BadRequest().json(format!("{}",e))
+                    };
+                },
+                Ok(_) => return HttpResponse::BadRequest().json("You haven't created a webhook"),
+                Err(error) => return error
+            }
         },
         Err(error) => return error
     }   
 }
 
 pub async fn put_webhook(client: Data<client::jira::Client>, mongodb: web::Data<client::mongodb::Client>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
-    match middleware::validate(req_user, &mongodb).await {
-        Ok(user) => {
-            if user.webhook_url.is_none(){
-                return HttpResponse::BadRequest().json("You haven't created a webhook")
-            } 
-
-            if user.webhook_functional.is_none() {
-                return HttpResponse::BadRequest().json("Please check your webhook status at least once before repairing")
+    match middleware::validate(req_user, &mongodb, Role::Admin).await {
+        Ok(access) => {
+            match default_site(&mongodb, &access.workspace).await {
+                Ok(Some(site)) => repair(&client, &mongodb, site).await,
+                Ok(None) => return HttpResponse::BadRequest().json("You haven't created a webhook"),
+                Err(error) => return error
             }
-            
-            if user.webhook_functional.is_some() && user.webhook_functional.unwrap() == true {
-                return HttpResponse::BadRequest().json("Your current webhook status is functional, please check webhook status again beforehand if you think your webhook is non functional")
-            } 
+        },
+        Err(error) => return error
+    }   
+}
 
-            match client.repair_webhook(&mongodb.mongodb, user).await {
-                Ok(message) => return HttpResponse::Ok().json(message),
-                Err(e)=> return HttpResponse::BadRequest().json(format!("{}",e))
+pub async fn repair(client: &client::jira::Client, mongodb: &client::mongodb::Client, site: JiraSite) -> HttpResponse {
+    if site.webhook_url.is_none() && site.jira_oauth.is_none() {
+        return HttpResponse::BadRequest().json("You haven't created a webhook")
+    }
+
+    if site.webhook_url.is_some() && site.webhook_functional.is_none() {
+        return HttpResponse::BadRequest().json("Please check your webhook status at least once before repairing")
+    }
+    
+    if site.webhook_functional.is_some() && site.webhook_functional.unwrap() == true {
+        return HttpResponse::BadRequest().json("Your current webhook status is functional, please check webhook status again beforehand if you think your webhook is non functional")
+    } 
+
+    match client.repair_webhook(&mongodb.mongodb, site).await {
+        Ok(message) => return HttpResponse::Ok().json(message),
+        Err(e)=> return HttpResponse::BadRequest().json(format!("{}",e))
+    };
+}
+
+/// Cookie scoped to the OAuth routes, lasting as long as the signed `state`.
+fn oauth_cookie(nonce: &str) -> Cookie<'static> {
+    Cookie::build(OAUTH_COOKIE, nonce.to_string())
+        .path("/jira/oauth")
+        .http_only(true)
+        .secure(config::get().public_url.starts_with("https://"))
+        .same_site(SameSite::Lax)
+        .max_age(Duration::minutes(10))
+        .finish()
+}
+
+/// The frontend must call this with credentials so the browser keeps the nonce cookie,
+/// the callback is only accepted from the browser that started the flow.
+pub async fn oauth_authorize(client: Data<client::jira::Client>, mongodb: web::Data<client::mongodb::Client>, secret: web::Data<String>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
+    match middleware::validate(req_user, &mongodb, Role::Admin).await {
+        Ok(access) => {
+            let nonce: String = rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(32)
+                .map(char::from)
+                .collect();
+            match client.authorize_url(access.workspace.id, access.user.id, &nonce, &secret) {
+                Ok(url) => return HttpResponse::Ok().cookie(oauth_cookie(&nonce)).json(serde_json::json!({ "url": url })),
+                Err(e) => return HttpResponse::BadRequest().json(format!("{}",e))
             };
         },
         Err(error) => return error
-    }   
+    }
+}
+
+pub async fn oauth_callback(req: HttpRequest, client: Data<client::jira::Client>, mongodb: web::Data<client::mongodb::Client>, query: web::Query<OAuthCallback>, secret: web::Data<String>) -> HttpResponse {
+    if let Some(error) = &query.error {
+        return HttpResponse::BadRequest().json(format!("Jira authorization was not granted: {}", error))
+    }
+    let state = match query.state.as_deref().and_then(|state| middleware::decode_oauth_state(state, &secret)) {
+        Some(state) => state,
+        None => return HttpResponse::BadRequest().json("Authorization request is invalid or has expired, please try again")
+    };
+    // Someone else's consent link must not connect this browser's Jira account to their workspace
+    if req.cookie(OAUTH_COOKIE).map(|cookie| cookie.value().to_string()) != Some(state.nonce.clone()) {
+        return HttpResponse::BadRequest().json("Authorization was started from another browser, please try again")
+    }
+    let code = match &query.code {
+        Some(code) => code,
+        None => return HttpResponse::BadRequest().json("Authorization code is missing")
+    };
+
+    // The state only proves who started the flow, check they may still manage the workspace
+    let user = match mongodb.get_one_user(state.user.to_hex()).await {
+        Ok(mut users) if !users.is_empty() => users.remove(0),
+        Ok(_) => return HttpResponse::NotFound().json("User Not Found"),
+        Err(e) => return HttpResponse::InternalServerError().json(e)
+    };
+    match middleware::authorize(&mongodb, user, state.workspace, Role::Admin).await {
+        Ok(access) => {
+            match client.complete_oauth(&mongodb.mongodb, &access.workspace, code).await {
+                Ok(message) => {
+                    let mut spent = oauth_cookie("");
+                    spent.make_removal();
+                    return HttpResponse::Ok().cookie(spent).json(message)
+                },
+                Err(e) => return HttpResponse::BadRequest().json(format!("{}",e))
+            };
+        },
+        Err(error) => return error
+    }
+}
+
+/// Disconnects every site the workspace connected through OAuth.
+pub async fn oauth_disconnect(client: Data<client::jira::Client>, mongodb: web::Data<client::mongodb::Client>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
+    match middleware::validate(req_user, &mongodb, Role::Admin).await {
+        Ok(access) => {
+            let sites: Vec<JiraSite> = match mongodb.get_sites(&access.workspace).await {
+                Ok(sites) => sites.into_iter().filter(|site| site.jira_oauth.is_some()).collect(),
+                Err(e) => return HttpResponse::InternalServerError().json(e)
+            };
+            if sites.is_empty() {
+                return HttpResponse::BadRequest().json("This workspace is not connected to Jira with OAuth")
+            }
+            let mut messages = vec![];
+            for site in sites {
+                let name = site.name.clone();
+                match client.delete_webhook(&mongodb.mongodb, site).await {
+                    Ok(message) => messages.push(format!("{}: {}", name, message)),
+                    Err(e) => return HttpResponse::BadRequest().json(format!("{}",e))
+                };
+            }
+            return HttpResponse::Ok().json(messages)
+        },
+        Err(error) => return error
+    }
 }
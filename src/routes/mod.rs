@@ -2,4 +2,10 @@ pub mod jira;
 pub mod connector;
 pub mod event;
 pub mod user;
-pub mod log;
\ No newline at end of file
+pub mod log;
+pub mod bot;
+pub mod apikey;
+pub mod workspace;
+pub mod site;
+
+pub mod history;
\ No newline at end of file
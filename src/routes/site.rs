@@ -0,0 +1,148 @@
+use std::str::FromStr;
+
+use actix_web::{web::{self, ReqData}, web::Data, HttpResponse};
+use mongodb::bson::oid::ObjectId;
+
+use crate::{client, actions, errortype::ConnectorError, middleware, middleware::{Access, Claims}, models::{workspace::Role, site::{JiraSite, SiteResponse}}, routes::jira::{self, WebhookInput}};
+
+async fn find(mongodb: &client::mongodb::Client, access: &Access, id: &str) -> Result<JiraSite, HttpResponse> {
+    let id = match ObjectId::from_str(id) { Ok(id) => id, Err(_) => return Err(HttpResponse::NotFound().json("Jira site not found")) };
+    match mongodb.get_site(&access.workspace, id).await {
+        Ok(Some(site)) => Ok(site),
+        Ok(None) => Err(HttpResponse::NotFound().json("Jira site not found")),
+        Err(e) => Err(HttpResponse::InternalServerError().json(e))
+    }
+}
+
+pub async fn get(mongodb: web::Data<client::mongodb::Client>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
+    match middleware::validate(req_user, &mongodb, Role::Viewer).await {
+        Ok(access) => {
+            match mongodb.get_sites(&access.workspace).await {
+                Ok(sites) => return HttpResponse::Ok().json(sites.into_iter().map(SiteResponse::from).collect::<Vec<_>>()),
+                Err(e) => return HttpResponse::InternalServerError().json(e)
+            }
+        },
+        Err(error) => return error
+    }
+}
+
+pub async fn post(client: Data<client::jira::Client>, mongodb: web::Data<client::mongodb::Client>, webhook: web::Json<WebhookInput>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
+    match middleware::validate(req_user, &mongodb, Role::Admin).await {
+        Ok(access) => {
+            if let Some(missing) = webhook.missing() {
+                return HttpResponse::BadRequest().json(missing)
+            }
+            let sites = match mongodb.get_sites(&access.workspace).await {
+                Ok(sites) => sites,
+                Err(e) => return HttpResponse::InternalServerError().json(e)
+            };
+            let jira_url = webhook.jira_url.trim_end_matches('/');
+            if sites.iter().any(|site| site.jira_url.as_deref().is_some_and(|url| url.trim_end_matches('/') == jira_url)) {
+                return HttpResponse::BadRequest().json("This Jira site is already connected to the workspace")
+            }
+
+            // Only a site migrated from the workspace has the workspace id, projects without a
+            // site mean that one and must not move to whichever site is added next
+            let id = ObjectId::new();
+            match client.create_webhook(&mongodb.mongodb, &webhook, JiraSite::new(id, access.workspace.id, &webhook)).await {
+                Ok(_) => return HttpResponse::Ok().json(serde_json::json!({ "id": id.to_hex(), "message": "Webhook successfully created" })),
+                Err(e)=> return HttpResponse::BadRequest().json(format!("{}",e))
+            };
+        },
+        Err(error) => return error
+    }
+}
+
+pub async fn get_one(mongodb: web::Data<client::mongodb::Client>, id: web::Path<String>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
+    match middleware::validate(req_user, &mongodb, Role::Viewer).await {
+        Ok(access) => {
+            match find(&mongodb, &access, &id).await {
+                Ok(site) => return HttpResponse::Ok().json(SiteResponse::from(site)),
+                Err(error) => return error
+            }
+        },
+        Err(error) => return error
+    }
+}
+
+/// Names of the connectors with a project on `site`, it can't be deleted while they exist.
+pub async fn site_users(db: &client::storage::Client, site: &JiraSite) -> Result<Vec<String>, HttpResponse> {
+    let (workspace, id) = (site.workspace.to_hex(), site.id.to_hex());
+    match actions::connector::get_connectors(db, workspace.clone()).await {
+        Ok(cons) => Ok(cons.into_iter()
+            .filter(|con| con.project.iter().any(|project| project.site.as_deref().unwrap_or(&workspace) == id))
+            .map(|con| con.name)
+            .collect()),
+        Err(ConnectorError::ConEmpty) => Ok(vec![]),
+        Err(e) => Err(HttpResponse::InternalServerError().json(format!("{}", e)))
+    }
+}
+
+pub async fn delete(client: Data<client::jira::Client>, db: web::Data<client::storage::Client>, mongodb: web::Data<client::mongodb::Client>, id: web::Path<String>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
+    match middleware::validate(req_user, &mongodb, Role::Admin).await {
+        Ok(access) => {
+            match find(&mongodb, &access, &id).await {
+                Ok(site) => {
+                    match site_users(&db, &site).await {
+                        Ok(users) if !users.is_empty() => return HttpResponse::BadRequest().json(format!("Jira site is still used by connector(s): {}", users.join(", "))),
+                        Ok(_) => (),
+                        Err(error) => return error
+                    }
+                    match client.delete_webhook(&mongodb.mongodb, site).await {
+                        Ok(message) => return HttpResponse::Ok().json(message),
+                        Err(e)=> return HttpResponse::BadRequest().json(format!("{}",e))
+                    };
+                },
+                Err(error) => return error
+            }
+        },
+        Err(error) => return error
+    }
+}
+
+pub async fn get_projects(client: Data<client::jira::Client>, mongodb: web::Data<client::mongodb::Client>, id: web::Path<String>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
+    match middleware::validate(req_user, &mongodb, Role::Viewer).await {
+        Ok(access) => {
+            match find(&mongodb, &access, &id).await {
+                Ok(site) => {
+                    match client.get_projects(&mongodb.mongodb, site).await {
+                        Ok(projects) => return HttpResponse::Ok().json(projects),
+                        Err(e)=> return HttpResponse::BadRequest().json(format!("{}",e))
+                    };
+                },
+                Err(error) => return error
+            }
+        },
+        Err(error) => return error
+    }
+}
+
+pub async fn check_webhook(client: Data<client::jira::Client>, mongodb: web::Data<client::mongodb::Client>, id: web::Path<String>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
+    match middleware::validate(req_user, &mongodb, Role::Viewer).await {
+        Ok(access) => {
+            match find(&mongodb, &access, &id).await {
+                Ok(site) if site.webhook_url.is_some() => {
+                    match client.check_webhook(&mongodb.mongodb, site).await {
+                        Ok(message) => return HttpResponse::Ok().json(message),
+                        Err(e)=> return HttpResponse::BadRequest().json(format!("{}",e))
+                    };
+                },
+                Ok(_) => return HttpResponse::BadRequest().json("You haven't created a webhook"),
+                Err(error) => return error
+            }
+        },
+        Err(error) => return error
+    }
+}
+
+pub async fn put_webhook(client: Data<client::jira::Client>, mongodb: web::Data<client::mongodb::Client>, id: web::Path<String>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
+    match middleware::validate(req_user, &mongodb, Role::Admin).await {
+        Ok(access) => {
+            match find(&mongodb, &access, &id).await {
+                Ok(site) => return jira::repair(&client, &mongodb, site).await,
+                Err(error) => return error
+            }
+        },
+        Err(error) => return error
+    }
+}
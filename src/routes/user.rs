@@ -1,23 +1,56 @@
-use actix_web::{web::{self, ReqData}, HttpResponse};
+use actix_web::{web::{self, ReqData}, HttpRequest, HttpResponse};
 use actix_web_httpauth::extractors::basic::BasicAuth;
 
-use crate::{client, models::user::UserInput, middleware, middleware::Claims};
+use crate::{client, models::{user::{UserInput, UserResponse, PasswordInput, TotpInput}, session::{RefreshInput, MfaInput, LoginResponse}}, middleware, middleware::Claims, password, guard::{self, LoginGuard}, errortype::LoginError};
 
-pub async fn register(db: web::Data<client::rusoto::Client>, client: web::Data<client::mongodb::Client>, payload: web::Json<UserInput>) -> HttpResponse {
-    match client.create_user(&db.s3, payload.clone()).await {
-        Ok(ok) => return HttpResponse::Ok().json(ok),
+pub async fn register(db: web::Data<client::storage::Client>, client: web::Data<client::mongodb::Client>, payload: web::Json<UserInput>) -> HttpResponse {
+    let mut errors = password::check_username(&payload.username);
+    errors.extend(password::check_password(&payload.password, &payload.username));
+    if !errors.is_empty() {
+        return HttpResponse::BadRequest().json(errors)
+    }
+    match client.create_user(&db, payload.clone()).await {
+        Ok(id) => return HttpResponse::Ok().json(serde_json::json!({ "insertedId": id })),
         Err(e) => return HttpResponse::BadRequest().json(format!("{}",e))
     };
 }
 
-pub async fn login(client: web::Data<client::mongodb::Client>, credentials: BasicAuth, secret: web::Data<String>) -> HttpResponse {
+pub async fn login(client: web::Data<client::mongodb::Client>, guard: web::Data<LoginGuard>, req: HttpRequest, credentials: BasicAuth, secret: web::Data<String>) -> HttpResponse {
     let username = credentials.user_id();
     let password = credentials.password();
+    let ip = guard::client_ip(&req);
+
     match password {
         Some(pass) => {
+            let attempt = match guard.begin(username, &ip, None) {
+                Ok(attempt) => attempt,
+                Err(lockout) => return locked_out(lockout.duration)
+            };
+            tokio::time::sleep(attempt.delay).await;
+
             match client.login(username.to_string(), pass.to_string(), &secret).await {
-                Ok(users) => return HttpResponse::Ok().json(users),
-                Err(e)=> return HttpResponse::BadRequest().json(e)
+                Ok(users) => {
+                    // A pending second factor keeps the failure count so codes can't be guessed between password retries
+                    match users {
+                        LoginResponse::Tokens(_) => guard.record_success(username, &ip),
+                        _ => guard.release(username, &ip)
+                    }
+                    return HttpResponse::Ok().json(users)
+                },
+                Err(LoginError::Other(e)) => {
+                    guard.release(username, &ip);
+                    return HttpResponse::InternalServerError().json(e)
+                },
+                Err(e) => {
+                    for lockout in &attempt.lockouts {
+                        client.write_audit("login_lockout", Some(username.to_string()), Some(ip.clone()),
+                            format!("Locked {} {} for {} seconds after repeated failed logins", lockout.scope, lockout.key, lockout.duration.as_secs())).await;
+                    }
+                    match attempt.lockouts.first() {
+                        Some(lockout) => return locked_out(lockout.duration),
+                        None => return HttpResponse::BadRequest().json(e.to_string())
+                    }
+                }
             };
         },
         None => return HttpResponse::Unauthorized().json("Username and password required"),
@@ -25,20 +58,99 @@ pub async fn login(client: web::Data<client::mongodb::Client>, credentials: Basi
     
 }
 
+pub async fn login_second_factor(client: web::Data<client::mongodb::Client>, guard: web::Data<LoginGuard>, req: HttpRequest, payload: web::Json<MfaInput>, secret: web::Data<String>) -> HttpResponse {
+    let ip = guard::client_ip(&req);
+    let claims = match middleware::decode_mfa(&payload.mfa_token, &secret) {
+        Some(claims) => claims,
+        None => return HttpResponse::Unauthorized().json("Two-factor challenge expired, please log in again")
+    };
+
+    let attempt = match guard.begin(&claims.username, &ip, Some(&payload.mfa_token)) {
+        Ok(attempt) => attempt,
+        Err(lockout) if lockout.scope == "challenge" => return challenge_spent(),
+        Err(lockout) => return locked_out(lockout.duration)
+    };
+    tokio::time::sleep(attempt.delay).await;
+
+    match client.login_second_factor(&claims, &payload.code, &secret).await {
+        Ok(tokens) => {
+            guard.record_success(&claims.username, &ip);
+            return HttpResponse::Ok().json(tokens)
+        },
+        Err(LoginError::Other(e)) => {
+            guard.release(&claims.username, &ip);
+            return HttpResponse::InternalServerError().json(e)
+        },
+        Err(e) => {
+            for lockout in &attempt.lockouts {
+                client.write_audit("login_lockout", Some(claims.username.clone()), Some(ip.clone()),
+                    format!("Locked {} {} for {} seconds after repeated failed two-factor codes", lockout.scope, lockout.key, lockout.duration.as_secs())).await;
+            }
+            match attempt.lockouts.iter().find(|lockout| lockout.scope != "challenge") {
+                Some(lockout) => return locked_out(lockout.duration),
+                None if !attempt.lockouts.is_empty() => return challenge_spent(),
+                None => return HttpResponse::BadRequest().json(e.to_string())
+            }
+        }
+    }
+}
+
+fn challenge_spent() -> HttpResponse {
+    HttpResponse::Unauthorized().json("Too many wrong two-factor codes, please log in again")
+}
+
+fn locked_out(remaining: std::time::Duration) -> HttpResponse {
+    let seconds = remaining.as_secs().max(1);
+    HttpResponse::TooManyRequests()
+        .insert_header(("Retry-After", seconds.to_string()))
+        .json(format!("Too many failed login attempts, try again in {} seconds", seconds))
+}
+
+pub async fn refresh(client: web::Data<client::mongodb::Client>, payload: web::Json<RefreshInput>, secret: web::Data<String>) -> HttpResponse {
+    match client.refresh_session(&payload.refresh_token, &secret).await {
+        Ok(tokens) => return HttpResponse::Ok().json(tokens),
+        Err(e) => return HttpResponse::Unauthorized().json(e)
+    };
+}
+
+pub async fn logout(client: web::Data<client::mongodb::Client>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
+    match req_user {
+        Some(claims) => {
+            match client.revoke_session(claims.sid).await {
+                Ok(ok) => return HttpResponse::Ok().json(ok),
+                Err(e) => return HttpResponse::InternalServerError().json(e)
+            };
+        },
+        None => return HttpResponse::Unauthorized().json("Must be logged in")
+    }
+}
+
+pub async fn logout_all(client: web::Data<client::mongodb::Client>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
+    match middleware::validate_user(req_user, &client).await {
+        Ok(user) => {
+            match client.revoke_all_sessions(user.id).await {
+                Ok(ok) => return HttpResponse::Ok().json(ok),
+                Err(e) => return HttpResponse::InternalServerError().json(e)
+            };
+        },
+        Err(error) => return error
+    }
+}
+
 pub async fn get(client: web::Data<client::mongodb::Client>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
-    match middleware::validate(req_user, &client).await {
+    match middleware::validate_user(req_user, &client).await {
         Ok(user) => {
-            return HttpResponse::Ok().json(user)
+            return HttpResponse::Ok().json(UserResponse::from(user))
         },
         Err(error) => return error
     }  
 }
 
-pub async fn delete(db: web::Data<client::rusoto::Client>, jira: web::Data<client::jira::Client> ,client: web::Data<client::mongodb::Client>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
-    match middleware::validate(req_user, &client).await {
+pub async fn delete(db: web::Data<client::storage::Client>, jira: web::Data<client::jira::Client> ,client: web::Data<client::mongodb::Client>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
+    match middleware::validate_user(req_user, &client).await {
         Ok(user) => {
-            match client.delete_user(&db.s3, &jira.reqwest, user).await {
-                Ok(ok) => return HttpResponse::Ok().json(ok),
+            match client.delete_user(&db, &jira, user).await {
+                Ok(count) => return HttpResponse::Ok().json(serde_json::json!({ "deletedCount": count })),
                 Err(e) => return HttpResponse::BadRequest().json(format!("{}",e))
             };
         },
@@ -46,3 +158,59 @@ pub async fn delete(db: web::Data<client::rusoto::Client>, jira: web::Data<clien
     }   
 }
 
+pub async fn change_password(client: web::Data<client::mongodb::Client>, payload: web::Json<PasswordInput>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
+    let sid = match &req_user {
+        Some(claims) => claims.sid,
+        None => return HttpResponse::Unauthorized().json("Must be logged in")
+    };
+    match middleware::validate_user(req_user, &client).await {
+        Ok(user) => {
+            let errors = password::check_password(&payload.new_password, &user.username);
+            if !errors.is_empty() {
+                return HttpResponse::BadRequest().json(errors)
+            }
+            let payload = payload.into_inner();
+            match client.change_password(&user, sid, payload.current_password, payload.new_password).await {
+                Ok(ok) => return HttpResponse::Ok().json(ok),
+                Err(e) => return HttpResponse::BadRequest().json(e)
+            };
+        },
+        Err(error) => return error
+    }
+}
+
+pub async fn enroll_totp(client: web::Data<client::mongodb::Client>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
+    match middleware::validate_user(req_user, &client).await {
+        Ok(user) => {
+            match client.enroll_totp(&user).await {
+                Ok(enrollment) => return HttpResponse::Ok().json(enrollment),
+                Err(e) => return HttpResponse::BadRequest().json(e)
+            };
+        },
+        Err(error) => return error
+    }
+}
+
+pub async fn confirm_totp(client: web::Data<client::mongodb::Client>, payload: web::Json<TotpInput>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
+    match middleware::validate_user(req_user, &client).await {
+        Ok(user) => {
+            match client.confirm_totp(&user, &payload.code).await {
+                Ok(codes) => return HttpResponse::Ok().json(codes),
+                Err(e) => return HttpResponse::BadRequest().json(e)
+            };
+        },
+        Err(error) => return error
+    }
+}
+
+pub async fn disable_totp(client: web::Data<client::mongodb::Client>, payload: web::Json<TotpInput>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
+    match middleware::validate_user(req_user, &client).await {
+        Ok(user) => {
+            match client.disable_totp(&user, &payload.code).await {
+                Ok(ok) => return HttpResponse::Ok().json(ok),
+                Err(e) => return HttpResponse::BadRequest().json(e)
+            };
+        },
+        Err(error) => return error
+    }
+}
@@ -0,0 +1,192 @@
+use std::str::FromStr;
+
+use actix_web::{web::{self, ReqData}, HttpResponse};
+use mongodb::bson::oid::ObjectId;
+
+use crate::{client, middleware, middleware::Claims, models::workspace::{Role, RoleInput, WorkspaceInput, WorkspaceResponse, InvitationInput}};
+
+pub async fn get(mongodb: web::Data<client::mongodb::Client>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
+    match middleware::validate_user(req_user, &mongodb).await {
+        Ok(user) => {
+            if let Err(e) = mongodb.personal_workspace(&user).await {
+                return HttpResponse::InternalServerError().json(e)
+            }
+            match mongodb.get_workspaces(user.id).await {
+                Ok(list) => return HttpResponse::Ok().json(list.into_iter().map(|ws| WorkspaceResponse::new(ws, &user.id)).collect::<Vec<_>>()),
+                Err(e) => return HttpResponse::InternalServerError().json(e)
+            }
+        },
+        Err(error) => return error
+    }
+}
+
+pub async fn post(db: web::Data<client::storage::Client>, mongodb: web::Data<client::mongodb::Client>, payload: web::Json<WorkspaceInput>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
+    match middleware::validate_user(req_user, &mongodb).await {
+        Ok(user) => {
+            if payload.name.is_empty() {
+                return HttpResponse::BadRequest().json("Workspace name must not be empty!")
+            }
+            match mongodb.create_workspace(&db, payload.into_inner().name, &user).await {
+                Ok(workspace) => return HttpResponse::Ok().json(WorkspaceResponse::new(workspace, &user.id)),
+                Err(e) => return HttpResponse::InternalServerError().json(e)
+            }
+        },
+        Err(error) => return error
+    }
+}
+
+pub async fn get_one(mongodb: web::Data<client::mongodb::Client>, id: web::Path<String>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
+    let id = match ObjectId::from_str(&id) { Ok(id) => id, Err(_) => return HttpResponse::NotFound().json("Workspace not found") };
+    match middleware::validate_user(req_user, &mongodb).await {
+        Ok(user) => {
+            match middleware::authorize(&mongodb, user, id, Role::Viewer).await {
+                Ok(access) => return HttpResponse::Ok().json(WorkspaceResponse::new(access.workspace, &access.user.id)),
+                Err(error) => return error
+            }
+        },
+        Err(error) => return error
+    }
+}
+
+pub async fn delete(db: web::Data<client::storage::Client>, jira: web::Data<client::jira::Client>, mongodb: web::Data<client::mongodb::Client>, id: web::Path<String>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
+    let id = match ObjectId::from_str(&id) { Ok(id) => id, Err(_) => return HttpResponse::NotFound().json("Workspace not found") };
+    match middleware::validate_user(req_user, &mongodb).await {
+        Ok(user) => {
+            if id == user.id {
+                return HttpResponse::BadRequest().json("Your personal workspace is removed together with your account")
+            }
+            match middleware::authorize(&mongodb, user, id, Role::Owner).await {
+                Ok(access) => {
+                    match mongodb.delete_workspace(&db, &jira, access.workspace).await {
+                        Ok(ok) => return HttpResponse::Ok().json(ok),
+                        Err(e) => return HttpResponse::InternalServerError().json(e)
+                    }
+                },
+                Err(error) => return error
+            }
+        },
+        Err(error) => return error
+    }
+}
+
+pub async fn update_member(mongodb: web::Data<client::mongodb::Client>, path: web::Path<(String, String)>, payload: web::Json<RoleInput>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
+    let (id, member) = path.into_inner();
+    let id = match ObjectId::from_str(&id) { Ok(id) => id, Err(_) => return HttpResponse::NotFound().json("Workspace not found") };
+    let member = match ObjectId::from_str(&member) { Ok(member) => member, Err(_) => return HttpResponse::NotFound().json("Member not found") };
+    match middleware::validate_user(req_user, &mongodb).await {
+        Ok(user) => {
+            match middleware::authorize(&mongodb, user, id, Role::Admin).await {
+                Ok(access) => {
+                    let current = match access.workspace.role_of(&member) {
+                        Some(current) => current,
+                        None => return HttpResponse::NotFound().json("Member not found")
+                    };
+                    if current == Role::Owner || payload.role == Role::Owner {
+                        return HttpResponse::BadRequest().json("The workspace owner cannot be changed")
+                    }
+                    if (current == Role::Admin || payload.role == Role::Admin) && access.role != Role::Owner {
+                        return HttpResponse::Forbidden().json("Only the owner can grant or revoke the admin role")
+                    }
+                    match mongodb.set_member_role(id, member, payload.role).await {
+                        Ok(_) => return HttpResponse::Ok().json("Member role updated"),
+                        Err(e) => return HttpResponse::InternalServerError().json(e)
+                    }
+                },
+                Err(error) => return error
+            }
+        },
+        Err(error) => return error
+    }
+}
+
+pub async fn delete_member(mongodb: web::Data<client::mongodb::Client>, path: web::Path<(String, String)>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
+    let (id, member) = path.into_inner();
+    let id = match ObjectId::from_str(&id) { Ok(id) => id, Err(_) => return HttpResponse::NotFound().json("Workspace not found") };
+    let member = match ObjectId::from_str(&member) { Ok(member) => member, Err(_) => return HttpResponse::NotFound().json("Member not found") };
+    match middleware::validate_user(req_user, &mongodb).await {
+        Ok(user) => {
+            // Any member may leave on their own, removing someone else needs admin
+            let required = if member == user.id { Role::Viewer } else { Role::Admin };
+            match middleware::authorize(&mongodb, user, id, required).await {
+                Ok(access) => {
+                    match access.workspace.role_of(&member) {
+                        Some(Role::Owner) => return HttpResponse::BadRequest().json("The workspace owner cannot be removed"),
+                        Some(Role::Admin) if member != access.user.id && access.role != Role::Owner => return HttpResponse::Forbidden().json("Only the owner can remove an admin"),
+                        Some(_) => (),
+                        None => return HttpResponse::NotFound().json("Member not found")
+                    }
+                    match mongodb.remove_member(id, member).await {
+                        Ok(_) => return HttpResponse::Ok().json("Member removed"),
+                        Err(e) => return HttpResponse::InternalServerError().json(e)
+                    }
+                },
+                Err(error) => return error
+            }
+        },
+        Err(error) => return error
+    }
+}
+
+pub async fn invite(mongodb: web::Data<client::mongodb::Client>, id: web::Path<String>, payload: web::Json<InvitationInput>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
+    let id = match ObjectId::from_str(&id) { Ok(id) => id, Err(_) => return HttpResponse::NotFound().json("Workspace not found") };
+    match middleware::validate_user(req_user, &mongodb).await {
+        Ok(user) => {
+            match middleware::authorize(&mongodb, user, id, Role::Admin).await {
+                Ok(access) => {
+                    if payload.role == Role::Admin && access.role != Role::Owner {
+                        return HttpResponse::Forbidden().json("Only the owner can invite admins")
+                    }
+                    match mongodb.invite_member(&access.workspace, payload.into_inner(), &access.user).await {
+                        Ok(ok) => return HttpResponse::Ok().json(ok),
+                        Err(e) => return HttpResponse::BadRequest().json(e)
+                    }
+                },
+                Err(error) => return error
+            }
+        },
+        Err(error) => return error
+    }
+}
+
+pub async fn get_invitations(mongodb: web::Data<client::mongodb::Client>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
+    match middleware::validate_user(req_user, &mongodb).await {
+        Ok(user) => {
+            match mongodb.get_invitations(&user.username).await {
+                Ok(list) => return HttpResponse::Ok().json(list.into_iter().map(|inv| serde_json::json!({
+                    "id": inv.id.to_hex(),
+                    "workspace": inv.workspace.to_hex(),
+                    "workspace_name": inv.workspace_name,
+                    "role": inv.role,
+                    "invited_by": inv.invited_by,
+                    "created_at": inv.created_at
+                })).collect::<Vec<_>>()),
+                Err(e) => return HttpResponse::InternalServerError().json(e)
+            }
+        },
+        Err(error) => return error
+    }
+}
+
+pub async fn accept_invitation(mongodb: web::Data<client::mongodb::Client>, id: web::Path<String>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
+    match middleware::validate_user(req_user, &mongodb).await {
+        Ok(user) => {
+            match mongodb.answer_invitation(&id, &user, true).await {
+                Ok(ok) => return HttpResponse::Ok().json(ok),
+                Err(e) => return HttpResponse::NotFound().json(e)
+            }
+        },
+        Err(error) => return error
+    }
+}
+
+pub async fn decline_invitation(mongodb: web::Data<client::mongodb::Client>, id: web::Path<String>, req_user: Option<ReqData<Claims>>) -> HttpResponse {
+    match middleware::validate_user(req_user, &mongodb).await {
+        Ok(user) => {
+            match mongodb.answer_invitation(&id, &user, false).await {
+                Ok(ok) => return HttpResponse::Ok().json(ok),
+                Err(e) => return HttpResponse::NotFound().json(e)
+            }
+        },
+        Err(error) => return error
+    }
+}
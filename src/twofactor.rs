@@ -0,0 +1,79 @@
+use rand::{Rng, distributions::Alphanumeric};
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use crate::crypto;
+
+const RECOVERY_CODES: usize = 10;
+
+fn issuer() -> String {
+    std::env::var("TOTP_ISSUER").unwrap_or_else(|_| "Telconnect".to_string())
+}
+
+fn build(secret: &str, username: &str) -> Result<TOTP, String> {
+    let bytes = Secret::Encoded(secret.to_string()).to_bytes().map_err(|e| format!("{:?}", e))?;
+    TOTP::new(Algorithm::SHA1, 6, 1, 30, bytes, Some(issuer()), username.to_string()).map_err(|e| e.to_string())
+}
+
+/// Returns a new base32 secret and its `otpauth://` URI for authenticator apps.
+pub fn generate(username: &str) -> Result<(String, String), String> {
+    let secret = Secret::generate_secret().to_encoded().to_string();
+    let url = build(&secret, username)?.get_url();
+    Ok((secret, url))
+}
+
+pub fn check(secret: &str, username: &str, code: &str) -> bool {
+    matched_step(secret, username, code).is_some()
+}
+
+/// Time step the code was generated for, allowing one step of clock drift either way.
+/// Callers record it so the same code can't be used twice.
+pub fn matched_step(secret: &str, username: &str, code: &str) -> Option<u64> {
+    let mut totp = build(secret, username).ok()?;
+    let skew = totp.skew as u64;
+    totp.skew = 0;
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).ok()?.as_secs();
+    let current = now / totp.step;
+    (current.saturating_sub(skew)..=current + skew)
+        .rev()
+        .find(|step| totp.check(code.trim(), step * totp.step))
+}
+
+/// Returns the plaintext recovery codes to show once, and the hashes to store.
+pub fn recovery_codes() -> (Vec<String>, Vec<String>) {
+    let codes: Vec<String> = (0..RECOVERY_CODES)
+        .map(|_| {
+            let raw: String = rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(10)
+                .map(|c| char::from(c).to_ascii_lowercase())
+                .collect();
+            format!("{}-{}", &raw[..5], &raw[5..])
+        })
+        .collect();
+    let hashes = codes.iter().map(|code| crypto::digest(code)).collect();
+    (codes, hashes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn code_at(secret: &str, offset: i64) -> (String, u64) {
+        let totp = build(secret, "alice").unwrap();
+        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+        let time = (now as i64 + offset * totp.step as i64) as u64;
+        (totp.generate(time), time / totp.step)
+    }
+
+    #[test]
+    fn codes_report_their_step() {
+        let (secret, _) = generate("alice").unwrap();
+        for offset in [-1, 0, 1] {
+            let (code, step) = code_at(&secret, offset);
+            assert_eq!(matched_step(&secret, "alice", &code), Some(step));
+        }
+        let (stale, _) = code_at(&secret, -3);
+        assert_eq!(matched_step(&secret, "alice", &stale), None);
+        assert!(!check(&secret, "alice", "not a code"));
+    }
+}